@@ -0,0 +1,35 @@
+//! Wave function collapse: fills a grid with tiles so that every pair of
+//! neighbors connects, from a [`Tileset`] describing how tiles fit together.
+//!
+//! Load a tileset, build a [`Model`] for it with a [`ModelBuilder`], and run
+//! it:
+//!
+//! ```no_run
+//! use wfc::{Model, Tileset};
+//!
+//! let tileset = Tileset::load("tileset.json")?;
+//! let mut model = Model::builder(&tileset)
+//!     .width(40)
+//!     .height(30)
+//!     .seed(42)
+//!     .build()?;
+//! model.try_run()?;
+//! let indices = model.grid().to_indices();
+//! # Ok::<(), wfc::WfcError>(())
+//! ```
+
+mod wfc;
+
+#[cfg(feature = "async")]
+pub use wfc::AsyncSteps;
+pub use wfc::{
+    alpha_mask, luma_map, AdjacencyRule, AnnealOptions, AnnealingModel, AtlasResolver, BiasField,
+    Cell, ChunkedModel, DistanceRule, Edge, EmbeddedResolver, FileResolver, GraphModel,
+    GraphOptions, GraphTile, GraphTileset, Grid, GridResult, Heuristic, HexModel, HexOptions,
+    HexTile, HexTileset, HierarchicalModel, ImageResolver, LayerConstraint, LayeredModel, Model,
+    ModelBuilder, NoiseBias, Observer, Options, OverlapOptions, OverlappingModel, PlacedTile,
+    Progress, Propagator, RecoveryMode, Rect, Reflection, RenderMode, RetryPolicy, RngState,
+    RunStats, SavedState, SockName, Socket, SoftMatch, StartPoint, Step, Steps, SymmetricModel,
+    Symmetry, TileConfig, Tileset, TilesetProblem, Topology, TriModel, TriOptions, TriTile,
+    TriTileset, WfcError, WfcEvent,
+};
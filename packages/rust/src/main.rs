@@ -1,23 +1,25 @@
-mod wfc;
+use wfc::{Model, Tileset, WfcError};
 
-use std::{fs, path::Path};
-use wfc::{Model, Options};
+fn main() -> Result<(), WfcError> {
+    let show_progress = std::env::args().skip(1).any(|arg| arg == "--progress");
+    let tileset = Tileset::load("../../tilesets/outdoor/tileset.json")?;
+    for problem in tileset.validate() {
+        eprintln!("warning: {}", problem);
+    }
+    let mut model = Model::builder(&tileset).build()?;
 
-fn main() {
-    let data = fs::read_to_string(Path::new("../../tilesets/outdoor/tileset.json"))
-        .expect("Could not read tileset!");
-
-    let tileset = serde_json::from_str(&data).expect("Could not deserialize tileset");
-
-    let mut model = Model::new(
-        &tileset,
-        Options {
-            width: None,
-            height: None,
-            framerate: None,
-            seed: None,
-        },
-    );
-
-    model.run();
+    if show_progress {
+        while !model.run_with_limit(1) {
+            let progress = model.progress();
+            eprint!(
+                "\r{}/{} cells, {} steps",
+                progress.collapsed, progress.total, progress.steps
+            );
+        }
+        eprintln!();
+        model.grid().check_contradiction()?;
+    } else {
+        model.try_run()?;
+    }
+    Ok(())
 }
@@ -1,280 +1,4421 @@
-use rand::seq::SliceRandom;
-use serde::Deserialize;
-use std::collections::HashSet;
+mod anneal;
+mod builder;
+mod chunked;
+mod export;
+#[cfg(feature = "async")]
+mod future;
+mod graph;
+mod hex;
+mod hierarchy;
+mod layers;
+mod noise;
+mod overlap;
+mod render;
+mod resolve;
+mod symmetric;
+mod tri;
+mod validate;
+mod wave;
 
-enum Edge {
+pub use anneal::{AnnealOptions, AnnealingModel};
+pub use builder::ModelBuilder;
+pub use chunked::ChunkedModel;
+#[cfg(feature = "async")]
+pub use future::AsyncSteps;
+pub use graph::{GraphModel, GraphOptions, GraphTile, GraphTileset, Topology};
+pub use hex::{HexModel, HexOptions, HexTile, HexTileset};
+pub use hierarchy::HierarchicalModel;
+pub use layers::{LayerConstraint, LayeredModel};
+pub use noise::NoiseBias;
+pub use overlap::{OverlapOptions, OverlappingModel};
+pub use render::RenderMode;
+pub use resolve::{AtlasResolver, EmbeddedResolver, FileResolver, ImageResolver};
+pub use symmetric::SymmetricModel;
+pub use tri::{TriModel, TriOptions, TriTile, TriTileset};
+pub use validate::TilesetProblem;
+
+use image::RgbaImage;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap, VecDeque},
+    fmt,
+    ops::{Index, IndexMut, Range},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+};
+
+#[derive(Debug)]
+pub enum WfcError {
+    InvalidTileset(String),
+    /// The tileset has no tiles at all, so no cell could ever be filled.
+    EmptyTileset,
+    UnknownTile(usize),
+    OutOfBounds {
+        x: usize,
+        y: usize,
+    },
+    Contradiction {
+        x: usize,
+        y: usize,
+    },
+    InvalidGrid(String),
+    /// The run was stopped by [`Options::cancel`].
+    Cancelled,
+    Image(image::ImageError),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for WfcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WfcError::InvalidTileset(reason) => write!(f, "invalid tileset: {}", reason),
+            WfcError::EmptyTileset => f.write_str("the tileset has no tiles"),
+            WfcError::UnknownTile(index) => write!(f, "no tile with index {}", index),
+            WfcError::OutOfBounds { x, y } => write!(f, "cell ({}, {}) is outside the grid", x, y),
+            WfcError::Contradiction { x, y } => {
+                write!(f, "cell ({}, {}) has no remaining possibilities", x, y)
+            }
+            WfcError::InvalidGrid(reason) => write!(f, "invalid grid: {}", reason),
+            WfcError::Cancelled => f.write_str("the run was cancelled"),
+            WfcError::Image(err) => write!(f, "could not load tile image: {}", err),
+            WfcError::Io(err) => write!(f, "could not read tileset: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for WfcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WfcError::Image(err) => Some(err),
+            WfcError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for WfcError {
+    fn from(err: image::ImageError) -> Self {
+        WfcError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for WfcError {
+    fn from(err: std::io::Error) -> Self {
+        WfcError::Io(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
     Top,
     Right,
     Bottom,
     Left,
 }
 
-#[derive(Eq, Hash, Debug, Deserialize)]
-struct Sock(u32);
+const EDGES: [Edge; 4] = [Edge::Top, Edge::Right, Edge::Bottom, Edge::Left];
 
-impl PartialEq for Sock {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+impl Edge {
+    fn opposite(self) -> Edge {
+        match self {
+            Edge::Top => Edge::Bottom,
+            Edge::Right => Edge::Left,
+            Edge::Bottom => Edge::Top,
+            Edge::Left => Edge::Right,
+        }
     }
 }
 
-#[derive(Eq, Hash, Debug, Deserialize)]
-pub struct Socket(Sock, Sock, Sock);
+/// One point along an edge, written in a tileset as a number below
+/// `u32::MAX` or as a label such as `"grass"`. Labels are interned into ids
+/// above every number, so they compare as cheaply.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct Sock(u64);
+
+impl Sock {
+    /// Reserved for wildcard sockets, so never read from a tileset.
+    const ANY: Sock = Sock(u64::MAX);
 
-impl PartialEq for Socket {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0 && self.1 == other.1 && self.2 == other.2
+    /// Labels are only understood while a tileset is being read, and are
+    /// interned into that tileset's [`Labels`].
+    fn parse(value: &serde_json::Value) -> Option<Sock> {
+        match value {
+            serde_json::Value::String(label) => READING.with(|labels| {
+                labels
+                    .borrow_mut()
+                    .as_mut()
+                    .map(|labels| labels.intern(label))
+            }),
+            _ => value
+                .as_u64()
+                .filter(|&sock| sock < u32::MAX as u64)
+                .map(Sock),
+        }
     }
 }
 
-#[derive(Eq, Hash, Debug, Deserialize)]
-pub struct TileConfig {
-    image: String,
-    sockets: (Socket, Socket, Socket, Socket),
+/// The sock labels of one tileset, each mapped to its interned id.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+struct Labels(HashMap<String, u64>);
+
+thread_local! {
+    /// The labels of the tileset being read on this thread, if any.
+    static READING: RefCell<Option<Labels>> = const { RefCell::new(None) };
 }
 
-impl PartialEq for TileConfig {
-    fn eq(&self, other: &Self) -> bool {
-        self.image == other.image
+impl Labels {
+    fn intern(&mut self, label: &str) -> Sock {
+        let next = (1 << 32) + self.0.len() as u64;
+        Sock(*self.0.entry(label.to_string()).or_insert(next))
+    }
+
+    /// Runs `read`, interning any labels it parses into a fresh set, which
+    /// is returned alongside its result.
+    fn scope<R>(read: impl FnOnce() -> R) -> (R, Labels) {
+        let outer = READING.with(|labels| labels.replace(Some(Labels::default())));
+        let result = read();
+        let labels = READING.with(|labels| labels.replace(outer));
+        (result, labels.unwrap_or_default())
     }
 }
 
-#[derive(PartialEq, Hash, Debug, Deserialize)]
-pub struct Tileset {
-    pub size: u32,
-    pub tiles: Vec<TileConfig>,
+/// Deserializes a tileset's tiles with labels of their own, for tilesets
+/// that have no use for the labels once they're read.
+fn labelled<'de, D: serde::Deserializer<'de>, R: Deserialize<'de>>(
+    deserializer: D,
+) -> Result<R, D::Error> {
+    Labels::scope(|| R::deserialize(deserializer)).0
 }
 
-pub struct Options {
-    pub width: Option<usize>,
-    pub height: Option<usize>,
-    pub framerate: Option<u32>,
-    pub seed: Option<u32>,
+/// A sock to border the grid with, by number or by the label the tileset
+/// gives it. See [`Options::borders`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SockName {
+    Number(u32),
+    Label(String),
 }
 
-#[derive(Debug)]
-struct Settings {
-    width: usize,
-    height: usize,
-    framerate: Option<u32>,
-    seed: Option<u32>,
+impl From<u32> for SockName {
+    fn from(sock: u32) -> Self {
+        SockName::Number(sock)
+    }
 }
 
-#[derive(Clone, Debug)]
-struct Cell<'t> {
-    possibilities: HashSet<&'t TileConfig>,
+impl From<&str> for SockName {
+    fn from(label: &str) -> Self {
+        SockName::Label(label.to_string())
+    }
 }
 
-impl<'t> Cell<'t> {
-    fn new(tileset: &'t Tileset) -> Self {
-        Cell {
-            possibilities: HashSet::from_iter(tileset.tiles.iter()),
-        }
+impl From<String> for SockName {
+    fn from(label: String) -> Self {
+        SockName::Label(label)
     }
+}
 
-    fn constrain(&mut self, other_cell: &Cell, edge: Edge) -> bool {
-        if self.is_collapsed() {
-            return false;
+impl fmt::Display for SockName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SockName::Number(sock) => write!(f, "{}", sock),
+            SockName::Label(label) => write!(f, "{:?}", label),
         }
+    }
+}
 
-        let unreachables: Vec<&'t TileConfig> = self
-            .possibilities
-            .iter()
-            .filter_map(|&possibility| {
-                if other_cell
-                    .possibilities
-                    .iter()
-                    .any(|config| Cell::connects_to(possibility, config, &edge))
-                {
-                    None
-                } else {
-                    Some(possibility)
-                }
-            })
-            .collect();
+impl<'de> Deserialize<'de> for Sock {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Sock::parse(&value).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "sock `{}` is neither a label nor an integer from 0 to {}",
+                value,
+                u32::MAX - 1
+            ))
+        })
+    }
+}
+
+/// The socks along one edge of a tile, listed clockwise. Top and bottom edges
+/// may use a different number of socks than left and right edges, but every
+/// tile in a tileset must agree on the arity of each pair of opposing edges.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Socket(Vec<Sock>);
+
+impl Socket {
+    /// A socket that matches any opposing socket, written `"*"` in place of
+    /// an edge's socks.
+    fn wildcard() -> Socket {
+        Socket(vec![Sock::ANY])
+    }
+
+    fn is_wildcard(&self) -> bool {
+        self.0 == [Sock::ANY]
+    }
+
+    fn arity(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// The symmetry classes of the original WFC "simple tiled" model, named for
+/// the letter each one resembles. The class decides how many distinct
+/// rotations and reflections of a tile exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Symmetry {
+    /// Unchanged by any rotation, like a plain grass square.
+    X,
+    /// Unchanged by a half turn, like a straight road.
+    I,
+    /// Unchanged by a half turn, like a diagonal.
+    #[serde(rename = "\\")]
+    Backslash,
+    /// A corner, with four distinct rotations.
+    L,
+    /// A junction, with four distinct rotations.
+    T,
+    /// Unchanged by a half turn, but not by a reflection, so it has two
+    /// rotations and a mirror image of each.
+    S,
+    /// Asymmetric, with all four rotations and a mirror image of each.
+    F,
+}
 
-        for &poss in &unreachables {
-            self.possibilities.remove(poss);
+impl Symmetry {
+    fn rotations(self) -> u8 {
+        match self {
+            Symmetry::X => 1,
+            Symmetry::I | Symmetry::Backslash | Symmetry::S => 2,
+            Symmetry::L | Symmetry::T | Symmetry::F => 4,
         }
+    }
 
-        unreachables.len() > 0
+    fn reflect(self) -> Option<Reflection> {
+        match self {
+            Symmetry::S | Symmetry::F => Some(Reflection::Horizontal),
+            _ => None,
+        }
     }
+}
+
+/// Which mirror images of a tile to generate alongside it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reflection {
+    /// Flipped left to right.
+    Horizontal,
+    /// Flipped top to bottom.
+    Vertical,
+    Both,
+}
+
+/// A tile is identified by its index in [`Tileset::tiles`], so several tiles
+/// may share one image with different sockets or weights. `T` is the type of
+/// the tile's `data`, which is raw JSON unless the tileset is loaded as
+/// another type.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(
+    try_from = "RawTileConfig<T>",
+    bound(deserialize = "T: DeserializeOwned + Default")
+)]
+pub struct TileConfig<T = serde_json::Value> {
+    image: String,
+    sockets: (Socket, Socket, Socket, Socket),
+    /// The sockets on the tile's upper and lower faces, used only when grids
+    /// are stacked with [`LayeredModel::stacked`]. A tile's upper face fits
+    /// under another's lower face when their socks are the same, as seen from
+    /// above. Both are empty unless the tile lists six sockets.
+    faces: (Socket, Socket),
+    /// Whether `sockets` were derived from the terrain at the tile's corners.
+    /// See [`TileConfig::corners`].
+    cornered: bool,
+    /// One sock at each corner, clockwise from the top left, which must equal
+    /// the sock at the same point of each diagonal neighbor that has them. A
+    /// rotatable tile's are checked as drawn, whatever its turn.
+    corner_sockets: Option<[u32; 4]>,
+    /// Transparent tiles stand for empty space: they have no image, connect to
+    /// anything, and are skipped when rendering.
+    transparent: bool,
+    /// How likely this tile is relative to the others. Defaults to 1.
+    weight: Option<f64>,
+    /// The fewest and most cells that may end up as this tile. Each variant
+    /// generated from the tile is counted on its own.
+    min_count: Option<usize>,
+    max_count: Option<usize>,
+    /// Names that global constraints, such as [`Options::connected`], use to
+    /// refer to groups of tiles.
+    tags: Vec<String>,
+    /// Tags, one of which the tile beneath this one must carry when grids are
+    /// layered with [`LayeredModel::sequenced`]. Empty means anything.
+    on: Vec<String>,
+    /// Generates one variant per distinct rotation and reflection of this
+    /// tile. Every variant carries the tile's full weight.
+    symmetry: Option<Symmetry>,
+    /// How many quarter-turn variants to generate, from `symmetry` or given
+    /// directly as `rotations`. 1 means only the tile as drawn.
+    rotations: u8,
+    /// Lets the tile be turned on the fly to fit its neighbors, instead of
+    /// generating a variant per rotation as `symmetry` does. This keeps the
+    /// tile list small, but matching is slower and a rotation is only fixed
+    /// once its cell collapses.
+    rotatable: bool,
+    /// Generates mirrored variants of this tile, and of each of its rotations.
+    reflect: Option<Reflection>,
+    /// Whether the tile's image is flipped left to right, before `rotation`
+    /// is applied.
+    mirrored: bool,
+    /// Quarter turns clockwise from the tile's image.
+    rotation: u8,
+    /// Anything else the tileset says about the tile, such as gameplay data,
+    /// carried through to every variant and the result untouched.
+    data: T,
+}
+
+/// A tile as written in a tileset file. Its sockets are kept as raw values
+/// so that a malformed one can be reported along with its tile and edge.
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: DeserializeOwned + Default"))]
+struct RawTileConfig<T> {
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    sockets: Option<Vec<serde_json::Value>>,
+    /// The terrain at each corner, clockwise from the top left, in place of
+    /// `sockets`.
+    #[serde(default)]
+    corners: Option<[u32; 4]>,
+    #[serde(default)]
+    corner_sockets: Option<[u32; 4]>,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    weight: Option<f64>,
+    #[serde(default)]
+    min_count: Option<usize>,
+    #[serde(default)]
+    max_count: Option<usize>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    on: Vec<String>,
+    #[serde(default)]
+    symmetry: Option<Symmetry>,
+    /// `true` for all four rotations, or how many of them to generate.
+    #[serde(default)]
+    rotations: Option<serde_json::Value>,
+    #[serde(default)]
+    rotatable: bool,
+    #[serde(default)]
+    reflect: Option<Reflection>,
+    #[serde(default)]
+    data: T,
+}
+
+impl<T> TryFrom<RawTileConfig<T>> for TileConfig<T> {
+    type Error = String;
 
-    fn connects_to(config: &'t TileConfig, other: &'t TileConfig, edge: &Edge) -> bool {
-        let (Socket(start, mid, end), Socket(other_start, other_mid, other_end)) = match edge {
-            Edge::Top => (&config.sockets.0, &other.sockets.2),
-            Edge::Right => (&config.sockets.1, &other.sockets.3),
-            Edge::Bottom => (&config.sockets.2, &other.sockets.0),
-            Edge::Left => (&config.sockets.3, &other.sockets.1),
+    fn try_from(raw: RawTileConfig<T>) -> Result<Self, Self::Error> {
+        let (sockets, faces) = match (raw.sockets, raw.corners) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "tile `{}` declares both `sockets` and `corners`",
+                    raw.image
+                ))
+            }
+            (None, Some(corners)) => {
+                if corners.contains(&u32::MAX) {
+                    return Err(format!(
+                        "tile `{}`: corner terrains must be integers from 0 to {}",
+                        raw.image,
+                        u32::MAX - 1
+                    ));
+                }
+                // Each edge runs clockwise between the two corners on it.
+                let [top_left, top_right, bottom_right, bottom_left] =
+                    corners.map(|corner| Sock(corner.into()));
+                let edge = |from: &Sock, to: &Sock| Socket(vec![from.clone(), to.clone()]);
+                (
+                    (
+                        edge(&top_left, &top_right),
+                        edge(&top_right, &bottom_right),
+                        edge(&bottom_right, &bottom_left),
+                        edge(&bottom_left, &top_left),
+                    ),
+                    Default::default(),
+                )
+            }
+            (None, None) => Default::default(),
+            (Some(mut values), None) => {
+                if values.len() != 4 && values.len() != 6 {
+                    return Err(format!(
+                        "tile `{}` has {} sockets, but needs one per edge (4), or per edge and face (6)",
+                        raw.image,
+                        values.len()
+                    ));
+                }
+                let faces = values.split_off(4);
+                let socket = |edge: usize, value: &serde_json::Value| {
+                    let name = ["Top", "Right", "Bottom", "Left", "Up", "Down"][edge];
+                    if value == "*" {
+                        return Ok(Socket::wildcard());
+                    }
+                    let socks = value.as_array().ok_or_else(|| {
+                        format!(
+                            "tile `{}`, edge {} ({}): expected an array of socks or `\"*\"`, found `{}`",
+                            raw.image, edge, name, value
+                        )
+                    })?;
+                    socks
+                        .iter()
+                        .map(|sock| {
+                            Sock::parse(sock).ok_or_else(|| {
+                                format!(
+                                    "tile `{}`, edge {} ({}): sock `{}` is neither a label nor an integer from 0 to {}",
+                                    raw.image, edge, name, sock, u32::MAX - 1
+                                )
+                            })
+                        })
+                        .collect::<Result<_, _>>()
+                        .map(Socket)
+                };
+                let faces = match faces.as_slice() {
+                    [up, down] => (socket(4, up)?, socket(5, down)?),
+                    _ => Default::default(),
+                };
+                (
+                    (
+                        socket(0, &values[0])?,
+                        socket(1, &values[1])?,
+                        socket(2, &values[2])?,
+                        socket(3, &values[3])?,
+                    ),
+                    faces,
+                )
+            }
+        };
+
+        let reflect = match (raw.symmetry, raw.reflect) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "tile `{}` declares both `symmetry` and `reflect`",
+                    raw.image
+                ))
+            }
+            (Some(symmetry), None) => symmetry.reflect(),
+            (None, reflect) => reflect,
+        };
+        let rotations = match (raw.symmetry, raw.rotations) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "tile `{}` declares both `symmetry` and `rotations`",
+                    raw.image
+                ))
+            }
+            (Some(symmetry), None) => symmetry.rotations(),
+            (None, None) | (None, Some(serde_json::Value::Bool(false))) => 1,
+            (None, Some(serde_json::Value::Bool(true))) => 4,
+            (None, Some(value)) => value
+                .as_u64()
+                .filter(|count| (1..=4).contains(count))
+                .map(|count| count as u8)
+                .ok_or_else(|| {
+                    format!(
+                        "tile `{}`: `rotations` must be a boolean or a count from 1 to 4, found `{}`",
+                        raw.image, value
+                    )
+                })?,
         };
 
-        start == other_end && mid == other_mid && end == other_start
+        Ok(TileConfig {
+            image: raw.image,
+            sockets,
+            faces,
+            cornered: raw.corners.is_some(),
+            corner_sockets: raw.corner_sockets,
+            transparent: raw.transparent,
+            weight: raw.weight,
+            min_count: raw.min_count,
+            max_count: raw.max_count,
+            tags: raw.tags,
+            on: raw.on,
+            symmetry: raw.symmetry,
+            rotations,
+            rotatable: raw.rotatable,
+            reflect,
+            mirrored: false,
+            rotation: 0,
+            data: raw.data,
+        })
     }
+}
 
-    fn entropy(&self) -> usize {
-        self.possibilities.len() - 1
+impl<T> TileConfig<T> {
+    /// The tile's `data`, or `T::default()` if the tileset gave none.
+    pub fn data(&self) -> &T {
+        &self.data
     }
 
-    fn collapse(&mut self) {
-        let mut rng = rand::thread_rng();
-        let ps = self.possibilities.clone();
-        let collapsed = ps.iter().collect::<Vec<_>>();
-        let collapsed = collapsed.choose(&mut rng).unwrap().to_owned();
-        self.possibilities = HashSet::new();
-        self.possibilities.insert(collapsed);
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
     }
 
-    fn is_collapsed(&self) -> bool {
-        self.entropy() == 0
+    pub fn weight(&self) -> f64 {
+        self.weight.unwrap_or(1.0)
     }
 
-    fn image() {
-        todo!()
+    pub fn rotation(&self) -> u8 {
+        self.rotation
     }
-}
 
-#[derive(Debug)]
-pub struct Grid<'t> {
-    tileset: &'t Tileset,
-    options: Settings,
-    cells: Vec<Vec<Cell<'t>>>,
-    finished: bool,
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own| own == tag)
+    }
+
+    /// Whether this tile's image or one of its tags is `name`, for options
+    /// such as [`Options::ground`] that take either.
+    fn is_named(&self, name: &str) -> bool {
+        self.image == name || self.has_tag(name)
+    }
+
+    /// Whether this tile may sit on `below` in the layer underneath.
+    pub fn fits_on<U>(&self, below: &TileConfig<U>) -> bool {
+        self.on.is_empty() || self.on.iter().any(|tag| below.has_tag(tag))
+    }
+
+    /// The terrain at each corner, clockwise from the top left, for tiles
+    /// given by `corners` rather than `sockets`. Such tiles fit wherever the
+    /// corners they share agree, so a grid of them is really a grid of
+    /// terrain at the cell corners, its dual. See [`Grid::to_corners`].
+    pub fn corners(&self) -> Option<[u32; 4]> {
+        if !self.cornered {
+            return None;
+        }
+        let (Socket(top), _, Socket(bottom), _) = &self.sockets;
+        // Corner terrains are always numbers, so they fit back into a u32.
+        Some([&top[0], &top[1], &bottom[0], &bottom[1]].map(|Sock(corner)| *corner as u32))
+    }
+
+    /// Whether `other` may sit diagonally off `corner` of this tile, counting
+    /// clockwise from the top left. Tiles without corner sockets fit anything.
+    fn fits_diagonally(&self, corner: usize, other: &TileConfig<T>) -> bool {
+        match (self.corner_sockets, other.corner_sockets) {
+            (Some(own), Some(theirs)) => own[corner] == theirs[(corner + 2) % 4],
+            _ => true,
+        }
+    }
+
+    /// Whether the tile's image is flipped left to right before it is
+    /// rotated.
+    pub fn is_mirrored(&self) -> bool {
+        self.mirrored
+    }
+
+    /// The tile without its data, for working out how it connects.
+    fn shape(&self) -> TileConfig<()> {
+        TileConfig {
+            image: self.image.clone(),
+            sockets: self.sockets.clone(),
+            faces: self.faces.clone(),
+            cornered: self.cornered,
+            corner_sockets: self.corner_sockets,
+            transparent: self.transparent,
+            weight: self.weight,
+            min_count: self.min_count,
+            max_count: self.max_count,
+            tags: self.tags.clone(),
+            on: self.on.clone(),
+            symmetry: self.symmetry,
+            rotations: self.rotations,
+            rotatable: self.rotatable,
+            reflect: self.reflect,
+            mirrored: self.mirrored,
+            rotation: self.rotation,
+            data: (),
+        }
+    }
+
+    fn socket(&self, edge: Edge) -> &Socket {
+        match edge {
+            Edge::Top => &self.sockets.0,
+            Edge::Right => &self.sockets.1,
+            Edge::Bottom => &self.sockets.2,
+            Edge::Left => &self.sockets.3,
+        }
+    }
+
+    /// How strongly `other` fits against `edge` of this tile, from 0 (never)
+    /// to 1 (a clean match). Each pair of facing socks contributes 1 if they
+    /// are equal, the strength of a matching `soft` entry if there is one, and
+    /// 0 otherwise; the edge's strength is the product over all of them. A
+    /// wildcard socket on either side is a clean match.
+    fn connects_to(&self, other: &TileConfig<T>, edge: Edge, soft: &[SoftMatch]) -> f64 {
+        if self.transparent || other.transparent {
+            return 1.0;
+        }
+
+        let (socket, other_socket) = (self.socket(edge), other.socket(edge.opposite()));
+        if socket.is_wildcard() || other_socket.is_wildcard() {
+            return 1.0;
+        }
+        let (Socket(socks), Socket(other_socks)) = (socket, other_socket);
+
+        if socks.len() != other_socks.len() {
+            return 0.0;
+        }
+
+        socks
+            .iter()
+            .zip(other_socks.iter().rev())
+            .map(|(sock, other_sock)| {
+                if sock == other_sock {
+                    return 1.0;
+                }
+
+                soft.iter()
+                    .find(|SoftMatch { socks: (a, b), .. }| {
+                        (a, b) == (sock, other_sock) || (b, a) == (sock, other_sock)
+                    })
+                    .map_or(0.0, |soft| soft.strength.clamp(0.0, 1.0))
+            })
+            .product()
+    }
 }
 
-impl<'t> Grid<'t> {
-    fn new(tileset: &'t Tileset, options: Settings) -> Self {
-        let mut cells: Vec<Vec<Cell>> = Vec::new();
-        for _ in 0..options.height {
-            let mut row = Vec::new();
-            for _ in 0..options.width {
-                row.push(Cell::new(&tileset));
-            }
+impl<T: Clone> TileConfig<T> {
+    /// This tile turned a quarter clockwise: each socket moves to the next
+    /// edge, and since socks are listed clockwise their order is unchanged.
+    fn rotated(&self) -> TileConfig<T> {
+        let (top, right, bottom, left) = self.sockets.clone();
+        TileConfig {
+            sockets: (left, top, right, bottom),
+            corner_sockets: self.corner_sockets.map(|[a, b, c, d]| [d, a, b, c]),
+            rotation: (self.rotation + 1) % 4,
+            ..self.clone()
+        }
+    }
 
-            cells.push(row);
+    /// This tile flipped left to right. Flipping reverses the clockwise order
+    /// of every edge's socks, and swaps the left and right edges.
+    fn mirrored(&self) -> TileConfig<T> {
+        let (top, right, bottom, left) = self.sockets.clone();
+        let reversed = |Socket(mut socks): Socket| {
+            socks.reverse();
+            Socket(socks)
+        };
+        TileConfig {
+            sockets: (
+                reversed(top),
+                reversed(left),
+                reversed(bottom),
+                reversed(right),
+            ),
+            corner_sockets: self.corner_sockets.map(|[a, b, c, d]| [b, a, d, c]),
+            mirrored: !self.mirrored,
+            rotation: (4 - self.rotation) % 4,
+            ..self.clone()
         }
+    }
 
-        Grid {
-            tileset,
-            options,
-            cells,
-            finished: false,
+    /// This tile's mirror images under `reflect`.
+    fn reflections(&self, reflect: Reflection) -> Vec<TileConfig<T>> {
+        let flipped = || self.mirrored().rotated().rotated();
+        match reflect {
+            Reflection::Horizontal => vec![self.mirrored()],
+            Reflection::Vertical => vec![flipped()],
+            Reflection::Both => vec![self.mirrored(), flipped()],
         }
     }
+}
 
-    fn propagate(&mut self) {
-        loop {
-            let mut constrained = false;
+/// Lets two different socks meet, discouraged by `strength` (between 0 and
+/// 1) relative to a clean match.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct SoftMatch {
+    socks: (Sock, Sock),
+    strength: f64,
+}
 
-            for y in 0..self.options.height {
-                for x in 0..self.options.width {
-                    let mut cell = self.cells[y][x].clone();
+/// Lets the tile drawn from image `other` sit on `edge` of any tile drawn
+/// from image `tile`, and so `tile` on the opposite edge of `other`.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct AdjacencyRule {
+    pub tile: String,
+    pub edge: Edge,
+    pub other: String,
+}
 
-                    if let Some(top) = if y > 0 { self.get(x, y - 1) } else { None } {
-                        if cell.constrain(&top, Edge::Top) {
-                            constrained = true;
-                        }
-                    }
-                    if let Some(right) = if x < self.options.width - 1 {
-                        self.get(x + 1, y)
-                    } else {
-                        None
-                    } {
-                        if cell.constrain(right, Edge::Right) {
-                            constrained = true;
-                        }
-                    }
-                    if let Some(bottom) = if y < self.options.height - 1 {
-                        self.get(x, y + 1)
-                    } else {
-                        None
-                    } {
-                        if cell.constrain(bottom, Edge::Bottom) {
-                            constrained = true;
-                        }
-                    }
-                    if let Some(left) = if x > 0 { self.get(x - 1, y) } else { None } {
-                        if cell.constrain(left, Edge::Left) {
-                            constrained = true;
-                        }
-                    }
+/// The tiles a model picks from, and how they may meet. `T` is the type of
+/// each tile's `data`; see [`Tileset::from_json_typed`].
+#[derive(PartialEq, Debug)]
+pub struct Tileset<T = serde_json::Value> {
+    pub size: u32,
+    pub tiles: Vec<TileConfig<T>>,
+    pub soft: Vec<SoftMatch>,
+    /// Which tiles may neighbor which, spelled out in place of sockets. When
+    /// any are given, sockets are ignored and tiles connect only as these
+    /// rules allow. Rules name tiles by image and don't turn with rotated or
+    /// mirrored variants.
+    pub adjacency: Vec<AdjacencyRule>,
+    /// Sets of tags that can't share an output, such as `["winter",
+    /// "summer"]`. Once any cell collapses to a tile with one tag in a set,
+    /// tiles with the others are ruled out everywhere.
+    pub exclusive: Vec<Vec<String>>,
+    /// The sock labels the tileset uses, so that options can name them too.
+    labels: Labels,
+}
+
+impl<'de, T: DeserializeOwned + Default + Clone> Deserialize<'de> for Tileset<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (raw, labels) = Labels::scope(|| RawTileset::<T>::deserialize(deserializer));
+        Ok(Tileset {
+            labels,
+            ..Tileset::from(raw?)
+        })
+    }
+}
 
-                    self.cells[y][x] = cell;
+/// A tileset as written, before tiles are expanded into their variants.
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: DeserializeOwned + Default"))]
+struct RawTileset<T> {
+    size: u32,
+    tiles: Vec<TileConfig<T>>,
+    #[serde(default)]
+    soft: Vec<SoftMatch>,
+    #[serde(default)]
+    adjacency: Vec<AdjacencyRule>,
+    #[serde(default)]
+    exclusive: Vec<Vec<String>>,
+}
+
+impl<T: Clone> From<RawTileset<T>> for Tileset<T> {
+    fn from(raw: RawTileset<T>) -> Self {
+        let mut tiles = Vec::with_capacity(raw.tiles.len());
+        for tile in raw.tiles {
+            let mut variants = vec![tile];
+            for _ in 1..variants[0].rotations {
+                let next = variants[variants.len() - 1].rotated();
+                variants.push(next);
+            }
+            if let Some(reflect) = variants[0].reflect {
+                // A reflection may land on an orientation already generated,
+                // such as a vertical flip among all four rotations.
+                for reflection in variants
+                    .iter()
+                    .flat_map(|tile| tile.reflections(reflect))
+                    .collect::<Vec<_>>()
+                {
+                    if !variants.iter().any(|tile| {
+                        (tile.mirrored, tile.rotation) == (reflection.mirrored, reflection.rotation)
+                    }) {
+                        variants.push(reflection);
+                    }
                 }
             }
+            tiles.extend(variants);
+        }
 
-            if !constrained {
-                break;
-            }
+        Tileset {
+            size: raw.size,
+            tiles,
+            soft: raw.soft,
+            adjacency: raw.adjacency,
+            exclusive: raw.exclusive,
+            labels: Labels::default(),
         }
     }
+}
 
-    fn next_lowest_entropy(&mut self) -> Option<&mut Cell<'t>> {
-        let mut cell_x = 0;
-        let mut cell_y = 0;
-        let mut entropy = self.tileset.tiles.len();
+impl Tileset {
+    /// Parses a tileset from its JSON description, keeping each tile's
+    /// `data` as raw JSON.
+    pub fn from_json(json: &str) -> Result<Self, WfcError> {
+        Tileset::from_json_typed(json)
+    }
 
-        for (y, row) in self.cells.iter().enumerate() {
-            for (x, cell) in row.iter().enumerate() {
-                if cell.is_collapsed() {
-                    continue;
+    /// Reads and parses the tileset JSON at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WfcError> {
+        Tileset::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl<T: DeserializeOwned + Default + Clone> Tileset<T> {
+    /// Like [`Tileset::from_json`], but reads each tile's `data` as a `T`.
+    /// Tiles without `data` get `T::default()`.
+    pub fn from_json_typed(json: &str) -> Result<Self, WfcError> {
+        serde_json::from_str(json).map_err(|err| WfcError::InvalidTileset(err.to_string()))
+    }
+
+    /// Like [`Tileset::load`], but reads each tile's `data` as a `T`.
+    pub fn load_typed(path: impl AsRef<Path>) -> Result<Self, WfcError> {
+        Tileset::from_json_typed(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl<T> Tileset<T> {
+    /// Loads every tile's image from `dir`, in tile order. Transparent tiles
+    /// get a fully transparent image.
+    pub fn load_images(&self, dir: impl AsRef<Path>) -> Result<Vec<RgbaImage>, WfcError> {
+        self.load_images_with(&FileResolver::new(dir))
+    }
+
+    /// Like [`Tileset::load_images`], but looks each image up through
+    /// `resolver` rather than on disk.
+    pub fn load_images_with(
+        &self,
+        resolver: &impl ImageResolver,
+    ) -> Result<Vec<RgbaImage>, WfcError> {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                if tile.transparent {
+                    return Ok(RgbaImage::new(self.size, self.size));
                 }
 
-                let e = cell.entropy();
-                if e < entropy {
-                    entropy = e;
-                    cell_x = x;
-                    cell_y = y;
+                let mut image = resolver.resolve(&tile.image)?;
+                if tile.mirrored {
+                    image::imageops::flip_horizontal_in_place(&mut image);
+                }
+                let image = match tile.rotation {
+                    1 => image::imageops::rotate90(&image),
+                    2 => image::imageops::rotate180(&image),
+                    3 => image::imageops::rotate270(&image),
+                    _ => image,
+                };
+                if image.dimensions() != (self.size, self.size) {
+                    return Err(WfcError::InvalidTileset(format!(
+                        "tile `{}` is {}x{}, but the tileset size is {}",
+                        tile.image,
+                        image.width(),
+                        image.height(),
+                        self.size
+                    )));
+                }
+
+                Ok(image)
+            })
+            .collect()
+    }
+
+    /// The total weight of every tile.
+    pub fn weight_sum(&self) -> f64 {
+        self.tiles.iter().map(TileConfig::weight).sum()
+    }
+
+    /// Each tile's share of [`Tileset::weight_sum`], summing to 1. The shares
+    /// are all 0 if the total weight is.
+    pub fn normalized_weights(&self) -> Vec<f64> {
+        let sum = self.weight_sum();
+        self.tiles
+            .iter()
+            .map(|tile| if sum > 0.0 { tile.weight() / sum } else { 0.0 })
+            .collect()
+    }
+
+    /// How strongly `other` fits against `edge` of `tile`, by the tileset's
+    /// adjacency rules if it has any, or else by their sockets.
+    fn connection<U>(&self, tile: &TileConfig<U>, other: &TileConfig<U>, edge: Edge) -> f64 {
+        if self.adjacency.is_empty() {
+            return tile.connects_to(other, edge, &self.soft);
+        }
+        if tile.transparent || other.transparent {
+            return 1.0;
+        }
+
+        let allowed = self.adjacency.iter().any(|rule| {
+            (rule.tile == tile.image && rule.edge == edge && rule.other == other.image)
+                || (rule.tile == other.image
+                    && rule.edge == edge.opposite()
+                    && rule.other == tile.image)
+        });
+        if allowed {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Every top socket must line up with every bottom socket, and likewise
+    /// for left and right, so each opposing pair needs a single arity.
+    /// Sockets don't matter once adjacency rules are given.
+    fn check_arity(&self) -> Result<(), WfcError> {
+        if !self.adjacency.is_empty() {
+            return Ok(());
+        }
+        let opaque = || self.tiles.iter().filter(|tile| !tile.transparent);
+        // Wildcards fit any arity, so the first real socket on each axis
+        // sets it.
+        let arity = |edges: [Edge; 2]| {
+            opaque()
+                .flat_map(|tile| edges.map(|edge| tile.socket(edge)))
+                .find(|socket| !socket.is_wildcard())
+                .map_or(0, Socket::arity)
+        };
+        let vertical = arity([Edge::Top, Edge::Bottom]);
+        let horizontal = arity([Edge::Right, Edge::Left]);
+
+        for tile in opaque() {
+            let (top, right, bottom, left) = &tile.sockets;
+            for (edge, socket, expected) in [
+                (Edge::Top, top, vertical),
+                (Edge::Right, right, horizontal),
+                (Edge::Bottom, bottom, vertical),
+                (Edge::Left, left, horizontal),
+            ] {
+                if !socket.is_wildcard() && socket.arity() != expected {
+                    return Err(WfcError::InvalidTileset(format!(
+                        "tile `{}` has {} socks on its {:?} edge, but opposing edges have {}",
+                        tile.image,
+                        socket.arity(),
+                        edge,
+                        expected
+                    )));
                 }
             }
         }
 
-        self.get(cell_x, cell_y)
+        Ok(())
     }
 
-    fn step(&mut self) {
-        let next = self.next_lowest_entropy().unwrap();
-        if next.is_collapsed() {
-            self.finished = true;
-            return;
+    /// Weights are relative likelihoods, so each must be a finite number no
+    /// less than 0.
+    fn check_weights(&self) -> Result<(), WfcError> {
+        match self.tiles.iter().find(|tile| !valid_weight(tile.weight())) {
+            Some(tile) => Err(WfcError::InvalidTileset(format!(
+                "tile `{}` has weight {}, but weights must be finite and at least 0",
+                tile.image,
+                tile.weight()
+            ))),
+            None => Ok(()),
         }
+    }
 
-        next.collapse();
-        self.propagate();
+    fn check_rules(&self) -> Result<(), WfcError> {
+        for rule in &self.adjacency {
+            for image in [&rule.tile, &rule.other] {
+                if !self.tiles.iter().any(|tile| &tile.image == image) {
+                    return Err(WfcError::InvalidTileset(format!(
+                        "an adjacency rule names tile `{}`, but no tile has that image",
+                        image
+                    )));
+                }
+            }
+        }
+
+        Ok(())
     }
 
-    fn get(&mut self, x: usize, y: usize) -> Option<&mut Cell<'t>> {
-        self.cells.get_mut(y)?.get_mut(x)
+    fn check_counts(&self) -> Result<(), WfcError> {
+        for tile in &self.tiles {
+            if let (Some(min), Some(max)) = (tile.min_count, tile.max_count) {
+                if min > max {
+                    return Err(WfcError::InvalidTileset(format!(
+                        "tile `{}` needs at least {} cells, but at most {}",
+                        tile.image, min, max
+                    )));
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
-pub struct Model<'t> {
-    tileset: &'t Tileset,
-    grid: Grid<'t>,
+fn valid_weight(weight: f64) -> bool {
+    weight.is_finite() && weight >= 0.0
 }
 
-const DEFAULT_WIDTH: usize = 10;
-const DEFAULT_HEIGHT: usize = 10;
+/// Which tiles may sit against each edge of every other tile, computed once
+/// per tileset and shared by every grid generated from it.
+#[derive(Debug)]
+struct Adjacency {
+    /// `strength[tile][edge][other]` is how well `other` fits on that edge,
+    /// where 0 means it may not sit there at all.
+    strength: Vec<[Vec<f64>; 4]>,
+    /// `compatible[tile][edge]` lists every `other` that may sit on that edge.
+    compatible: Vec<[Vec<usize>; 4]>,
+    /// Like `strength`, but between tiles turned on the fly, indexed by
+    /// `tile * 4 + rotation`. Only built when some tile is rotatable, in which
+    /// case `strength` holds the best match over every pair of rotations.
+    oriented: Option<Vec<[Vec<f64>; 4]>>,
+}
 
-impl<'t> Model<'t> {
-    pub fn new(tileset: &'t Tileset, options: Options) -> Self {
-        let settings = Settings {
-            width: options.width.unwrap_or(DEFAULT_WIDTH),
-            height: options.height.unwrap_or(DEFAULT_HEIGHT),
-            framerate: None,
-            seed: None,
+impl Adjacency {
+    fn new<T>(tileset: &Tileset<T>, forbidden: &[(usize, usize, Edge)]) -> Result<Self, WfcError> {
+        let table = |tiles: &[Option<TileConfig<()>>]| {
+            tiles
+                .iter()
+                .map(|config| {
+                    EDGES.map(|edge| {
+                        tiles
+                            .iter()
+                            .map(|other| match (config, other) {
+                                (Some(config), Some(other)) => {
+                                    tileset.connection(config, other, edge)
+                                }
+                                _ => 0.0,
+                            })
+                            .collect()
+                    })
+                })
+                .collect::<Vec<[Vec<f64>; 4]>>()
         };
 
-        let grid = Grid::new(&tileset, settings);
-
-        Model { tileset, grid }
-    }
+        let mut oriented = tileset
+            .tiles
+            .iter()
+            .any(|config| config.rotatable)
+            .then(|| {
+                let turns = tileset
+                    .tiles
+                    .iter()
+                    .flat_map(|config| {
+                        std::iter::successors(Some(config.shape()), |turned| Some(turned.rotated()))
+                            .take(4)
+                            .enumerate()
+                            .map(|(rotation, turned)| {
+                                (rotation == 0 || config.rotatable).then_some(turned)
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                table(&turns)
+            });
+        let mut strength = match &oriented {
+            Some(oriented) => (0..tileset.tiles.len())
+                .map(|tile| {
+                    EDGES.map(|edge| {
+                        (0..tileset.tiles.len())
+                            .map(|other| {
+                                (0..4)
+                                    .flat_map(|rotation| {
+                                        (0..4).map(move |other_rotation| {
+                                            oriented[tile * 4 + rotation][edge as usize]
+                                                [other * 4 + other_rotation]
+                                        })
+                                    })
+                                    .fold(0.0, f64::max)
+                            })
+                            .collect()
+                    })
+                })
+                .collect(),
+            None => table(
+                &tileset
+                    .tiles
+                    .iter()
+                    .map(|tile| Some(tile.shape()))
+                    .collect::<Vec<_>>(),
+            ),
+        };
 
-    pub fn run(&mut self) {
-        loop {
-            self.grid.step();
-            if self.grid.finished {
-                break;
+        for &(tile, other, edge) in forbidden {
+            for index in [tile, other] {
+                if index >= tileset.tiles.len() {
+                    return Err(WfcError::UnknownTile(index));
+                }
+            }
+            strength[tile][edge as usize][other] = 0.0;
+            strength[other][edge.opposite() as usize][tile] = 0.0;
+            if let Some(oriented) = &mut oriented {
+                for rotation in 0..4 {
+                    for other_rotation in 0..4 {
+                        let (turned, other_turned) =
+                            (tile * 4 + rotation, other * 4 + other_rotation);
+                        oriented[turned][edge as usize][other_turned] = 0.0;
+                        oriented[other_turned][edge.opposite() as usize][turned] = 0.0;
+                    }
+                }
             }
         }
+
+        let compatible = strength
+            .iter()
+            .map(|edges| {
+                edges.each_ref().map(|others| {
+                    others
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(other, &strength)| (strength > 0.0).then_some(other))
+                        .collect()
+                })
+            })
+            .collect();
+
+        Ok(Adjacency {
+            strength,
+            compatible,
+            oriented,
+        })
+    }
+
+    fn allows(&self, tile: usize, edge: Edge, other: usize) -> bool {
+        self.strength(tile, edge, other) > 0.0
+    }
+
+    fn strength(&self, tile: usize, edge: Edge, other: usize) -> f64 {
+        self.strength[tile][edge as usize][other]
+    }
+
+    /// The strength between `(tile, rotation)` and `(other, rotation)`, or 0
+    /// if no tile is rotatable.
+    fn oriented_strength(&self, tile: (usize, u8), edge: Edge, other: (usize, u8)) -> f64 {
+        self.oriented.as_ref().map_or(0.0, |oriented| {
+            oriented[tile.0 * 4 + tile.1 as usize][edge as usize][other.0 * 4 + other.1 as usize]
+        })
     }
 }
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Propagator {
+    /// Keeps a queue of cells to re-check against their neighbors, seeded
+    /// around every removal and growing only when a re-check removes more.
+    #[default]
+    Worklist,
+    /// Counts, per cell, tile, and edge, how many neighboring tiles support
+    /// each possibility (as in AC-4), and only removes a tile once one of its
+    /// counts reaches zero.
+    SupportCount,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecoveryMode {
+    /// Leave contradicted cells empty and carry on with the rest of the grid.
+    #[default]
+    None,
+    /// Throw the grid away and start over.
+    Restart,
+    /// Reset the contradicted cell and its neighborhood to every tile and
+    /// propagate again, widening the neighborhood on each attempt before
+    /// falling back to a restart. The first neighborhood reaches
+    /// [`Options::reset_radius`] cells out.
+    LocalReset,
+    /// Undo the most recent decision and rule out the tile it chose, so the
+    /// cell tries another, rewinding further whenever that runs out of tiles.
+    /// Gives up, leaving the contradiction, after rewinding
+    /// [`Options::max_backtrack_depth`] decisions in a row.
+    Backtrack,
+    /// Like [`RecoveryMode::Backtrack`], but jumps straight back to the most
+    /// recent decision that ruled anything out of the contradicted cell,
+    /// undoing every decision after it at once, since none of them could have
+    /// caused the contradiction. When that decision then runs out of tiles,
+    /// it jumps back to whatever narrowed either cell, and so on. Each jump
+    /// counts once towards [`Options::max_backtrack_depth`].
+    Backjump,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many runs to try in all, counting the first.
+    pub max_attempts: usize,
+    /// Seeds each retry from the previous attempt's RNG. Otherwise retries
+    /// reuse the original seed, so only unseeded models can turn out any
+    /// differently.
+    pub reseed: bool,
+}
+
+/// How a call to [`Model::run`] went.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunStats {
+    /// How many fresh grids were run, counting the first.
+    pub attempts: usize,
+    /// How many times recovery restarted a grid, across every attempt.
+    pub restarts: usize,
+    /// Whether the final grid is free of contradictions.
+    pub succeeded: bool,
+    /// Whether the run was stopped by [`Options::cancel`] before finishing.
+    pub cancelled: bool,
+}
+
+/// How far a grid has got, as returned by [`Model::progress`]. Masked-out
+/// cells aren't counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// How many cells have collapsed to a single tile.
+    pub collapsed: usize,
+    pub total: usize,
+    /// How many cells no tile fits.
+    pub contradictions: usize,
+    /// How many steps have been taken, counting those later undone.
+    pub steps: usize,
+}
+
+/// One collapse and the propagation after it, as yielded by [`Model::steps`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub x: usize,
+    pub y: usize,
+    /// The tile the cell collapsed to, or `None` if no tile fit.
+    pub tile: Option<usize>,
+    /// [`Grid::collapsed_fraction`] once the step, and any recovery, is done.
+    pub collapsed: f64,
+    /// Whether the grid was left with a contradiction recovery couldn't
+    /// resolve.
+    pub contradicted: bool,
+}
+
+/// Steps a model's grid one collapse at a time. See [`Model::steps`].
+pub struct Steps<'m, 't, T = serde_json::Value> {
+    grid: &'m mut Grid<'t, T>,
+}
+
+impl<'t, T> Steps<'_, 't, T> {
+    /// The grid as the last step left it.
+    pub fn grid(&self) -> &Grid<'t, T> {
+        self.grid
+    }
+}
+
+impl<T> Iterator for Steps<'_, '_, T> {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        if self.grid.finished || self.grid.is_cancelled() {
+            return None;
+        }
+        if let Some(stop_at) = self.grid.options.stop_at {
+            if self.grid.collapsed_fraction() >= stop_at {
+                self.grid.fill_remaining();
+                return None;
+            }
+        }
+        self.grid.advance()
+    }
+}
+
+/// Where the very first collapse happens. Every later collapse follows the
+/// lowest-entropy heuristic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartPoint {
+    /// The top-left cell.
+    #[default]
+    Corner,
+    /// The middle of the grid, which tends to grow structure outward evenly.
+    Center,
+    /// A uniformly random cell.
+    Random,
+    At(usize, usize),
+}
+
+/// A rectangle of cells, `width` across and `height` down from `(x, y)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// How [`Grid::step`] picks the next cell to collapse, after the first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Heuristic {
+    /// The cell with the fewest weighted possibilities left, which dead-ends
+    /// least often.
+    #[default]
+    LeastEntropy,
+    /// The first open cell in row-major order. Much faster, but only
+    /// tilesets that rarely contradict survive it.
+    Scanline,
+    /// Outward from the middle of the grid, ring by ring.
+    Spiral,
+    /// Any open cell, uniformly at random.
+    Random,
+}
+
+/// A rule on how far apart cells with certain tags may be, counted in steps
+/// between neighbors, without wrapping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DistanceRule {
+    /// Every cell with `tag` is within `distance` of a cell with `other`, as
+    /// every house might need a road nearby.
+    Near {
+        tag: String,
+        other: String,
+        distance: usize,
+    },
+    /// No cell with `tag` is within `distance` of another cell with `other`,
+    /// which may be the same tag, to keep bosses apart.
+    Apart {
+        tag: String,
+        other: String,
+        distance: usize,
+    },
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    /// How many grids [`LayeredModel::stacked`] stacks into a volume. A
+    /// single [`Model`] is always one deep.
+    pub depth: Option<usize>,
+    pub framerate: Option<u32>,
+    /// Seeds every random choice the run makes, so the same tileset, options,
+    /// and seed always give the same output. Unseeded runs draw from entropy;
+    /// see [`Grid::rng_state`] to replay one.
+    pub seed: Option<u64>,
+    pub propagator: Option<Propagator>,
+    /// Tile pairs that may never be neighbors even if their sockets match.
+    /// `(tile, other, edge)` forbids `other` from sitting on `edge` of `tile`.
+    pub forbidden: Vec<(usize, usize, Edge)>,
+    /// Once this fraction of cells has collapsed, [`Model::run`] stops
+    /// searching and fills every remaining cell with its highest-weight
+    /// candidate, without further propagation.
+    pub stop_at: Option<f64>,
+    /// Checked between steps: once it's set, [`Model::run`] stops where it
+    /// is, leaving the partial grid to inspect, and reports the run as
+    /// cancelled. Set it from another thread to abort a long run.
+    pub cancel: Option<Arc<AtomicBool>>,
+    pub recovery: Option<RecoveryMode>,
+    /// How many decisions [`RecoveryMode::Backtrack`] may rewind, or jumps
+    /// [`RecoveryMode::Backjump`] may make, to resolve a single
+    /// contradiction. Unlimited by default.
+    pub max_backtrack_depth: Option<usize>,
+    /// How far [`RecoveryMode::LocalReset`] first reaches from the
+    /// contradicted cell, in cells. Defaults to 1.
+    pub reset_radius: Option<usize>,
+    /// Whether [`Model::run`] starts over with a fresh grid when a run ends in
+    /// a contradiction that recovery couldn't resolve.
+    pub retry: Option<RetryPolicy>,
+    /// Per-cell weight multipliers, as `(tile, multiplier)` pairs, applied on
+    /// top of each tile's base weight when the cell at `(x, y)` collapses.
+    /// Tiles left out keep their base weight.
+    pub bias_field: Option<Box<BiasField>>,
+    pub start: Option<StartPoint>,
+    pub heuristic: Option<Heuristic>,
+    /// Replaces every random choice with the lowest-index one, making a run a
+    /// pure function of the tileset and options: each step collapses the
+    /// first lowest-entropy cell in row-major order to its first candidate
+    /// with any weight, [`StartPoint::Random`] starts in the corner, and the
+    /// seed is ignored.
+    pub deterministic: bool,
+    /// Replaces the base weight of each listed tile for this run only,
+    /// leaving the tileset untouched.
+    pub weight_overrides: HashMap<usize, f64>,
+    /// Makes particular neighbors more or less likely, as `(tile, other,
+    /// multiplier)`: a cell's weight for `tile` is multiplied once for every
+    /// collapsed neighbor that is `other`, and the same the other way round.
+    pub pair_weights: Vec<(usize, usize, f64)>,
+    /// Per-cell weight multipliers for particular tiles, each given row by
+    /// row for the whole grid, such as water growing likelier towards the
+    /// bottom of the map. See [`luma_map`].
+    pub weight_maps: HashMap<usize, Vec<Vec<f64>>>,
+    /// Smooth noise fields that sway the weights of tagged tiles across the
+    /// grid, for large-scale structure. Each is seeded from the run's seed, so
+    /// retries keep the same fields.
+    pub noise: Vec<NoiseBias>,
+    /// Joins the left edge of the grid to the right and the top to the
+    /// bottom, so the output tiles seamlessly.
+    pub wrap: bool,
+    /// Guarantees output that tiles seamlessly: the grid wraps while it's
+    /// solved, and [`Model::run`] counts a grid whose opposite edges don't
+    /// connect, such as one finished early by `stop_at`, as a failed
+    /// attempt. See [`Grid::is_seamless`].
+    pub periodic: bool,
+    /// Restricts every cell along an edge of the grid to tiles whose socket
+    /// on that edge is exactly the given socks, as if the grid were bordered
+    /// by tiles with that socket. Socks are given by number or by label.
+    /// Ignored when the grid wraps.
+    pub borders: Vec<(Edge, Vec<SockName>)>,
+    /// Cells collapsed to fixed tiles before generation starts, as
+    /// `(x, y, tile)`. See [`Model::pin`].
+    pub pins: Vec<(usize, usize, usize)>,
+    /// Tags whose cells must all join up into one region: every cell that
+    /// collapses to a tile with one of these tags must be reachable from
+    /// every other through neighboring cells with the same tag.
+    pub connected: Vec<String>,
+    /// The fewest cells each region of a tag may have, as `(tag, cells)`,
+    /// where a region is a group of tagged cells joined through neighbors.
+    /// No smaller orphans are left.
+    pub min_region: Vec<(String, usize)>,
+    /// Limits on how many cells may end up with a tile carrying each tag, as
+    /// `(tag, min, max)`, counting the tagged tiles together.
+    pub tag_counts: Vec<(String, Option<usize>, Option<usize>)>,
+    /// Restricts cells to tiles with a tag before generation starts, as
+    /// `(x, y, tag)`. Unlike [`Options::pins`], the cell is left to choose
+    /// among the tagged tiles.
+    pub tag_pins: Vec<(usize, usize, String)>,
+    /// Restricts every cell along an edge of the grid to tiles with a tag.
+    /// Ignored when the grid wraps, like `borders`.
+    pub tag_borders: Vec<(Edge, String)>,
+    /// Tags, or images of tiles, that at least one cell must end up with. A
+    /// run contradicts as soon as too few cells are left that could.
+    pub required: Vec<String>,
+    /// Rules on how near cells with certain tags must be to each other, or
+    /// how far apart, enforced after every collapse.
+    pub distances: Vec<DistanceRule>,
+    /// A tag, or the image of a tile, that fills the bottom row of the grid
+    /// and appears nowhere else, like the ground under a side-on level.
+    pub ground: Option<String>,
+    /// The tiles each cell may take, row by row, with every other tile ruled
+    /// out before generation, and again after every reset. This is how
+    /// [`HierarchicalModel`] constrains a detail grid by its coarse layout.
+    pub allowed: Option<Vec<Vec<Vec<usize>>>>,
+    /// Which cells take part in generation, row by row. Masked-out cells are
+    /// left empty and don't count as anyone's neighbor, so the grid can take
+    /// any shape. When `width` and `height` aren't given they come from the
+    /// mask. See [`alpha_mask`].
+    pub mask: Option<Vec<Vec<bool>>>,
+}
+
+/// A mask with a cell for every pixel of `image`, masked in wherever the pixel
+/// isn't fully transparent.
+pub fn alpha_mask(image: &RgbaImage) -> Vec<Vec<bool>> {
+    image
+        .rows()
+        .map(|row| row.map(|pixel| pixel.0[3] > 0).collect())
+        .collect()
+}
+
+/// A weight map with a cell for every pixel of `image`, from 0 where the pixel
+/// is black to 1 where it's white.
+pub fn luma_map(image: &RgbaImage) -> Vec<Vec<f64>> {
+    image
+        .rows()
+        .map(|row| {
+            row.map(|pixel| {
+                let [r, g, b, _] = pixel.0;
+                (0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64) / 255.0
+            })
+            .collect()
+        })
+        .collect()
+}
+
+pub type BiasField = dyn Fn(usize, usize) -> Vec<(usize, f64)> + Send + Sync;
+
+#[derive(Clone)]
+struct Bias(Arc<BiasField>);
+
+impl fmt::Debug for Bias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Bias(..)")
+    }
+}
+
+/// Something that happened while generating, sent to every channel from
+/// [`Model::subscribe`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WfcEvent {
+    /// The cell was left with `tile` alone.
+    Collapsed { x: usize, y: usize, tile: usize },
+    /// `tile` was ruled out of the cell.
+    Eliminated { x: usize, y: usize, tile: usize },
+    /// The cell was left with no tile at all.
+    Contradiction { x: usize, y: usize },
+    /// The grid has nothing left to collapse.
+    Finished,
+}
+
+/// Called with `(x, y, tile)` for each event it was registered for. See
+/// [`Model::on_collapse`] and [`Model::on_eliminate`].
+pub type Observer = dyn Fn(usize, usize, usize) + Send + Sync;
+
+#[derive(Clone)]
+struct Hook(Arc<Observer>);
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook(..)")
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Settings {
+    width: usize,
+    height: usize,
+    framerate: Option<u32>,
+    seed: Option<u64>,
+    propagator: Propagator,
+    stop_at: Option<f64>,
+    cancel: Option<Arc<AtomicBool>>,
+    recovery: RecoveryMode,
+    max_backtrack_depth: Option<usize>,
+    reset_radius: usize,
+    retry: Option<RetryPolicy>,
+    bias: Option<Bias>,
+    start: StartPoint,
+    heuristic: Heuristic,
+    deterministic: bool,
+    weight_overrides: HashMap<usize, f64>,
+    /// Both orderings of every pair in [`Options::pair_weights`].
+    pair_weights: HashMap<(usize, usize), f64>,
+    weight_maps: HashMap<usize, Vec<Vec<f64>>>,
+    noise: Vec<(NoiseBias, noise::Perlin)>,
+    wrap: bool,
+    periodic: bool,
+    borders: Vec<(Edge, Socket)>,
+    connected: Vec<String>,
+    min_region: Vec<(String, usize)>,
+    /// The tiles of every tag in [`Options::tag_counts`], along with its
+    /// limits, and of every [`Options::required`] name, needing one.
+    tag_counts: Vec<(Vec<usize>, Option<usize>, Option<usize>)>,
+    distances: Vec<DistanceRule>,
+    ground: Option<String>,
+    allowed: Option<Vec<Vec<Vec<usize>>>>,
+    mask: Option<Vec<Vec<bool>>>,
+    on_collapse: Option<Hook>,
+    on_eliminate: Option<Hook>,
+    /// Every channel handed out by [`Model::subscribe`].
+    subscribers: Vec<Sender<WfcEvent>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            framerate: None,
+            seed: None,
+            propagator: Propagator::default(),
+            stop_at: None,
+            cancel: None,
+            recovery: RecoveryMode::default(),
+            max_backtrack_depth: None,
+            reset_radius: 1,
+            retry: None,
+            bias: None,
+            start: StartPoint::default(),
+            heuristic: Heuristic::default(),
+            deterministic: false,
+            weight_overrides: HashMap::new(),
+            pair_weights: HashMap::new(),
+            weight_maps: HashMap::new(),
+            noise: Vec::new(),
+            wrap: false,
+            periodic: false,
+            borders: Vec::new(),
+            connected: Vec::new(),
+            min_region: Vec::new(),
+            tag_counts: Vec::new(),
+            distances: Vec::new(),
+            ground: None,
+            allowed: None,
+            mask: None,
+            on_collapse: None,
+            on_eliminate: None,
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+/// A cell of a [`Grid`], holding the tiles it may still become.
+#[derive(Clone, Debug)]
+pub struct Cell {
+    possibilities: BTreeSet<usize>,
+}
+
+impl Cell {
+    /// The indices of the tiles the cell may still become, in order.
+    pub fn possibilities(&self) -> &BTreeSet<usize> {
+        &self.possibilities
+    }
+
+    /// The cell's tile, once it has collapsed to a single one.
+    pub fn tile(&self) -> Option<usize> {
+        self.possibilities
+            .first()
+            .copied()
+            .filter(|_| self.is_collapsed())
+    }
+
+    fn new<T>(tileset: &Tileset<T>) -> Self {
+        Cell {
+            possibilities: (0..tileset.tiles.len()).collect(),
+        }
+    }
+
+    fn constrain(
+        &mut self,
+        other_cell: &Cell,
+        edge: Edge,
+        adjacency: &Adjacency,
+        checks: &mut usize,
+        removed: &mut Vec<usize>,
+    ) -> bool {
+        let before = removed.len();
+        self.possibilities.retain(|&possibility| {
+            let allowed = other_cell.possibilities.iter().any(|&other| {
+                *checks += 1;
+                adjacency.allows(possibility, edge, other)
+            });
+            if !allowed {
+                removed.push(possibility);
+            }
+            allowed
+        });
+
+        removed.len() > before
+    }
+
+    fn entropy(&self) -> usize {
+        self.possibilities.len().saturating_sub(1)
+    }
+
+    /// The Shannon entropy of the remaining possibilities, each as likely as
+    /// its share of their total weight in `weights` (indexed by tile).
+    fn shannon_entropy(&self, weights: &[f64]) -> f64 {
+        let (sum, sum_log) = self
+            .possibilities
+            .iter()
+            .map(|&tile| weights[tile])
+            .filter(|&weight| weight > 0.0)
+            .fold((0.0, 0.0), |(sum, sum_log), weight| {
+                (sum + weight, sum_log + weight * weight.ln())
+            });
+        if sum > 0.0 {
+            sum.ln() - sum_log / sum
+        } else {
+            0.0
+        }
+    }
+
+    /// Collapses to a random possibility, chosen in proportion to `weights`
+    /// (indexed by tile), returning the tiles it ruled out.
+    fn collapse(&mut self, rng: &mut ChaCha8Rng, weights: &[f64]) -> Vec<usize> {
+        let candidates = self.possibilities.iter().copied().collect::<Vec<_>>();
+        let collapsed = match candidates.choose_weighted(rng, |&tile| weights[tile]) {
+            Ok(&tile) => tile,
+            Err(_) => *candidates.choose(rng).unwrap(),
+        };
+        self.collapse_to(collapsed)
+    }
+
+    /// Collapses to the lowest-index candidate with any weight, or to the
+    /// lowest-index candidate if none has.
+    fn collapse_first(&mut self, weights: &[f64]) -> Vec<usize> {
+        let first = self.possibilities.first().copied();
+        let collapsed = self
+            .possibilities
+            .iter()
+            .copied()
+            .find(|&tile| weights[tile] > 0.0)
+            .or(first);
+        match collapsed {
+            Some(tile) => self.collapse_to(tile),
+            None => Vec::new(),
+        }
+    }
+
+    fn collapse_to(&mut self, tile: usize) -> Vec<usize> {
+        let removed = std::mem::replace(&mut self.possibilities, BTreeSet::from([tile]));
+        removed.into_iter().filter(|&other| other != tile).collect()
+    }
+
+    pub fn is_collapsed(&self) -> bool {
+        self.possibilities.len() == 1
+    }
+
+    /// Whether no tile fits the cell, which masked-out cells never have.
+    pub fn is_contradiction(&self) -> bool {
+        self.possibilities.is_empty()
+    }
+}
+
+/// Everything needed to put a grid's RNG back exactly where it was, so a
+/// checkpointed run continues with the same collapses it would have made.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngState {
+    seed: [u8; 32],
+    stream: u64,
+    word_pos: u128,
+}
+
+/// The cell at `(x, y)`. Panics if it's off the grid.
+impl<T> Index<(usize, usize)> for Grid<'_, T> {
+    type Output = Cell;
+
+    fn index(&self, (x, y): (usize, usize)) -> &Cell {
+        &self.cells[y][x]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<'_, T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Cell {
+        &mut self.cells[y][x]
+    }
+}
+
+/// A finished grid, as returned by [`Grid::result`].
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct GridResult<'t, T = serde_json::Value> {
+    pub width: usize,
+    pub height: usize,
+    /// Each cell's tile, row by row, or `None` if the cell is masked out.
+    pub cells: Vec<Vec<Option<PlacedTile<'t, T>>>>,
+}
+
+// Neither struct holds a `T` of its own, so cloning doesn't need `T: Clone`.
+impl<T> Clone for GridResult<'_, T> {
+    fn clone(&self) -> Self {
+        GridResult {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.clone(),
+        }
+    }
+}
+
+/// A collapsed cell's tile, and how to draw it.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct PlacedTile<'t, T = serde_json::Value> {
+    /// The tile's index in the tileset.
+    pub tile: usize,
+    /// The tile's image, relative to the tileset.
+    pub image: &'t str,
+    /// How many quarter turns clockwise to draw the image, applied after
+    /// mirroring it.
+    pub rotation: u8,
+    /// Whether the image is mirrored left to right.
+    pub mirrored: bool,
+    /// The tile's data, as the tileset gave it.
+    pub data: &'t T,
+}
+
+impl<T> Clone for PlacedTile<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for PlacedTile<'_, T> {}
+
+/// A half-finished run, saved by [`Model::snapshot`] to be carried on by
+/// [`Model::resume`], possibly in another process. It holds the grid and its
+/// RNG, but not the tileset or options, which the resuming model brings.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedState {
+    width: usize,
+    height: usize,
+    /// The remaining tile indices of every cell, row by row.
+    possibilities: Vec<Vec<Vec<usize>>>,
+    turns: Vec<Vec<Option<u8>>>,
+    rng: RngState,
+    started: bool,
+    finished: bool,
+    restarts: usize,
+    steps: usize,
+    pins: Vec<(usize, usize, usize)>,
+    history: Vec<Decision>,
+}
+
+/// A single collapse, along with every possibility it eliminated.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Decision {
+    x: usize,
+    y: usize,
+    tile: Option<usize>,
+    removed: Vec<(usize, usize, usize)>,
+}
+
+#[derive(Debug)]
+pub struct Grid<'t, T = serde_json::Value> {
+    tileset: &'t Tileset<T>,
+    adjacency: Arc<Adjacency>,
+    /// How the cells join up, shared with the grid's forks.
+    topology: Arc<Topology>,
+    /// Shared with the grid's forks until either changes them.
+    options: Arc<Settings>,
+    cells: Vec<Vec<Cell>>,
+    rng: ChaCha8Rng,
+    finished: bool,
+    history: Vec<Decision>,
+    undone: Vec<Decision>,
+    /// Support counts for [`Propagator::SupportCount`], indexed by cell, then
+    /// tile, then edge. `None` until first needed, or after possibilities
+    /// were restored behind the propagator's back.
+    supports: Option<Vec<[usize; 4]>>,
+    /// Tiles removed from a cell that the support counts haven't seen yet.
+    pending: Vec<(usize, usize, usize)>,
+    /// How many tile pairs the propagator has tested for compatibility.
+    checks: usize,
+    restarts: usize,
+    /// Whether the first collapse, at the [`StartPoint`], has happened.
+    started: bool,
+    /// How many steps the grid has taken, across restarts.
+    steps: usize,
+    /// The quarter turns chosen for each collapsed cell's rotatable tile, or
+    /// `None` until one has been picked.
+    turns: Vec<Vec<Option<u8>>>,
+    /// Cells fixed to a tile as `(x, y, tile)`, which survive recovery.
+    pins: Vec<(usize, usize, usize)>,
+    /// Cells for [`Propagator::Worklist`] to re-check besides those around
+    /// `pending`, such as ones reset to every tile.
+    dirty: Vec<(usize, usize)>,
+    /// Every possibility removed since the current decision began, as
+    /// `(x, y, tile)`, so the decision can be undone without a snapshot.
+    trail: Vec<(usize, usize, usize)>,
+    /// Each cell's Shannon entropy, as `(count, entropy)`, valid while it
+    /// still has `count` possibilities. Possibilities only shrink between
+    /// resets and undos, which clear it.
+    entropies: Vec<(usize, f64)>,
+    /// Every cell in [`Heuristic::Spiral`] order, built when first needed.
+    spiral: Vec<(usize, usize)>,
+}
+
+impl<'t, T> Grid<'t, T> {
+    fn new(tileset: &'t Tileset<T>, adjacency: Arc<Adjacency>, options: Settings) -> Self {
+        let mut cells: Vec<Vec<Cell>> = Vec::new();
+        for _ in 0..options.height {
+            let mut row = Vec::new();
+            for _ in 0..options.width {
+                row.push(Cell::new(tileset));
+            }
+
+            cells.push(row);
+        }
+
+        let rng = match options.seed {
+            _ if options.deterministic => ChaCha8Rng::seed_from_u64(0),
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+
+        // A grid with no cells has nothing left to collapse.
+        let finished = options.width == 0 || options.height == 0;
+        let turns = vec![vec![None; options.width]; options.height];
+        let topology = match options.wrap {
+            true => Topology::torus(options.width, options.height),
+            false => Topology::square(options.width, options.height),
+        };
+
+        let mut grid = Grid {
+            tileset,
+            adjacency,
+            topology: Arc::new(topology),
+            options: Arc::new(options),
+            cells,
+            rng,
+            finished,
+            history: Vec::new(),
+            undone: Vec::new(),
+            supports: None,
+            pending: Vec::new(),
+            checks: 0,
+            restarts: 0,
+            steps: 0,
+            started: false,
+            turns,
+            pins: Vec::new(),
+            dirty: Vec::new(),
+            trail: Vec::new(),
+            entropies: Vec::new(),
+            spiral: Vec::new(),
+        };
+        grid.apply_mask();
+        grid.apply_borders();
+        grid.apply_ground();
+        grid.apply_allowed();
+        if !grid.pending.is_empty() {
+            grid.propagate();
+        }
+        grid
+    }
+
+    /// Builds a fully collapsed grid from the tile index of every cell, row by
+    /// row. This is the inverse of [`Grid::to_indices`].
+    pub fn from_indices(tileset: &'t Tileset<T>, indices: &[Vec<usize>]) -> Result<Self, WfcError> {
+        tileset.check_arity()?;
+
+        let width = row_width(indices)?;
+        let settings = Settings {
+            width,
+            height: indices.len(),
+            ..Default::default()
+        };
+        let adjacency = Arc::new(Adjacency::new(tileset, &[])?);
+        let mut grid = Grid::new(tileset, adjacency, settings);
+
+        for (row, tiles) in grid.cells.iter_mut().zip(indices) {
+            for (cell, &tile) in row.iter_mut().zip(tiles) {
+                if tile >= tileset.tiles.len() {
+                    return Err(WfcError::UnknownTile(tile));
+                }
+                cell.collapse_to(tile);
+            }
+        }
+        grid.finished = true;
+
+        Ok(grid)
+    }
+
+    fn propagate(&mut self) {
+        loop {
+            match self.options.propagator {
+                Propagator::Worklist => self.propagate_worklist(),
+                Propagator::SupportCount => self.propagate_supports(),
+            }
+            if !self.constrain_diagonals() {
+                return;
+            }
+        }
+    }
+
+    /// Rules out every tile that no tile left in one of its diagonal
+    /// neighbors fits against, for tilesets with corner sockets. Returns
+    /// whether anything was removed, which then needs propagating.
+    fn constrain_diagonals(&mut self) -> bool {
+        let tiles = &self.tileset.tiles;
+        if tiles.iter().all(|tile| tile.corner_sockets.is_none()) {
+            return false;
+        }
+
+        let mut unfit = Vec::new();
+        for y in 0..self.options.height {
+            for x in 0..self.options.width {
+                for (corner, nx, ny) in self.diagonal_neighbors(x, y) {
+                    let neighbor = &self.cells[ny][nx].possibilities;
+                    if neighbor.is_empty() {
+                        continue;
+                    }
+                    for &tile in &self.cells[y][x].possibilities {
+                        self.checks += neighbor.len();
+                        if !neighbor
+                            .iter()
+                            .any(|&other| tiles[tile].fits_diagonally(corner, &tiles[other]))
+                        {
+                            unfit.push((x, y, tile));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut removed = false;
+        for (x, y, tile) in unfit {
+            removed |= self.remove(x, y, tile);
+        }
+        removed
+    }
+
+    /// The diagonal neighbors of `(x, y)` in the grid's [`Topology`], along
+    /// with the corner they share, clockwise from the top left. Each is
+    /// found by stepping up or down and then across, so it wraps and skips
+    /// masked-out cells as [`Grid::neighbors`] does.
+    fn diagonal_neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize, usize)> {
+        let width = self.options.width;
+        [[0, 3], [0, 1], [2, 1], [2, 3]]
+            .iter()
+            .enumerate()
+            .filter_map(|(corner, ports)| {
+                let node = self.topology.walk(y * width + x, ports)?;
+                Some((corner, node % width, node / width))
+            })
+            .filter(|&(_, nx, ny)| self.in_mask(nx, ny))
+            .collect()
+    }
+
+    fn propagate_worklist(&mut self) {
+        let width = self.options.width;
+        let mut queued = vec![false; width * self.options.height];
+        let mut queue = VecDeque::new();
+        let mut seeds = std::mem::take(&mut self.dirty);
+        for (x, y, _) in std::mem::take(&mut self.pending) {
+            seeds.extend(self.neighbors(x, y).into_iter().map(|(_, nx, ny)| (nx, ny)));
+        }
+        for (x, y) in seeds {
+            if !std::mem::replace(&mut queued[y * width + x], true) {
+                queue.push_back((x, y));
+            }
+        }
+
+        while let Some((x, y)) = queue.pop_front() {
+            queued[y * width + x] = false;
+            let mut cell = self.cells[y][x].clone();
+            let mut removed = Vec::new();
+            let mut constrained = false;
+            for (edge, nx, ny) in self.neighbors(x, y) {
+                let neighbor = &self.cells[ny][nx];
+                if cell.constrain(
+                    neighbor,
+                    edge,
+                    &self.adjacency,
+                    &mut self.checks,
+                    &mut removed,
+                ) {
+                    constrained = true;
+                }
+            }
+            self.cells[y][x] = cell;
+            self.observe(x, y, &removed);
+            self.trail
+                .extend(removed.into_iter().map(|tile| (x, y, tile)));
+
+            if constrained {
+                for (_, nx, ny) in self.neighbors(x, y) {
+                    if !std::mem::replace(&mut queued[ny * width + nx], true) {
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+        }
+    }
+
+    fn propagate_supports(&mut self) {
+        // Anything dirty was reset, which discards the supports, so recounting
+        // them covers it.
+        self.dirty.clear();
+        let tile_count = self.tileset.tiles.len();
+        let mut supports = match self.supports.take() {
+            Some(supports) => supports,
+            None => self.count_supports(),
+        };
+
+        let adjacency = Arc::clone(&self.adjacency);
+        while let Some((x, y, tile)) = self.pending.pop() {
+            for (edge, nx, ny) in self.neighbors(x, y) {
+                let opposite = edge.opposite() as usize;
+                for &other in &adjacency.compatible[tile][edge as usize] {
+                    self.checks += 1;
+                    let support =
+                        &mut supports[(ny * self.options.width + nx) * tile_count + other];
+                    support[opposite] -= 1;
+                    if support[opposite] == 0 {
+                        self.remove(nx, ny, other);
+                    }
+                }
+            }
+        }
+
+        self.supports = Some(supports);
+    }
+
+    /// Counts the supports of every tile in every cell from scratch, queuing
+    /// any possibility that is already unsupported for removal.
+    fn count_supports(&mut self) -> Vec<[usize; 4]> {
+        let tile_count = self.tileset.tiles.len();
+        let mut supports = vec![[0; 4]; self.options.width * self.options.height * tile_count];
+        let mut unsupported = Vec::new();
+
+        for y in 0..self.options.height {
+            for x in 0..self.options.width {
+                for (edge, nx, ny) in self.neighbors(x, y) {
+                    let neighbor = &self.cells[ny][nx].possibilities;
+                    for tile in 0..tile_count {
+                        let compatible = &self.adjacency.compatible[tile][edge as usize];
+                        let count = if neighbor.len() == tile_count {
+                            compatible.len()
+                        } else if compatible.len() < neighbor.len() {
+                            self.checks += compatible.len();
+                            compatible
+                                .iter()
+                                .filter(|other| neighbor.contains(other))
+                                .count()
+                        } else {
+                            self.checks += neighbor.len();
+                            neighbor
+                                .iter()
+                                .filter(|&&other| self.adjacency.allows(tile, edge, other))
+                                .count()
+                        };
+                        supports[(y * self.options.width + x) * tile_count + tile][edge as usize] =
+                            count;
+
+                        if count == 0 {
+                            unsupported.push((x, y, tile));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending.clear();
+        for (x, y, tile) in unsupported {
+            self.remove(x, y, tile);
+        }
+
+        supports
+    }
+
+    /// Rules `tile` out at `(x, y)`, queuing the removal for propagation and
+    /// logging it on the trail. Returns whether it was still possible.
+    fn remove(&mut self, x: usize, y: usize, tile: usize) -> bool {
+        if !self.cells[y][x].possibilities.remove(&tile) {
+            return false;
+        }
+        self.pending.push((x, y, tile));
+        self.trail.push((x, y, tile));
+        self.observe(x, y, &[tile]);
+        true
+    }
+
+    /// Queues and logs tiles already taken out of `(x, y)`, as
+    /// [`Grid::remove`] does.
+    fn log_removed(&mut self, x: usize, y: usize, removed: Vec<usize>) {
+        self.observe(x, y, &removed);
+        for tile in removed {
+            self.pending.push((x, y, tile));
+            self.trail.push((x, y, tile));
+        }
+    }
+
+    /// Tells the observers that `removed` were just ruled out at `(x, y)`,
+    /// and whether that left the cell with a single tile.
+    fn observe(&self, x: usize, y: usize, removed: &[usize]) {
+        if removed.is_empty() {
+            return;
+        }
+        if let Some(Hook(hook)) = &self.options.on_eliminate {
+            for &tile in removed {
+                hook(x, y, tile);
+            }
+        }
+        for &tile in removed {
+            self.emit(WfcEvent::Eliminated { x, y, tile });
+        }
+
+        let cell = &self.cells[y][x];
+        if let Some(tile) = cell.tile() {
+            if let Some(Hook(hook)) = &self.options.on_collapse {
+                hook(x, y, tile);
+            }
+            self.emit(WfcEvent::Collapsed { x, y, tile });
+        } else if cell.is_contradiction() {
+            self.emit(WfcEvent::Contradiction { x, y });
+        }
+    }
+
+    /// Sends `event` to every subscriber still listening.
+    fn emit(&self, event: WfcEvent) {
+        for subscriber in &self.options.subscribers {
+            // A subscriber that hung up just stops hearing about the run.
+            let _ = subscriber.send(event);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.emit(WfcEvent::Finished);
+    }
+
+    /// The neighbors of `(x, y)` in the grid's [`Topology`], along with the
+    /// edge they share. With [`Options::wrap`], cells on one edge of the grid
+    /// neighbor the cells on the opposite edge. Masked-out cells are never
+    /// neighbors.
+    fn neighbors(&self, x: usize, y: usize) -> Vec<(Edge, usize, usize)> {
+        let width = self.options.width;
+        self.topology
+            .edges(y * width + x)
+            .iter()
+            .map(|&(port, node, _)| (EDGES[port], node % width, node / width))
+            .filter(|&(_, nx, ny)| self.in_mask(nx, ny))
+            .collect()
+    }
+
+    /// A cell with the lowest Shannon entropy over its tiles' weights,
+    /// skipping cells that are already decided or contradicted. Ties are
+    /// broken at random, so generation doesn't sweep from the top-left, or
+    /// by taking the first in row-major order when deterministic.
+    fn next_lowest_entropy(&mut self) -> Option<(usize, usize)> {
+        let weights = self.base_weights();
+        let width = self.options.width;
+        let mut tied = Vec::new();
+        let mut entropy = f64::INFINITY;
+
+        self.entropies.resize(width * self.options.height, (0, 0.0));
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if cell.is_collapsed() || cell.is_contradiction() {
+                    continue;
+                }
+
+                let cached = &mut self.entropies[y * width + x];
+                if cached.0 != cell.possibilities.len() {
+                    *cached = (cell.possibilities.len(), cell.shannon_entropy(&weights));
+                }
+                let e = cached.1;
+                if e < entropy {
+                    entropy = e;
+                    tied.clear();
+                }
+                if e == entropy {
+                    tied.push((x, y));
+                }
+            }
+        }
+
+        if self.options.deterministic {
+            tied.first().copied()
+        } else {
+            tied.choose(&mut self.rng).copied()
+        }
+    }
+
+    /// The next cell to collapse under the grid's [`Heuristic`], if any are
+    /// left open.
+    fn next_cell(&mut self) -> Option<(usize, usize)> {
+        let open = |cell: &Cell| !cell.is_collapsed() && !cell.is_contradiction();
+        match self.options.heuristic {
+            Heuristic::LeastEntropy => self.next_lowest_entropy(),
+            Heuristic::Scanline => self.cells.iter().enumerate().find_map(|(y, row)| {
+                let x = row.iter().position(open)?;
+                Some((x, y))
+            }),
+            Heuristic::Spiral => {
+                if self.spiral.is_empty() {
+                    self.spiral = spiral(self.options.width, self.options.height);
+                }
+                self.spiral
+                    .iter()
+                    .copied()
+                    .find(|&(x, y)| open(&self.cells[y][x]))
+            }
+            Heuristic::Random => {
+                let mut cells = self.cells.iter().enumerate().flat_map(|(y, row)| {
+                    row.iter()
+                        .enumerate()
+                        .filter(|(_, cell)| open(cell))
+                        .map(move |(x, _)| (x, y))
+                });
+                if self.options.deterministic {
+                    return cells.next();
+                }
+                cells.collect::<Vec<_>>().choose(&mut self.rng).copied()
+            }
+        }
+    }
+
+    /// The cell chosen by the [`StartPoint`], unless it's outside the grid or
+    /// already decided.
+    fn start_point(&mut self) -> Option<(usize, usize)> {
+        let (width, height) = (self.options.width, self.options.height);
+        let (x, y) = match self.options.start {
+            StartPoint::Corner => (0, 0),
+            StartPoint::Random if self.options.deterministic => (0, 0),
+            StartPoint::Center => (width / 2, height / 2),
+            StartPoint::Random if width > 0 && height > 0 => {
+                (self.rng.gen_range(0..width), self.rng.gen_range(0..height))
+            }
+            StartPoint::Random => return None,
+            StartPoint::At(x, y) => (x, y),
+        };
+
+        let cell = self.cells.get(y)?.get(x)?;
+        (!cell.is_collapsed() && !cell.is_contradiction()).then_some((x, y))
+    }
+
+    /// Collapses the lowest-entropy cell at random and propagates the result.
+    pub fn step(&mut self) {
+        self.advance();
+    }
+
+    /// Like [`Grid::step`], but describes the step taken, or returns `None`
+    /// once nothing is left to collapse.
+    fn advance(&mut self) -> Option<Step> {
+        let start = if self.started {
+            None
+        } else {
+            self.start_point()
+        };
+        let Some((x, y)) = start.or_else(|| self.next_cell()) else {
+            self.finish();
+            return None;
+        };
+        self.started = true;
+        self.steps += 1;
+
+        self.trail.clear();
+        let tile = self.collapse_at(x, y);
+        self.record(x, y, tile);
+        self.recover();
+        Some(Step {
+            x,
+            y,
+            tile,
+            collapsed: self.collapsed_fraction(),
+            contradicted: self.check_contradiction().is_err(),
+        })
+    }
+
+    /// Collapses `(x, y)` to a weighted random candidate and propagates,
+    /// returning the candidate chosen.
+    fn collapse_at(&mut self, x: usize, y: usize) -> Option<usize> {
+        let weights = self.weights(x, y);
+        let removed = if self.options.deterministic {
+            self.cells[y][x].collapse_first(&weights)
+        } else {
+            self.cells[y][x].collapse(&mut self.rng, &weights)
+        };
+        let tile = self.cells[y][x].possibilities.first().copied();
+        self.log_removed(x, y, removed);
+        self.propagate();
+        self.orient_collapsed();
+        self.enforce_constraints(x, y);
+        tile
+    }
+
+    /// Applies the global constraints after the cell at `(x, y)` is decided,
+    /// re-propagating whatever they rule out until they hold. One that can
+    /// no longer be met empties a cell, so recovery treats it like any other
+    /// contradiction.
+    fn enforce_constraints(&mut self, x: usize, y: usize) {
+        loop {
+            let banned = self.enforce_counts(x, y)
+                | self.enforce_connectivity(x, y)
+                | self.enforce_exclusive()
+                | self.enforce_distances()
+                | self.enforce_regions();
+            if !banned || self.check_contradiction().is_err() {
+                return;
+            }
+            self.propagate();
+            self.orient_collapsed();
+        }
+    }
+
+    /// Holds every tile to its `min_count` and `max_count`, and the tiles of
+    /// every tag in [`Options::tag_counts`] to theirs together, returning
+    /// whether a group that reached its maximum was ruled out of any
+    /// undecided cell. Passing a maximum empties the extra cell; too few cells
+    /// left to reach a minimum empties `(x, y)`.
+    fn enforce_counts(&mut self, x: usize, y: usize) -> bool {
+        let mut limited: Vec<(Vec<usize>, Option<usize>, Option<usize>)> = self
+            .tileset
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile.min_count.is_some() || tile.max_count.is_some())
+            .map(|(index, tile)| (vec![index], tile.min_count, tile.max_count))
+            .collect();
+        limited.extend(self.options.tag_counts.iter().cloned());
+        let mut banned = false;
+        for (tiles, min, max) in &limited {
+            let (mut collapsed, mut open) = (Vec::new(), Vec::new());
+            for (cy, row) in self.cells.iter().enumerate() {
+                for (cx, cell) in row.iter().enumerate() {
+                    if !tiles.iter().any(|tile| cell.possibilities.contains(tile)) {
+                        continue;
+                    }
+                    if cell.is_collapsed() {
+                        collapsed.push((cx, cy));
+                    } else {
+                        open.push((cx, cy));
+                    }
+                }
+            }
+
+            if let Some(max) = *max {
+                if let Some(&(cx, cy)) = collapsed.get(max) {
+                    for &tile in tiles {
+                        self.remove(cx, cy, tile);
+                    }
+                    return false;
+                }
+                if collapsed.len() == max && !open.is_empty() {
+                    for (cx, cy) in open.drain(..) {
+                        for &tile in tiles {
+                            self.remove(cx, cy, tile);
+                        }
+                    }
+                    banned = true;
+                }
+            }
+            if min.is_some_and(|min| collapsed.len() + open.len() < min) {
+                self.empty(x, y);
+                return false;
+            }
+        }
+
+        banned
+    }
+
+    /// Rules out, everywhere, the tiles of every tag that shares a
+    /// [`Tileset::exclusive`] set with the tag of some collapsed cell,
+    /// returning whether anything was ruled out. Collapsed cells with a ruled
+    /// out tag are emptied too, so two tags that turn up at once contradict.
+    fn enforce_exclusive(&mut self) -> bool {
+        let tiles = &self.tileset.tiles;
+        let mut unfit = Vec::new();
+        for set in &self.tileset.exclusive {
+            let chosen = self.cells.iter().flatten().find_map(|cell| {
+                let tile = cell.possibilities.first().filter(|_| cell.is_collapsed())?;
+                set.iter().find(|tag| tiles[*tile].has_tag(tag))
+            });
+            let Some(chosen) = chosen else {
+                continue;
+            };
+            for (y, row) in self.cells.iter().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    for &tile in &cell.possibilities {
+                        let tile_config = &tiles[tile];
+                        if !tile_config.has_tag(chosen)
+                            && set.iter().any(|tag| tile_config.has_tag(tag))
+                        {
+                            unfit.push((x, y, tile));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut banned = false;
+        for (x, y, tile) in unfit {
+            banned |= self.remove(x, y, tile);
+        }
+        banned
+    }
+
+    /// Holds the grid to every [`Options::distances`] rule, returning whether
+    /// any tagged tile was ruled out. A cell that must have a tag rules the
+    /// tiles it must be kept apart from out of every cell too near it, and a
+    /// tag that needs another nearby is ruled out of every cell with none of
+    /// the other left in reach.
+    fn enforce_distances(&mut self) -> bool {
+        let (width, height) = (self.options.width, self.options.height);
+        let tiles = &self.tileset.tiles;
+        let mut unfit = Vec::new();
+        let mut rule_out = |x: usize, y: usize, tag: &str, cells: &[Vec<Cell>]| {
+            unfit.extend(
+                cells[y][x]
+                    .possibilities
+                    .iter()
+                    .filter(|&&tile| tiles[tile].has_tag(tag))
+                    .map(|&tile| (x, y, tile)),
+            );
+        };
+
+        for rule in &self.options.distances {
+            let (tag, other, distance, apart) = match rule {
+                DistanceRule::Near {
+                    tag,
+                    other,
+                    distance,
+                } => (tag, other, *distance, false),
+                DistanceRule::Apart {
+                    tag,
+                    other,
+                    distance,
+                } => (tag, other, *distance, true),
+            };
+            let may = |x: usize, y: usize, tag: &str| {
+                self.cells[y][x]
+                    .possibilities
+                    .iter()
+                    .any(|&tile| tiles[tile].has_tag(tag))
+            };
+            let must = |x: usize, y: usize, tag: &str| {
+                let cell = &self.cells[y][x];
+                !cell.is_contradiction()
+                    && cell
+                        .possibilities
+                        .iter()
+                        .all(|&tile| tiles[tile].has_tag(tag))
+            };
+
+            for y in 0..height {
+                for x in 0..width {
+                    let around = within(x, y, distance, width, height);
+                    if apart {
+                        // Either side of the rule keeps the other away.
+                        for (own, far) in [(tag, other), (other, tag)] {
+                            if must(x, y, own) {
+                                for &(nx, ny) in around.iter().filter(|&&cell| cell != (x, y)) {
+                                    rule_out(nx, ny, far, &self.cells);
+                                }
+                            }
+                        }
+                    } else if may(x, y, tag) && !around.iter().any(|&(nx, ny)| may(nx, ny, other)) {
+                        rule_out(x, y, tag, &self.cells);
+                    }
+                }
+            }
+        }
+
+        let mut banned = false;
+        for (x, y, tile) in unfit {
+            banned |= self.remove(x, y, tile);
+        }
+        banned
+    }
+
+    /// Rules each [`Options::min_region`] tag out of every group of cells
+    /// that may have it but could never grow to the minimum, returning
+    /// whether anything was ruled out. A cell that must have the tag in such
+    /// a group is left empty.
+    fn enforce_regions(&mut self) -> bool {
+        let (width, height) = (self.options.width, self.options.height);
+        let tiles = &self.tileset.tiles;
+        let mut unfit = Vec::new();
+
+        for (tag, min) in &self.options.min_region {
+            let may: Vec<bool> = self
+                .cells
+                .iter()
+                .flatten()
+                .map(|cell| {
+                    cell.possibilities
+                        .iter()
+                        .any(|&tile| tiles[tile].has_tag(tag))
+                })
+                .collect();
+            let mut seen = vec![false; width * height];
+            for start in 0..width * height {
+                if !may[start] || std::mem::replace(&mut seen[start], true) {
+                    continue;
+                }
+                let mut region = vec![start];
+                let mut queue = VecDeque::from([start]);
+                while let Some(i) = queue.pop_front() {
+                    for (_, nx, ny) in self.neighbors(i % width, i / width) {
+                        let j = ny * width + nx;
+                        if may[j] && !std::mem::replace(&mut seen[j], true) {
+                            region.push(j);
+                            queue.push_back(j);
+                        }
+                    }
+                }
+                if region.len() >= *min {
+                    continue;
+                }
+                for i in region {
+                    let (x, y) = (i % width, i / width);
+                    unfit.extend(
+                        self.cells[y][x]
+                            .possibilities
+                            .iter()
+                            .filter(|&&tile| tiles[tile].has_tag(tag))
+                            .map(|&tile| (x, y, tile)),
+                    );
+                }
+            }
+        }
+
+        let mut banned = false;
+        for (x, y, tile) in unfit {
+            banned |= self.remove(x, y, tile);
+        }
+        banned
+    }
+
+    /// Keeps the cells of each [`Options::connected`] tag in one region,
+    /// returning whether any tagged tile was ruled out. Cells that must be
+    /// tagged need a path between them through cells that still may be;
+    /// without one, `(x, y)` is emptied. Once some cell must be tagged, the
+    /// tag is ruled out of every cell that could never join it.
+    fn enforce_connectivity(&mut self, x: usize, y: usize) -> bool {
+        let (width, height) = (self.options.width, self.options.height);
+        let mut banned = false;
+
+        for tag in self.options.connected.clone() {
+            let tagged = |tile: &usize| self.tileset.tiles[*tile].has_tag(&tag);
+            let may: Vec<bool> = self
+                .cells
+                .iter()
+                .flatten()
+                .map(|cell| cell.possibilities.iter().any(tagged))
+                .collect();
+            let must: Vec<usize> = self
+                .cells
+                .iter()
+                .flatten()
+                .enumerate()
+                .filter(|(_, cell)| {
+                    !cell.is_contradiction() && cell.possibilities.iter().all(tagged)
+                })
+                .map(|(i, _)| i)
+                .collect();
+            let Some(&start) = must.first() else {
+                continue;
+            };
+
+            let mut reached = vec![false; width * height];
+            let mut queue = VecDeque::from([start]);
+            reached[start] = true;
+            while let Some(i) = queue.pop_front() {
+                for (_, nx, ny) in self.neighbors(i % width, i / width) {
+                    let j = ny * width + nx;
+                    if may[j] && !std::mem::replace(&mut reached[j], true) {
+                        queue.push_back(j);
+                    }
+                }
+            }
+
+            if must.iter().any(|&i| !reached[i]) {
+                self.empty(x, y);
+                return false;
+            }
+            for i in (0..width * height).filter(|&i| may[i] && !reached[i]) {
+                let (cx, cy) = (i % width, i / width);
+                let unfit: Vec<usize> = self.cells[cy][cx]
+                    .possibilities
+                    .iter()
+                    .copied()
+                    .filter(|tile| tagged(tile))
+                    .collect();
+                for tile in unfit {
+                    self.remove(cx, cy, tile);
+                }
+                banned = true;
+            }
+        }
+
+        banned
+    }
+
+    /// Rules out every tile left at `(x, y)`, leaving a contradiction.
+    fn empty(&mut self, x: usize, y: usize) {
+        for tile in self.cells[y][x].possibilities.clone() {
+            self.remove(x, y, tile);
+        }
+    }
+
+    /// How well each rotation of `tile` fits the collapsed neighbors of
+    /// `(x, y)`, or all 0 if no tile is rotatable.
+    fn turn_strengths(&self, x: usize, y: usize, tile: usize) -> [f64; 4] {
+        let mut strengths = [0.0; 4];
+        if self.adjacency.oriented.is_none() {
+            return strengths;
+        }
+
+        let turns = if self.tileset.tiles[tile].rotatable {
+            4
+        } else {
+            1
+        };
+        for (turn, strength) in strengths.iter_mut().enumerate().take(turns) {
+            *strength = self
+                .neighbors(x, y)
+                .into_iter()
+                .filter_map(|(edge, nx, ny)| {
+                    let neighbor = &self.cells[ny][nx];
+                    let other = *neighbor
+                        .possibilities
+                        .first()
+                        .filter(|_| neighbor.is_collapsed())?;
+                    let other_turn = self.turns[ny][nx]?;
+                    Some(self.adjacency.oriented_strength(
+                        (tile, turn as u8),
+                        edge,
+                        (other, other_turn),
+                    ))
+                })
+                .product();
+        }
+
+        strengths
+    }
+
+    /// Whether every collapsed cell on the right edge connects to the cell
+    /// across from it on the left edge, and likewise for the bottom and top,
+    /// so copies of the grid can be laid side by side. Cells that haven't
+    /// collapsed never connect.
+    pub fn is_seamless(&self) -> bool {
+        let (width, height) = (self.options.width, self.options.height);
+        if width == 0 || height == 0 {
+            return true;
+        }
+        let seams = (0..height)
+            .map(|y| ((width - 1, y), Edge::Right, (0, y)))
+            .chain((0..width).map(|x| ((x, height - 1), Edge::Bottom, (x, 0))));
+        seams
+            .filter(|&((x, y), _, (nx, ny))| self.in_mask(x, y) && self.in_mask(nx, ny))
+            .all(|(cell, edge, other)| self.connects(cell, edge, other))
+    }
+
+    /// Whether the collapsed cells `a` and `b`, with `b` on `edge` of `a`,
+    /// fit together in the rotations they were given.
+    fn connects(&self, a: (usize, usize), edge: Edge, b: (usize, usize)) -> bool {
+        let tile = |(x, y): (usize, usize)| {
+            let cell = &self.cells[y][x];
+            cell.possibilities
+                .first()
+                .copied()
+                .filter(|_| cell.is_collapsed())
+        };
+        let (Some(first), Some(second)) = (tile(a), tile(b)) else {
+            return false;
+        };
+        if self.adjacency.oriented.is_none() {
+            return self.adjacency.allows(first, edge, second);
+        }
+        let turn = |(x, y): (usize, usize)| self.turns[y][x].unwrap_or(0);
+        self.adjacency
+            .oriented_strength((first, turn(a)), edge, (second, turn(b)))
+            > 0.0
+    }
+
+    /// Picks a rotation for every newly collapsed cell that fits its collapsed
+    /// neighbors, removing each neighboring possibility that then fits its
+    /// own collapsed neighbors in no rotation, and re-propagates until every
+    /// collapsed cell is turned. A cell whose tile fits in no rotation is left
+    /// as a contradiction.
+    fn orient_collapsed(&mut self) {
+        if self.adjacency.oriented.is_none() {
+            return;
+        }
+
+        loop {
+            self.forget_turns();
+            let mut oriented = false;
+            for y in 0..self.options.height {
+                for x in 0..self.options.width {
+                    if self.cells[y][x].is_collapsed() && self.turns[y][x].is_none() {
+                        self.orient(x, y);
+                        oriented = true;
+                    }
+                }
+            }
+
+            if !oriented {
+                break;
+            }
+            self.propagate();
+        }
+    }
+
+    /// Drops the turn of every cell that is no longer collapsed.
+    fn forget_turns(&mut self) {
+        for (row, turns) in self.cells.iter().zip(&mut self.turns) {
+            for (cell, turn) in row.iter().zip(turns) {
+                if !cell.is_collapsed() {
+                    *turn = None;
+                }
+            }
+        }
+    }
+
+    fn orient(&mut self, x: usize, y: usize) {
+        let tile = self.cells[y][x].possibilities.first().copied().unwrap();
+        let strengths = self.turn_strengths(x, y, tile);
+        let turn = if self.options.deterministic {
+            (0..4).find(|&turn| strengths[turn as usize] > 0.0)
+        } else if self.tileset.tiles[tile].rotatable {
+            [0, 1, 2, 3]
+                .choose_weighted(&mut self.rng, |&turn| strengths[turn as usize])
+                .ok()
+                .copied()
+        } else {
+            (strengths[0] > 0.0).then_some(0)
+        };
+        let Some(turn) = turn else {
+            self.remove(x, y, tile);
+            return;
+        };
+
+        self.turns[y][x] = Some(turn);
+        for (_, nx, ny) in self.neighbors(x, y) {
+            if self.cells[ny][nx].is_collapsed() {
+                continue;
+            }
+
+            let unfit: Vec<usize> = self.cells[ny][nx]
+                .possibilities
+                .iter()
+                .copied()
+                .filter(|&other| {
+                    self.turn_strengths(nx, ny, other)
+                        .iter()
+                        .all(|&strength| strength == 0.0)
+                })
+                .collect();
+            for other in unfit {
+                self.remove(nx, ny, other);
+            }
+        }
+    }
+
+    /// How many quarter turns clockwise the tile at `(x, y)` is from its image,
+    /// whether from its symmetry or from being turned on the fly, or `None`
+    /// if the cell hasn't collapsed.
+    pub fn rotation_at(&self, x: usize, y: usize) -> Option<u8> {
+        let cell = self.cells.get(y)?.get(x)?;
+        let tile = cell.possibilities.first().filter(|_| cell.is_collapsed())?;
+        let turn = self.turns[y][x].unwrap_or(0);
+        Some((self.tileset.tiles[*tile].rotation + turn) % 4)
+    }
+
+    /// The base weight of `tile` for this run, after any override.
+    fn weight(&self, tile: usize) -> f64 {
+        self.options
+            .weight_overrides
+            .get(&tile)
+            .copied()
+            .unwrap_or_else(|| self.tileset.tiles[tile].weight())
+    }
+
+    fn base_weights(&self) -> Vec<f64> {
+        (0..self.tileset.tiles.len())
+            .map(|tile| self.weight(tile))
+            .collect()
+    }
+
+    /// The Shannon entropy of the cell at `(x, y)` over its base weights.
+    fn entropy_at(&self, x: usize, y: usize) -> f64 {
+        self.cells[y][x].shannon_entropy(&self.base_weights())
+    }
+
+    /// The weight of every tile at `(x, y)`, after applying the bias field,
+    /// the weight maps, the noise, the pair weights, and the match strength
+    /// against every collapsed neighbor.
+    fn weights(&self, x: usize, y: usize) -> Vec<f64> {
+        let mut weights = self.base_weights();
+        if let Some(Bias(field)) = &self.options.bias {
+            for (tile, multiplier) in field(x, y) {
+                if let Some(weight) = weights.get_mut(tile) {
+                    *weight *= multiplier;
+                }
+            }
+        }
+        for (&tile, map) in &self.options.weight_maps {
+            // A resized grid can outgrow its maps.
+            weights[tile] *= map.get(y).and_then(|row| row.get(x)).unwrap_or(&1.0);
+        }
+        for (bias, perlin) in &self.options.noise {
+            let n = perlin.at(x as f64 * bias.frequency, y as f64 * bias.frequency);
+            let multiplier = bias.multiplier(n);
+            for (tile, weight) in weights.iter_mut().enumerate() {
+                if self.tileset.tiles[tile].has_tag(&bias.tag) {
+                    *weight *= multiplier;
+                }
+            }
+        }
+        if !self.options.pair_weights.is_empty() {
+            for (_, nx, ny) in self.neighbors(x, y) {
+                let neighbor = &self.cells[ny][nx];
+                if let (true, Some(&other)) =
+                    (neighbor.is_collapsed(), neighbor.possibilities.first())
+                {
+                    for (tile, weight) in weights.iter_mut().enumerate() {
+                        if let Some(multiplier) = self.options.pair_weights.get(&(tile, other)) {
+                            *weight *= multiplier;
+                        }
+                    }
+                }
+            }
+        }
+        if self.adjacency.oriented.is_some() {
+            for (tile, weight) in weights.iter_mut().enumerate() {
+                *weight *= self
+                    .turn_strengths(x, y, tile)
+                    .into_iter()
+                    .fold(0.0, f64::max);
+            }
+            return weights;
+        }
+        for (edge, nx, ny) in self.neighbors(x, y) {
+            let neighbor = &self.cells[ny][nx];
+            if let (true, Some(&other)) = (neighbor.is_collapsed(), neighbor.possibilities.first())
+            {
+                for (tile, weight) in weights.iter_mut().enumerate() {
+                    *weight *= self.adjacency.strength(tile, edge, other);
+                }
+            }
+        }
+
+        weights
+    }
+
+    /// Applies the grid's [`RecoveryMode`] to any contradiction left behind
+    /// by the last step. Recovering adds possibilities back, which the undo
+    /// history can't express, so it also clears the history.
+    fn recover(&mut self) {
+        let mut attempts = 0;
+        while let Err(WfcError::Contradiction { x, y }) = self.check_contradiction() {
+            match self.options.recovery {
+                RecoveryMode::None => return,
+                RecoveryMode::Backtrack => return self.backtrack(),
+                RecoveryMode::Backjump => return self.backjump(),
+                RecoveryMode::LocalReset if attempts < MAX_LOCAL_RESETS => {
+                    self.reset_around(x, y, self.options.reset_radius + attempts);
+                    attempts += 1;
+                }
+                RecoveryMode::Restart | RecoveryMode::LocalReset => {
+                    if self.restarts < MAX_RESTARTS {
+                        self.restart();
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Resets every cell within `radius` of `(x, y)` to every tile, then
+    /// re-propagates so they're narrowed by the cells around them again.
+    fn reset_around(&mut self, x: usize, y: usize, radius: usize) {
+        let rows = y.saturating_sub(radius)..(y + radius + 1).min(self.options.height);
+        let columns = x.saturating_sub(radius)..(x + radius + 1).min(self.options.width);
+        self.reset_region(columns, rows);
+    }
+
+    /// Resets every cell in `columns` of `rows` to every tile, then
+    /// re-propagates so they're narrowed by the cells around them again.
+    fn reset_region(&mut self, columns: Range<usize>, rows: Range<usize>) {
+        for (y, row) in self
+            .cells
+            .iter_mut()
+            .enumerate()
+            .take(rows.end)
+            .skip(rows.start)
+        {
+            for (x, cell) in row
+                .iter_mut()
+                .enumerate()
+                .take(columns.end)
+                .skip(columns.start)
+            {
+                *cell = Cell::new(self.tileset);
+                self.dirty.push((x, y));
+            }
+        }
+
+        self.history.clear();
+        self.undone.clear();
+        self.supports = None;
+        self.entropies.clear();
+        self.forget_turns();
+        self.apply_mask();
+        self.apply_borders();
+        self.apply_ground();
+        self.apply_allowed();
+        self.apply_pins();
+        self.propagate();
+    }
+
+    fn restart(&mut self) {
+        for cell in self.cells.iter_mut().flatten() {
+            *cell = Cell::new(self.tileset);
+        }
+
+        self.history.clear();
+        self.undone.clear();
+        self.supports = None;
+        self.entropies.clear();
+        self.forget_turns();
+        self.pending.clear();
+        self.dirty.clear();
+        self.restarts += 1;
+        self.started = false;
+        self.apply_mask();
+        self.apply_borders();
+        self.apply_ground();
+        self.apply_allowed();
+        self.apply_pins();
+        self.propagate();
+    }
+
+    /// Collapses the cell at `(x, y)` to `tile` for good: like
+    /// [`Grid::collapse_cell`], but it can't be undone and recovery won't
+    /// reset it.
+    fn pin(&mut self, x: usize, y: usize, tile: usize) -> Result<(), WfcError> {
+        self.collapse_cell(x, y, tile)?;
+        self.pins.push((x, y, tile));
+        self.history.clear();
+        self.undone.clear();
+        Ok(())
+    }
+
+    /// Empties every masked-out cell. They aren't anyone's neighbor, so the
+    /// removals aren't propagated or logged.
+    fn apply_mask(&mut self) {
+        let Some(mask) = &self.options.mask else {
+            return;
+        };
+        for (row, mask) in self.cells.iter_mut().zip(mask) {
+            for (cell, &inside) in row.iter_mut().zip(mask) {
+                if !inside {
+                    cell.possibilities.clear();
+                }
+            }
+        }
+    }
+
+    /// Whether `(x, y)` takes part in generation under [`Options::mask`].
+    fn in_mask(&self, x: usize, y: usize) -> bool {
+        self.options.mask.as_ref().is_none_or(|mask| mask[y][x])
+    }
+
+    /// Rules out every tile along each bordered edge whose socket there
+    /// doesn't match the border, queuing the removals for the next
+    /// propagation.
+    fn apply_borders(&mut self) {
+        let (width, height) = (self.options.width, self.options.height);
+        if self.options.wrap || width == 0 || height == 0 {
+            return;
+        }
+
+        for (edge, socket) in self.options.borders.clone() {
+            let mut cells: Vec<(usize, usize)> = match edge {
+                Edge::Top => (0..width).map(|x| (x, 0)).collect(),
+                Edge::Right => (0..height).map(|y| (width - 1, y)).collect(),
+                Edge::Bottom => (0..width).map(|x| (x, height - 1)).collect(),
+                Edge::Left => (0..height).map(|y| (0, y)).collect(),
+            };
+            cells.retain(|&(x, y)| self.in_mask(x, y));
+            for (x, y) in cells {
+                let unfit: Vec<usize> = self.cells[y][x]
+                    .possibilities
+                    .iter()
+                    .copied()
+                    .filter(|&tile| {
+                        let tile = &self.tileset.tiles[tile];
+                        let own = tile.socket(edge);
+                        !tile.transparent && !own.is_wildcard() && own != &socket
+                    })
+                    .collect();
+                for tile in unfit {
+                    self.remove(x, y, tile);
+                }
+            }
+        }
+    }
+
+    /// Rules the [`Options::ground`] tiles out of every cell but those in the
+    /// bottom row, and every other tile out of the bottom row, queuing the
+    /// removals for the next propagation.
+    fn apply_ground(&mut self) {
+        let Some(ground) = self.options.ground.clone() else {
+            return;
+        };
+
+        let bottom = self.options.height.saturating_sub(1);
+        for y in 0..self.options.height {
+            for x in 0..self.options.width {
+                if !self.in_mask(x, y) {
+                    continue;
+                }
+                let unfit: Vec<usize> = self.cells[y][x]
+                    .possibilities
+                    .iter()
+                    .copied()
+                    .filter(|&tile| self.tileset.tiles[tile].is_named(&ground) != (y == bottom))
+                    .collect();
+                for tile in unfit {
+                    self.remove(x, y, tile);
+                }
+            }
+        }
+    }
+
+    /// Rules out every tile that [`Options::allowed`] leaves out of its cell,
+    /// queuing the removals for the next propagation.
+    fn apply_allowed(&mut self) {
+        let options = Arc::clone(&self.options);
+        let Some(allowed) = &options.allowed else {
+            return;
+        };
+
+        // A resized grid can differ from the listing.
+        for (y, row) in allowed.iter().enumerate().take(self.options.height) {
+            for (x, tiles) in row.iter().enumerate().take(self.options.width) {
+                let unfit: Vec<usize> = self.cells[y][x]
+                    .possibilities
+                    .iter()
+                    .copied()
+                    .filter(|tile| !tiles.contains(tile))
+                    .collect();
+                for tile in unfit {
+                    self.remove(x, y, tile);
+                }
+            }
+        }
+    }
+
+    /// Collapses every pinned cell again, queuing the removals for the next
+    /// propagation.
+    fn apply_pins(&mut self) {
+        for (x, y, tile) in self.pins.clone() {
+            let removed = self.cells[y][x].collapse_to(tile);
+            self.log_removed(x, y, removed);
+        }
+    }
+
+    /// Collapses the cell at `(x, y)` to the tile at `tile_index` and propagates
+    /// the result. Unlike [`Grid::step`], the choice of cell and tile is the
+    /// caller's, so this can be interleaved with automatic steps.
+    pub fn collapse_cell(&mut self, x: usize, y: usize, tile_index: usize) -> Result<(), WfcError> {
+        if tile_index >= self.tileset.tiles.len() {
+            return Err(WfcError::UnknownTile(tile_index));
+        }
+        let cell = self
+            .cells
+            .get(y)
+            .and_then(|row| row.get(x))
+            .ok_or(WfcError::OutOfBounds { x, y })?;
+        if !cell.possibilities.contains(&tile_index) {
+            return Err(WfcError::Contradiction { x, y });
+        }
+
+        self.trail.clear();
+        let removed = self.cells[y][x].collapse_to(tile_index);
+        self.log_removed(x, y, removed);
+        self.propagate();
+        self.orient_collapsed();
+        self.enforce_constraints(x, y);
+        self.record(x, y, Some(tile_index));
+        self.check_contradiction()
+    }
+
+    /// Rules the tile at `tile_index` out of the cell at `(x, y)` and
+    /// propagates the result, recording it as a decision that can be undone.
+    /// Banning a tile that's already ruled out does nothing. Fails with the
+    /// contradiction if the ban leaves some cell without a tile.
+    pub fn ban(&mut self, x: usize, y: usize, tile_index: usize) -> Result<(), WfcError> {
+        if tile_index >= self.tileset.tiles.len() {
+            return Err(WfcError::UnknownTile(tile_index));
+        }
+        let cell = self
+            .cells
+            .get(y)
+            .and_then(|row| row.get(x))
+            .ok_or(WfcError::OutOfBounds { x, y })?;
+        if !cell.possibilities.contains(&tile_index) {
+            return Ok(());
+        }
+
+        self.trail.clear();
+        self.remove(x, y, tile_index);
+        self.propagate();
+        self.orient_collapsed();
+        if self.cells[y][x].is_collapsed() {
+            self.enforce_constraints(x, y);
+        }
+        self.record(x, y, None);
+        self.check_contradiction()
+    }
+
+    /// Pushes the decision at `(x, y)` onto the undo stack, taking every
+    /// possibility removed since it began from the trail so it can be restored.
+    fn record(&mut self, x: usize, y: usize, tile: Option<usize>) {
+        let removed = std::mem::take(&mut self.trail);
+        self.history.push(Decision {
+            x,
+            y,
+            tile,
+            removed,
+        });
+        self.undone.clear();
+    }
+
+    /// Undoes decisions until the grid is free of contradictions, ruling out
+    /// the tile each one chose and enforcing the global constraints again,
+    /// since a required tile may have been ruled out of its last cell.
+    /// Ruling a tile out is a consequence of every decision before it, so its
+    /// removals are folded into the previous decision and restored if that
+    /// one is rewound too.
+    fn backtrack(&mut self) {
+        let mut depth = 0;
+        while self.check_contradiction().is_err() {
+            if self.options.max_backtrack_depth == Some(depth) {
+                return;
+            }
+            let Some(&Decision { x, y, tile, .. }) = self.history.last() else {
+                return;
+            };
+            self.undo();
+            self.undone.clear();
+            depth += 1;
+
+            let Some(tile) = tile else {
+                continue;
+            };
+            self.trail.clear();
+            self.remove(x, y, tile);
+            self.propagate();
+            self.orient_collapsed();
+            self.enforce_constraints(x, y);
+
+            let removed = std::mem::take(&mut self.trail);
+            if let Some(previous) = self.history.last_mut() {
+                previous.removed.extend(removed);
+            }
+        }
+    }
+
+    /// Jumps back over decisions until the grid is free of contradictions.
+    /// The decisions that removed possibilities from a contradicted cell are
+    /// its conflict set. The most recent one is rewound along with everything
+    /// after it, and its tile ruled out. That rests on the rest of the
+    /// conflict set, so the removals are folded into the latest decision left
+    /// in it, and the set carries over to the next contradiction.
+    fn backjump(&mut self) {
+        let mut depth = 0;
+        let mut conflicts = BTreeSet::new();
+        while let Err(WfcError::Contradiction { x, y }) = self.check_contradiction() {
+            if self.options.max_backtrack_depth == Some(depth) {
+                return;
+            }
+            conflicts.extend(self.culprits(x, y));
+            let Some(culprit) = conflicts.pop_last() else {
+                return;
+            };
+            let Decision { x, y, tile, .. } = self.history[culprit];
+            while self.history.len() > culprit {
+                self.undo();
+            }
+            self.undone.clear();
+            depth += 1;
+
+            let Some(tile) = tile else {
+                continue;
+            };
+            conflicts.extend(self.culprits(x, y));
+            self.trail.clear();
+            self.remove(x, y, tile);
+            self.propagate();
+            self.orient_collapsed();
+            self.enforce_constraints(x, y);
+
+            let removed = std::mem::take(&mut self.trail);
+            if let Some(&latest) = conflicts.last() {
+                self.history[latest].removed.extend(removed);
+            }
+        }
+    }
+
+    /// Which decisions in the history removed a possibility from `(x, y)`.
+    fn culprits(&self, x: usize, y: usize) -> Vec<usize> {
+        self.history
+            .iter()
+            .enumerate()
+            .filter(|(_, decision)| {
+                decision
+                    .removed
+                    .iter()
+                    .any(|&(rx, ry, _)| (rx, ry) == (x, y))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Reverts the most recent collapse decision, returning `false` if there
+    /// was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(decision) = self.history.pop() else {
+            return false;
+        };
+
+        for &(x, y, tile) in &decision.removed {
+            self.cells[y][x].possibilities.insert(tile);
+        }
+        self.supports = None;
+        self.entropies.clear();
+        self.forget_turns();
+        self.finished = false;
+        self.undone.push(decision);
+        true
+    }
+
+    /// Reapplies the most recently undone decision, returning `false` if there
+    /// was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(decision) = self.undone.pop() else {
+            return false;
+        };
+
+        for &(x, y, tile) in &decision.removed {
+            self.cells[y][x].possibilities.remove(&tile);
+        }
+        self.supports = None;
+        self.entropies.clear();
+        // Redoing the last decision of a run leaves nothing to collapse.
+        self.finished = self
+            .cells
+            .iter()
+            .flatten()
+            .all(|cell| cell.is_collapsed() || cell.is_contradiction());
+        self.history.push(decision);
+        true
+    }
+
+    /// Fails with the first cell, row by row, that no tile fits.
+    pub fn check_contradiction(&self) -> Result<(), WfcError> {
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if cell.is_contradiction() && self.in_mask(x, y) {
+                    return Err(WfcError::Contradiction { x, y });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The tile index of every cell, row by row, or `None` if any cell has
+    /// not collapsed to a single tile. Masked-out cells never collapse; see
+    /// [`Grid::to_masked_indices`].
+    pub fn to_indices(&self) -> Option<Vec<Vec<usize>>> {
+        self.cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell.possibilities.len() {
+                        1 => cell.possibilities.first().copied(),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The terrain at every corner of every cell, one row more and one column
+    /// more than the grid, or `None` if any corner has no collapsed cell with
+    /// [`TileConfig::corners`] touching it.
+    pub fn to_corners(&self) -> Option<Vec<Vec<u32>>> {
+        let (width, height) = (self.options.width, self.options.height);
+        (0..=height)
+            .map(|vy| {
+                (0..=width)
+                    .map(|vx| {
+                        // The cells to the bottom right, bottom left, top
+                        // left, and top right, and which of their corners
+                        // this is.
+                        [(0, 0, 0), (1, 0, 1), (1, 1, 2), (0, 1, 3)]
+                            .into_iter()
+                            .find_map(|(dx, dy, corner)| {
+                                let (x, y) = (vx.checked_sub(dx)?, vy.checked_sub(dy)?);
+                                self.corners_at(x, y).map(|corners| corners[corner])
+                            })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The corners of the tile at `(x, y)`, as turned there, once it has
+    /// collapsed.
+    fn corners_at(&self, x: usize, y: usize) -> Option<[u32; 4]> {
+        let cell = self.cells.get(y)?.get(x)?;
+        let tile = cell.possibilities.first().filter(|_| cell.is_collapsed())?;
+        let mut corners = self.tileset.tiles[*tile].corners()?;
+        corners.rotate_right(self.turns[y][x].unwrap_or(0) as usize);
+        Some(corners)
+    }
+
+    /// The cell at `(x, y)`, or `None` if it's off the grid.
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.cells.get(y)?.get(x)
+    }
+
+    /// Every cell as `(x, y, cell)`, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &Cell)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, cell)| (x, y, cell)))
+    }
+
+    /// Like [`Grid::to_indices`], but with `None` for every masked-out cell.
+    pub fn to_masked_indices(&self) -> Option<Vec<Vec<Option<usize>>>> {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(x, cell)| match self.in_mask(x, y) {
+                        false => Some(None),
+                        true => cell
+                            .possibilities
+                            .first()
+                            .copied()
+                            .filter(|_| cell.is_collapsed())
+                            .map(Some),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Every cell's tile as it should be drawn, row by row, with `None` for
+    /// masked-out cells, or `None` if any other cell hasn't collapsed to a
+    /// single tile.
+    pub fn result(&self) -> Option<GridResult<'t, T>> {
+        let cells = self
+            .to_masked_indices()?
+            .into_iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.into_iter()
+                    .enumerate()
+                    .map(|(x, tile)| {
+                        let tile = tile?;
+                        let config = &self.tileset.tiles[tile];
+                        Some(PlacedTile {
+                            tile,
+                            image: &config.image,
+                            rotation: self.rotation_at(x, y)?,
+                            mirrored: config.mirrored,
+                            data: &config.data,
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Some(GridResult {
+            width: self.options.width,
+            height: self.options.height,
+            cells,
+        })
+    }
+
+    pub fn rng_state(&self) -> RngState {
+        RngState {
+            seed: self.rng.get_seed(),
+            stream: self.rng.get_stream(),
+            word_pos: self.rng.get_word_pos(),
+        }
+    }
+
+    pub fn set_rng_state(&mut self, state: &RngState) {
+        self.rng = ChaCha8Rng::from_seed(state.seed);
+        self.rng.set_stream(state.stream);
+        self.rng.set_word_pos(state.word_pos);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// A copy of the grid as it stands, to try collapses on and throw away
+    /// if they lead nowhere. The fork shares the tileset, adjacency tables,
+    /// and settings with the grid, copying only the state a run changes. Its
+    /// RNG carries on from the grid's, so it picks what the grid would. It
+    /// reports to no observers or subscribers, since what happens to it may
+    /// never happen to the grid.
+    pub fn fork(&self) -> Grid<'t, T> {
+        let options = &self.options;
+        let options = if options.on_collapse.is_some()
+            || options.on_eliminate.is_some()
+            || !options.subscribers.is_empty()
+        {
+            Arc::new(Settings {
+                on_collapse: None,
+                on_eliminate: None,
+                subscribers: Vec::new(),
+                ..Settings::clone(options)
+            })
+        } else {
+            Arc::clone(options)
+        };
+
+        Grid {
+            tileset: self.tileset,
+            adjacency: Arc::clone(&self.adjacency),
+            topology: Arc::clone(&self.topology),
+            options,
+            cells: self.cells.clone(),
+            rng: self.rng.clone(),
+            finished: self.finished,
+            history: self.history.clone(),
+            undone: self.undone.clone(),
+            supports: self.supports.clone(),
+            pending: self.pending.clone(),
+            checks: self.checks,
+            restarts: self.restarts,
+            started: self.started,
+            steps: self.steps,
+            turns: self.turns.clone(),
+            pins: self.pins.clone(),
+            dirty: self.dirty.clone(),
+            trail: self.trail.clone(),
+            entropies: self.entropies.clone(),
+            spiral: self.spiral.clone(),
+        }
+    }
+
+    fn save(&self) -> SavedState {
+        SavedState {
+            width: self.options.width,
+            height: self.options.height,
+            possibilities: self
+                .cells
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|cell| cell.possibilities.iter().copied().collect())
+                        .collect()
+                })
+                .collect(),
+            turns: self.turns.clone(),
+            rng: self.rng_state(),
+            started: self.started,
+            finished: self.finished,
+            restarts: self.restarts,
+            steps: self.steps,
+            pins: self.pins.clone(),
+            history: self.history.clone(),
+        }
+    }
+
+    /// Puts the grid back as `state` left it, once `state` is known to fit.
+    fn restore(&mut self, state: SavedState) {
+        for (row, saved) in self.cells.iter_mut().zip(state.possibilities) {
+            for (cell, possibilities) in row.iter_mut().zip(saved) {
+                cell.possibilities = possibilities.into_iter().collect();
+            }
+        }
+        self.turns = state.turns;
+        self.set_rng_state(&state.rng);
+        self.started = state.started;
+        self.finished = state.finished;
+        self.restarts = state.restarts;
+        self.steps = state.steps;
+        self.pins = state.pins;
+        self.history = state.history;
+        self.undone.clear();
+        self.supports = None;
+        self.pending.clear();
+        self.dirty.clear();
+        self.trail.clear();
+        self.entropies.clear();
+    }
+
+    /// The sum of every cell's entropy, which falls with each possibility
+    /// removed and reaches 0 once every cell is decided.
+    pub fn total_entropy(&self) -> usize {
+        self.cells.iter().flatten().map(Cell::entropy).sum()
+    }
+
+    /// Whether [`Options::cancel`] has been set.
+    fn is_cancelled(&self) -> bool {
+        self.options
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// The Shannon entropy of every cell over its tiles' base weights, row by
+    /// row, for showing where the wave is least constrained. Decided,
+    /// contradicted, and masked-out cells have none.
+    pub fn entropy_map(&self) -> Vec<f32> {
+        let weights = self.base_weights();
+        self.cells
+            .iter()
+            .flatten()
+            .map(|cell| cell.shannon_entropy(&weights) as f32)
+            .collect()
+    }
+
+    /// How far the grid has got, for driving a progress bar.
+    pub fn progress(&self) -> Progress {
+        let mut progress = Progress {
+            steps: self.steps,
+            ..Progress::default()
+        };
+        for (.., cell) in self.iter().filter(|&(x, y, _)| self.in_mask(x, y)) {
+            progress.total += 1;
+            progress.collapsed += cell.is_collapsed() as usize;
+            progress.contradictions += cell.is_contradiction() as usize;
+        }
+        progress
+    }
+
+    /// The fraction of cells that have collapsed to a single tile, out of
+    /// those in the mask.
+    pub fn collapsed_fraction(&self) -> f64 {
+        let total = match &self.options.mask {
+            Some(mask) => mask.iter().flatten().filter(|&&inside| inside).count(),
+            None => self.options.width * self.options.height,
+        };
+        if total == 0 {
+            return 1.0;
+        }
+
+        let collapsed = self
+            .cells
+            .iter()
+            .flatten()
+            .filter(|cell| cell.is_collapsed())
+            .count();
+        collapsed as f64 / total as f64
+    }
+
+    /// Collapses every remaining cell to its highest-weight candidate, without
+    /// propagating, and marks the grid finished.
+    pub fn fill_remaining(&mut self) {
+        let weights = self.base_weights();
+        for cell in self.cells.iter_mut().flatten() {
+            let best = cell
+                .possibilities
+                .iter()
+                .rev()
+                .copied()
+                .max_by(|&a, &b| weights[a].total_cmp(&weights[b]));
+            if let Some(tile) = best {
+                cell.collapse_to(tile);
+            }
+        }
+
+        self.supports = None;
+        self.finish();
+    }
+
+    fn run(&mut self) {
+        while !self.finished && !self.is_cancelled() {
+            if let Some(stop_at) = self.options.stop_at {
+                if self.collapsed_fraction() >= stop_at {
+                    self.fill_remaining();
+                    break;
+                }
+            }
+
+            self.step();
+        }
+    }
+}
+
+pub struct Model<'t, T = serde_json::Value> {
+    tileset: &'t Tileset<T>,
+    adjacency: Arc<Adjacency>,
+    settings: Settings,
+    grid: Grid<'t, T>,
+}
+
+const DEFAULT_WIDTH: usize = 10;
+const DEFAULT_HEIGHT: usize = 10;
+const MAX_LOCAL_RESETS: usize = 3;
+const MAX_RESTARTS: usize = 10;
+
+impl<'t, T> Model<'t, T> {
+    /// Starts building a model of `tileset` setting by setting.
+    pub fn builder(tileset: &'t Tileset<T>) -> ModelBuilder<'t, T> {
+        ModelBuilder::new(tileset)
+    }
+
+    pub fn new(tileset: &'t Tileset<T>, options: Options) -> Result<Self, WfcError> {
+        if tileset.tiles.is_empty() {
+            return Err(WfcError::EmptyTileset);
+        }
+        if options.depth.is_some_and(|depth| depth != 1) {
+            return Err(WfcError::InvalidGrid(
+                "a model is one grid deep; stack grids with LayeredModel::stacked".to_string(),
+            ));
+        }
+        tileset.check_arity()?;
+        tileset.check_rules()?;
+        tileset.check_weights()?;
+        tileset.check_counts()?;
+        for (&tile, &weight) in &options.weight_overrides {
+            if tile >= tileset.tiles.len() {
+                return Err(WfcError::UnknownTile(tile));
+            }
+            if !valid_weight(weight) {
+                return Err(WfcError::InvalidTileset(format!(
+                    "tile {} is overridden with weight {}, but weights must be finite and at least 0",
+                    tile, weight
+                )));
+            }
+        }
+        let mut pair_weights = HashMap::new();
+        for &(tile, other, multiplier) in &options.pair_weights {
+            for index in [tile, other] {
+                if index >= tileset.tiles.len() {
+                    return Err(WfcError::UnknownTile(index));
+                }
+            }
+            if !valid_weight(multiplier) {
+                return Err(WfcError::InvalidTileset(format!(
+                    "tiles {} and {} are paired with weight {}, but weights must be finite and at least 0",
+                    tile, other, multiplier
+                )));
+            }
+            pair_weights.insert((tile, other), multiplier);
+            pair_weights.insert((other, tile), multiplier);
+        }
+
+        let noise_seed = match options.seed {
+            _ if options.deterministic => 0,
+            Some(seed) => seed,
+            None => rand::random(),
+        };
+        let mut noise = Vec::new();
+        for (i, bias) in options.noise.into_iter().enumerate() {
+            if !bias.amplitude.is_finite() || !bias.frequency.is_finite() {
+                return Err(WfcError::InvalidTileset(format!(
+                    "noise for tag `{}` has amplitude {} and frequency {}, but both must be finite",
+                    bias.tag, bias.amplitude, bias.frequency
+                )));
+            }
+            let perlin = noise::Perlin::new(noise_seed.wrapping_add(i as u64));
+            noise.push((bias, perlin));
+        }
+
+        let tagged = |tag: &str| -> Result<Vec<usize>, WfcError> {
+            let tiles: Vec<usize> = (0..tileset.tiles.len())
+                .filter(|&tile| tileset.tiles[tile].has_tag(tag))
+                .collect();
+            if tiles.is_empty() {
+                return Err(WfcError::InvalidGrid(format!(
+                    "no tile is tagged `{}`",
+                    tag
+                )));
+            }
+            Ok(tiles)
+        };
+        let mut tag_counts = Vec::new();
+        for (tag, min, max) in &options.tag_counts {
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    return Err(WfcError::InvalidGrid(format!(
+                        "tag `{}` needs at least {} cells, but at most {}",
+                        tag, min, max
+                    )));
+                }
+            }
+            tag_counts.push((tagged(tag)?, *min, *max));
+        }
+        for (tag, _) in &options.min_region {
+            tagged(tag)?;
+        }
+        for rule in &options.distances {
+            let (DistanceRule::Near { tag, other, .. } | DistanceRule::Apart { tag, other, .. }) =
+                rule;
+            tagged(tag)?;
+            tagged(other)?;
+        }
+        for name in &options.required {
+            let tiles: Vec<usize> = (0..tileset.tiles.len())
+                .filter(|&tile| tileset.tiles[tile].is_named(name))
+                .collect();
+            if tiles.is_empty() {
+                return Err(WfcError::InvalidGrid(format!(
+                    "no tile is named or tagged `{}` to require",
+                    name
+                )));
+            }
+            tag_counts.push((tiles, Some(1), None));
+        }
+
+        let mut borders = Vec::new();
+        for (edge, socks) in &options.borders {
+            // A label the tileset never uses can't match any tile.
+            let socket = socks
+                .iter()
+                .map(|sock| match sock {
+                    SockName::Number(sock) => Some(Sock(u64::from(*sock))),
+                    SockName::Label(label) => tileset.labels.0.get(label).copied().map(Sock),
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(Socket)
+                .filter(|socket| {
+                    tileset.tiles.iter().any(|tile| {
+                        tile.socket(*edge) == socket || tile.socket(*edge).is_wildcard()
+                    })
+                });
+            let Some(socket) = socket else {
+                let socks = socks.iter().map(SockName::to_string).collect::<Vec<_>>();
+                return Err(WfcError::InvalidGrid(format!(
+                    "no tile has socks [{}] on its {:?} edge to border the grid with",
+                    socks.join(", "),
+                    edge
+                )));
+            };
+            borders.push((*edge, socket));
+        }
+
+        let mask_size = match &options.mask {
+            Some(mask) => Some((row_width(mask)?, mask.len())),
+            None => None,
+        };
+        let mut settings = Settings {
+            width: options
+                .width
+                .or(mask_size.map(|(width, _)| width))
+                .unwrap_or(DEFAULT_WIDTH),
+            height: options
+                .height
+                .or(mask_size.map(|(_, height)| height))
+                .unwrap_or(DEFAULT_HEIGHT),
+            framerate: options.framerate,
+            seed: options.seed,
+            propagator: options.propagator.unwrap_or_default(),
+            stop_at: options.stop_at,
+            cancel: options.cancel,
+            recovery: options.recovery.unwrap_or_default(),
+            max_backtrack_depth: options.max_backtrack_depth,
+            reset_radius: options.reset_radius.unwrap_or(1),
+            retry: options.retry,
+            bias: options.bias_field.map(|field| Bias(Arc::from(field))),
+            start: options.start.unwrap_or_default(),
+            heuristic: options.heuristic.unwrap_or_default(),
+            deterministic: options.deterministic,
+            weight_overrides: options.weight_overrides,
+            pair_weights,
+            weight_maps: options.weight_maps,
+            noise,
+            wrap: options.wrap || options.periodic,
+            periodic: options.periodic,
+            borders,
+            connected: options.connected,
+            min_region: options.min_region,
+            tag_counts,
+            distances: options.distances,
+            ground: options.ground,
+            allowed: options.allowed,
+            mask: options.mask,
+            on_collapse: None,
+            on_eliminate: None,
+            subscribers: Vec::new(),
+        };
+
+        if let Some((width, height)) = mask_size {
+            if (width, height) != (settings.width, settings.height) {
+                return Err(WfcError::InvalidGrid(format!(
+                    "the mask is {}x{}, but the grid is {}x{}",
+                    width, height, settings.width, settings.height
+                )));
+            }
+        }
+        for (&tile, map) in &settings.weight_maps {
+            if tile >= tileset.tiles.len() {
+                return Err(WfcError::UnknownTile(tile));
+            }
+            let size = (row_width(map)?, map.len());
+            if size != (settings.width, settings.height) {
+                return Err(WfcError::InvalidGrid(format!(
+                    "the weight map for tile {} is {}x{}, but the grid is {}x{}",
+                    tile, size.0, size.1, settings.width, settings.height
+                )));
+            }
+            if let Some(&weight) = map.iter().flatten().find(|&&weight| !valid_weight(weight)) {
+                return Err(WfcError::InvalidTileset(format!(
+                    "the weight map for tile {} has weight {}, but weights must be finite and at least 0",
+                    tile, weight
+                )));
+            }
+        }
+
+        if let Some(allowed) = &settings.allowed {
+            let size = (row_width(allowed)?, allowed.len());
+            if size != (settings.width, settings.height) {
+                return Err(WfcError::InvalidGrid(format!(
+                    "the allowed tiles are given for {}x{} cells, but the grid is {}x{}",
+                    size.0, size.1, settings.width, settings.height
+                )));
+            }
+            let count = tileset.tiles.len();
+            if let Some(&tile) = allowed
+                .iter()
+                .flatten()
+                .flatten()
+                .find(|&&tile| tile >= count)
+            {
+                return Err(WfcError::UnknownTile(tile));
+            }
+        }
+        let (width, height) = (settings.width, settings.height);
+        let mut restrictions = Vec::new();
+        for (x, y, tag) in &options.tag_pins {
+            if *x >= width || *y >= height {
+                return Err(WfcError::OutOfBounds { x: *x, y: *y });
+            }
+            restrictions.push((*x, *y, tagged(tag)?));
+        }
+        for (edge, tag) in options
+            .tag_borders
+            .iter()
+            .filter(|_| !settings.wrap && width > 0 && height > 0)
+        {
+            let tiles = tagged(tag)?;
+            let cells: Vec<(usize, usize)> = match edge {
+                Edge::Top => (0..width).map(|x| (x, 0)).collect(),
+                Edge::Right => (0..height).map(|y| (width - 1, y)).collect(),
+                Edge::Bottom => (0..width).map(|x| (x, height - 1)).collect(),
+                Edge::Left => (0..height).map(|y| (0, y)).collect(),
+            };
+            restrictions.extend(cells.into_iter().map(|(x, y)| (x, y, tiles.clone())));
+        }
+        if !restrictions.is_empty() {
+            let every = (0..tileset.tiles.len()).collect::<Vec<_>>();
+            let allowed = settings
+                .allowed
+                .get_or_insert_with(|| vec![vec![every; width]; height]);
+            for (x, y, tiles) in restrictions {
+                allowed[y][x].retain(|tile| tiles.contains(tile));
+            }
+        }
+        if let Some(ground) = &settings.ground {
+            if !tileset.tiles.iter().any(|tile| tile.is_named(ground)) {
+                return Err(WfcError::InvalidGrid(format!(
+                    "no tile is named or tagged `{}` to lay as the ground",
+                    ground
+                )));
+            }
+        }
+
+        let adjacency = Arc::new(Adjacency::new(tileset, &options.forbidden)?);
+        let grid = Grid::new(tileset, Arc::clone(&adjacency), settings.clone());
+
+        let mut model = Model {
+            tileset,
+            adjacency,
+            settings,
+            grid,
+        };
+        for (x, y, tile) in options.pins {
+            model.pin(x, y, tile)?;
+        }
+        Ok(model)
+    }
+
+    /// Builds a model from a partial map given as JSON rows of tile indices,
+    /// with `null` for every cell left to generate, and runs it. The prompt
+    /// decides the grid's size; each given tile is pinned and propagated
+    /// before generation, failing if it conflicts with the others.
+    pub fn from_prompt(
+        tileset: &'t Tileset<T>,
+        prompt: &str,
+        options: Options,
+    ) -> Result<Self, WfcError> {
+        let rows: Vec<Vec<Option<usize>>> = serde_json::from_str(prompt)
+            .map_err(|error| WfcError::InvalidGrid(error.to_string()))?;
+        let width = row_width(&rows)?;
+
+        let mut model = Model::new(
+            tileset,
+            Options {
+                width: Some(width),
+                height: Some(rows.len()),
+                ..options
+            },
+        )?;
+        for (y, row) in rows.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                if let Some(tile) = *tile {
+                    model.pin(x, y, tile)?;
+                }
+            }
+        }
+
+        model.run();
+        model.grid.check_contradiction()?;
+        Ok(model)
+    }
+
+    /// Replaces the grid with a fresh one of the given size, reusing the
+    /// tileset and its adjacency table. A mask is cut or padded to the new
+    /// size, with any new cells masked out.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.settings.width = width;
+        self.settings.height = height;
+        if let Some(mask) = &mut self.settings.mask {
+            mask.resize(height, Vec::new());
+            for row in mask.iter_mut() {
+                row.resize(width, false);
+            }
+        }
+        self.grid = Grid::new(
+            self.tileset,
+            Arc::clone(&self.adjacency),
+            self.settings.clone(),
+        );
+    }
+
+    pub fn grid(&self) -> &Grid<'t, T> {
+        &self.grid
+    }
+
+    /// The frames per second an animated viewer should step the model at, if
+    /// one was asked for.
+    pub fn framerate(&self) -> Option<u32> {
+        self.settings.framerate
+    }
+
+    pub fn grid_mut(&mut self) -> &mut Grid<'t, T> {
+        &mut self.grid
+    }
+
+    /// Collapses the cell at `(x, y)` to the tile at `tile_index` for good and
+    /// propagates the result. Unlike [`Grid::collapse_cell`], the pin can't
+    /// be undone, and it survives recovery and retries.
+    pub fn pin(&mut self, x: usize, y: usize, tile_index: usize) -> Result<(), WfcError> {
+        self.grid.pin(x, y, tile_index)
+    }
+
+    /// Rerolls the cells inside `rect`: resets them to every tile,
+    /// re-constrains them from the cells around them, and runs again until
+    /// they're decided. Every collapsed cell outside `rect` is held in place
+    /// throughout, even if recovery restarts the grid. Fails if `rect` runs
+    /// off the grid, or with the contradiction if the region can't be
+    /// filled.
+    pub fn recollapse(&mut self, rect: Rect) -> Result<(), WfcError> {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+        if right > width || bottom > height {
+            return Err(WfcError::OutOfBounds {
+                x: right.saturating_sub(1),
+                y: bottom.saturating_sub(1),
+            });
+        }
+
+        let pins = self.grid.pins.clone();
+        for (y, row) in self.grid.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let inside = (rect.x..right).contains(&x) && (rect.y..bottom).contains(&y);
+                if let (false, true, Some(&tile)) =
+                    (inside, cell.is_collapsed(), cell.possibilities.first())
+                {
+                    self.grid.pins.push((x, y, tile));
+                }
+            }
+        }
+
+        self.grid.reset_region(rect.x..right, rect.y..bottom);
+        self.grid.finished = false;
+        self.run();
+        self.grid.pins = pins;
+        self.grid.check_contradiction()
+    }
+
+    /// Forces the cell at `(x, y)` to the tile at `tile_index` mid-run, as an
+    /// editor would, and propagates the result. Fails with the contradiction
+    /// if the tile is already ruled out there or the observation leaves some
+    /// cell without a tile. Like any other decision, it can be undone.
+    pub fn observe(&mut self, x: usize, y: usize, tile_index: usize) -> Result<(), WfcError> {
+        self.grid.collapse_cell(x, y, tile_index)
+    }
+
+    /// Rules the tile at `tile_index` out of the cell at `(x, y)` and
+    /// propagates the result. See [`Grid::ban`].
+    pub fn ban(&mut self, x: usize, y: usize, tile_index: usize) -> Result<(), WfcError> {
+        self.grid.ban(x, y, tile_index)
+    }
+
+    /// Reverts the most recent collapse decision, whether it was made by
+    /// [`Model::run`] or by [`Grid::collapse_cell`].
+    pub fn undo(&mut self) -> bool {
+        self.grid.undo()
+    }
+
+    /// A channel that hears about every collapse, elimination, and
+    /// contradiction from now on, and when the grid finishes, for consuming
+    /// the run live on another thread. Carries over to retries.
+    pub fn subscribe(&mut self) -> Receiver<WfcEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.settings.subscribers.push(sender.clone());
+        Arc::make_mut(&mut self.grid.options)
+            .subscribers
+            .push(sender);
+        receiver
+    }
+
+    /// The tiles the cell at `(x, y)` may still become, in index order, or
+    /// none if it's off the grid.
+    pub fn possibilities(&self, x: usize, y: usize) -> impl Iterator<Item = usize> + '_ {
+        self.grid
+            .get(x, y)
+            .into_iter()
+            .flat_map(|cell| cell.possibilities.iter().copied())
+    }
+
+    /// See [`Grid::entropy_map`].
+    pub fn entropy_map(&self) -> Vec<f32> {
+        self.grid.entropy_map()
+    }
+
+    /// How far the current grid has got. See [`Grid::progress`].
+    pub fn progress(&self) -> Progress {
+        self.grid.progress()
+    }
+
+    /// The generated output, once every cell has collapsed. See
+    /// [`Grid::result`].
+    pub fn result(&self) -> Option<GridResult<'t, T>> {
+        self.grid.result()
+    }
+
+    /// Saves the run so far, to be carried on by [`Model::resume`].
+    pub fn snapshot(&self) -> SavedState {
+        self.grid.save()
+    }
+
+    /// Carries on the run `state` was saved from, as if it had never
+    /// stopped, given a model built from the same tileset and options. Fails
+    /// if `state` is for a grid of another size or names a tile the tileset
+    /// doesn't have, leaving the model as it was.
+    pub fn resume(&mut self, state: SavedState) -> Result<(), WfcError> {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let sized = |row_widths: Vec<usize>| {
+            row_widths.len() == height && row_widths.iter().all(|&row| row == width)
+        };
+        if (state.width, state.height) != (width, height)
+            || !sized(state.possibilities.iter().map(Vec::len).collect())
+            || !sized(state.turns.iter().map(Vec::len).collect())
+        {
+            return Err(WfcError::InvalidGrid(format!(
+                "the saved grid is {}x{}, but the grid is {}x{}",
+                state.width, state.height, width, height
+            )));
+        }
+        let tiles = self.tileset.tiles.len();
+        let removed = state.history.iter().flat_map(|decision| &decision.removed);
+        if let Some(tile) = state
+            .possibilities
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .chain(state.pins.iter().map(|&(.., tile)| tile))
+            .chain(removed.map(|&(.., tile)| tile))
+            .find(|&tile| tile >= tiles)
+        {
+            return Err(WfcError::UnknownTile(tile));
+        }
+
+        self.grid.restore(state);
+        Ok(())
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.grid.redo()
+    }
+
+    /// Runs the grid to completion, retrying from scratch according to the
+    /// [`RetryPolicy`] if it ends in a contradiction.
+    pub fn run(&mut self) -> RunStats {
+        let mut stats = RunStats::default();
+        loop {
+            self.grid.run();
+            if self.end_attempt(&mut stats) {
+                return stats;
+            }
+        }
+    }
+
+    /// Counts the attempt the grid just stopped on towards `stats`, and
+    /// starts a fresh grid if the [`RetryPolicy`] calls for another. Returns
+    /// whether the run is over.
+    fn end_attempt(&mut self, stats: &mut RunStats) -> bool {
+        let max_attempts = self.settings.retry.map_or(1, |retry| retry.max_attempts);
+        stats.attempts += 1;
+        stats.restarts += self.grid.restarts;
+        stats.cancelled = !self.grid.finished && self.grid.is_cancelled();
+        stats.succeeded = !stats.cancelled
+            && self.grid.check_contradiction().is_ok()
+            && (!self.settings.periodic || self.grid.is_seamless());
+        if stats.succeeded || stats.cancelled || stats.attempts >= max_attempts {
+            return true;
+        }
+
+        let seed = match self.settings.retry {
+            Some(RetryPolicy { reseed: true, .. }) => Some(self.grid.rng.gen()),
+            _ => self.settings.seed,
+        };
+        self.retry(seed);
+        false
+    }
+
+    /// Calls `hook` with `(x, y, tile)` whenever a cell is left with a single
+    /// tile, whether chosen by a step, pinned, or forced by propagation.
+    /// Replaces any earlier hook, and carries over to retries.
+    pub fn on_collapse(&mut self, hook: impl Fn(usize, usize, usize) + Send + Sync + 'static) {
+        let hook = Some(Hook(Arc::new(hook)));
+        self.settings.on_collapse = hook.clone();
+        Arc::make_mut(&mut self.grid.options).on_collapse = hook;
+    }
+
+    /// Calls `hook` with `(x, y, tile)` whenever `tile` is ruled out of a
+    /// cell while generating. Undoing and redoing decisions by hand isn't
+    /// reported.
+    pub fn on_eliminate(&mut self, hook: impl Fn(usize, usize, usize) + Send + Sync + 'static) {
+        let hook = Some(Hook(Arc::new(hook)));
+        self.settings.on_eliminate = hook.clone();
+        Arc::make_mut(&mut self.grid.options).on_eliminate = hook;
+    }
+
+    /// Runs the grid one step at a time, yielding a [`Step`] after every
+    /// collapse so callers can watch or animate the run, and inspect the grid
+    /// in between through [`Steps::grid`]. Unlike [`Model::run`], a
+    /// contradiction is never retried from scratch.
+    pub fn steps(&mut self) -> Steps<'_, 't, T> {
+        Steps {
+            grid: &mut self.grid,
+        }
+    }
+
+    /// Takes at most `max_steps` steps, so generation can be spread across
+    /// frames, and returns whether the grid has finished. Call it again to
+    /// carry on where it stopped. Like [`Model::steps`], it never retries;
+    /// check [`Grid::check_contradiction`] once it finishes.
+    pub fn run_with_limit(&mut self, max_steps: usize) -> bool {
+        let mut steps = self.steps();
+        for _ in 0..max_steps {
+            if steps.next().is_none() {
+                break;
+            }
+        }
+        // The step that collapses the last cell doesn't know it was the last.
+        let decided = |cell: &Cell| cell.is_collapsed() || cell.is_contradiction();
+        if !self.grid.finished && self.grid.started && self.grid.cells.iter().flatten().all(decided)
+        {
+            self.grid.finish();
+        }
+        self.grid.finished
+    }
+
+    /// Like [`Model::run`], but fails with the contradiction the final grid
+    /// was left with, [`WfcError::Cancelled`] if it was cancelled, or
+    /// [`WfcError::InvalidGrid`] if a periodic grid came out without seamless
+    /// edges.
+    pub fn try_run(&mut self) -> Result<RunStats, WfcError> {
+        let stats = self.run();
+        self.grid.check_contradiction()?;
+        if stats.cancelled {
+            return Err(WfcError::Cancelled);
+        }
+        if !stats.succeeded {
+            return Err(WfcError::InvalidGrid(
+                "the output doesn't tile seamlessly".to_string(),
+            ));
+        }
+        Ok(stats)
+    }
+
+    /// A fresh model over the same tileset, settings, and pins, seeded with
+    /// `seed`.
+    fn reseeded(&self, seed: Option<u64>) -> Model<'t, T> {
+        Model {
+            tileset: self.tileset,
+            adjacency: Arc::clone(&self.adjacency),
+            grid: self.fresh_grid(seed, self.grid.pins.clone()),
+            settings: Settings {
+                seed,
+                ..self.settings.clone()
+            },
+        }
+    }
+
+    /// Replaces the grid with a fresh one seeded by `seed`, keeping its pins.
+    fn retry(&mut self, seed: Option<u64>) {
+        let pins = std::mem::take(&mut self.grid.pins);
+        self.grid = self.fresh_grid(seed, pins);
+    }
+
+    /// A fresh grid seeded by `seed`, with `pins` collapsed and propagated.
+    fn fresh_grid(&self, seed: Option<u64>, pins: Vec<(usize, usize, usize)>) -> Grid<'t, T> {
+        let settings = Settings {
+            seed,
+            ..self.settings.clone()
+        };
+        let mut grid = Grid::new(self.tileset, Arc::clone(&self.adjacency), settings);
+        grid.pins = pins;
+        grid.apply_pins();
+        grid.propagate();
+        grid
+    }
+
+    /// The remaining tile indices of every cell, row by row.
+    pub fn possibilities_snapshot(&self) -> Vec<Vec<Vec<usize>>> {
+        self.grid
+            .cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.possibilities.iter().copied().collect())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Steps until `pred` holds for the grid, the grid finishes, or a step
+    /// leaves a contradiction behind, keeping whatever has been generated so
+    /// far. Returns whether `pred` was satisfied.
+    pub fn run_until(&mut self, pred: impl Fn(&Grid<'t, T>) -> bool) -> bool {
+        loop {
+            if pred(&self.grid) {
+                return true;
+            }
+            if self.grid.finished || self.grid.check_contradiction().is_err() {
+                return false;
+            }
+
+            self.grid.step();
+        }
+    }
+
+    /// Every tile that may sit among the given neighbors, indexed by
+    /// [`Edge`] (so `neighbors[0]` is the tile above). Unknown neighbors are
+    /// `None` and don't constrain the result.
+    pub fn fit(&self, neighbors: [Option<usize>; 4]) -> Vec<usize> {
+        (0..self.tileset.tiles.len())
+            .filter(|&tile| {
+                EDGES.iter().zip(neighbors).all(|(&edge, neighbor)| {
+                    neighbor.is_none_or(|other| {
+                        other < self.tileset.tiles.len() && self.adjacency.allows(tile, edge, other)
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Generates one complete grid per seed in parallel, keeping the model's
+    /// pins and retrying and recovering as [`Model::try_run`] does. Each grid
+    /// owns an RNG seeded from its own entry in `seeds`, so the results are
+    /// reproducible no matter how the work is scheduled across threads.
+    pub fn generate_batch(&self, seeds: &[u64]) -> Vec<Result<Grid<'t, T>, WfcError>>
+    where
+        T: Sync,
+    {
+        seeds
+            .par_iter()
+            .map(|&seed| {
+                let mut model = self.reseeded(Some(seed));
+                model.try_run().map(|_| model.grid)
+            })
+            .collect()
+    }
+}
+
+/// Every cell of a `width` by `height` grid, spiraling clockwise out from the
+/// middle.
+fn spiral(width: usize, height: usize) -> Vec<(usize, usize)> {
+    let mut order = Vec::with_capacity(width * height);
+    let (mut x, mut y) = ((width / 2) as isize, (height / 2) as isize);
+    let push = |x: isize, y: isize, order: &mut Vec<(usize, usize)>| {
+        if (0..width as isize).contains(&x) && (0..height as isize).contains(&y) {
+            order.push((x as usize, y as usize));
+        }
+    };
+    push(x, y, &mut order);
+
+    let mut run = 1;
+    while order.len() < width * height {
+        for (dx, dy, len) in [(1, 0, run), (0, 1, run), (-1, 0, run + 1), (0, -1, run + 1)] {
+            for _ in 0..len {
+                x += dx;
+                y += dy;
+                push(x, y, &mut order);
+            }
+        }
+        run += 2;
+    }
+    order
+}
+
+/// Every cell within `distance` steps of `(x, y)` on a grid `width` by
+/// `height`, including `(x, y)` itself.
+fn within(x: usize, y: usize, distance: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+    let rows = y.saturating_sub(distance)..(y + distance + 1).min(height);
+    rows.flat_map(|ny| {
+        let reach = distance - ny.abs_diff(y);
+        let columns = x.saturating_sub(reach)..(x + reach + 1).min(width);
+        columns.map(move |nx| (nx, ny))
+    })
+    .collect()
+}
+
+/// The length shared by every row, failing if any row differs from the first.
+fn row_width<T>(rows: &[Vec<T>]) -> Result<usize, WfcError> {
+    let width = rows.first().map_or(0, Vec::len);
+    if let Some(y) = rows.iter().position(|row| row.len() != width) {
+        return Err(WfcError::InvalidGrid(format!(
+            "row {} has {} cells, but row 0 has {}",
+            y,
+            rows[y].len(),
+            width
+        )));
+    }
+
+    Ok(width)
+}
+
+#[cfg(test)]
+mod tests;
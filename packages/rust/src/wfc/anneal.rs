@@ -0,0 +1,207 @@
+use super::{Adjacency, Edge, Tileset, WfcError, EDGES};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+#[derive(Clone, Debug)]
+pub struct AnnealOptions {
+    pub width: usize,
+    pub height: usize,
+    pub seed: Option<u64>,
+    /// How many single-cell changes to try before giving up.
+    pub iterations: usize,
+    /// The starting temperature. At temperature `t`, a change that breaks `d`
+    /// more edges than it fixes is still made with probability `e^(-d/t)`.
+    pub temperature: f64,
+    /// What the temperature is multiplied by after every change tried.
+    pub cooling: f64,
+}
+
+impl Default for AnnealOptions {
+    fn default() -> Self {
+        AnnealOptions {
+            width: super::DEFAULT_WIDTH,
+            height: super::DEFAULT_HEIGHT,
+            seed: None,
+            iterations: 100_000,
+            temperature: 2.0,
+            cooling: 0.9995,
+        }
+    }
+}
+
+/// A solver that trades propagation for local search: it fills the grid in
+/// one greedy pass, then repairs every pair of neighbors that don't connect
+/// by simulated annealing, retiling one cell at a time. It can't prove a
+/// tileset unsolvable, but it often finds a solution where the tiled model's
+/// search dead-ends.
+#[derive(Debug)]
+pub struct AnnealingModel<'t, T = serde_json::Value> {
+    tileset: &'t Tileset<T>,
+    options: AnnealOptions,
+    adjacency: Adjacency,
+    /// The tile in every cell, row by row.
+    tiles: Vec<Vec<usize>>,
+    rng: ChaCha8Rng,
+}
+
+impl<'t, T> AnnealingModel<'t, T> {
+    /// Builds the model and fills the grid greedily, each cell taking a
+    /// weighted random tile among those that fit best with the cells above
+    /// and to the left of it. Tiles can't be turned on the fly.
+    pub fn new(tileset: &'t Tileset<T>, options: AnnealOptions) -> Result<Self, WfcError> {
+        if tileset.tiles.is_empty() {
+            return Err(WfcError::EmptyTileset);
+        }
+        if let Some(tile) = tileset.tiles.iter().find(|tile| tile.rotatable) {
+            return Err(WfcError::InvalidTileset(format!(
+                "tile `{}` is rotatable, which annealing doesn't support",
+                tile.image
+            )));
+        }
+        tileset.check_arity()?;
+        tileset.check_rules()?;
+        tileset.check_weights()?;
+
+        let rng = match options.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let mut model = AnnealingModel {
+            tileset,
+            adjacency: Adjacency::new(tileset, &[])?,
+            tiles: vec![vec![0; options.width]; options.height],
+            options,
+            rng,
+        };
+        model.fill();
+        Ok(model)
+    }
+
+    /// Anneals until every pair of neighbors connects, failing with the
+    /// first cell, in row-major order, that still has a mismatched neighbor
+    /// once the iterations run out.
+    pub fn run(&mut self) -> Result<(), WfcError> {
+        let (width, height) = (self.options.width, self.options.height);
+        let mut temperature = self.options.temperature;
+        let mut conflicts = self.conflicts();
+
+        for _ in 0..self.options.iterations {
+            if conflicts == 0 {
+                break;
+            }
+            let (x, y) = (self.rng.gen_range(0..width), self.rng.gen_range(0..height));
+            let tile = self.random_tile();
+            let before = self.conflicts_at(x, y, self.tiles[y][x]);
+            let after = self.conflicts_at(x, y, tile);
+
+            let delta = after as f64 - before as f64;
+            if delta <= 0.0 || self.rng.gen::<f64>() < (-delta / temperature).exp() {
+                self.tiles[y][x] = tile;
+                conflicts = conflicts + after - before;
+            }
+            temperature = (temperature * self.options.cooling).max(f64::MIN_POSITIVE);
+        }
+
+        self.check_contradiction()
+    }
+
+    /// How many pairs of neighbors don't connect.
+    pub fn conflicts(&self) -> usize {
+        let (width, height) = (self.options.width, self.options.height);
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                [(Edge::Right, x + 1, y), (Edge::Bottom, x, y + 1)]
+                    .into_iter()
+                    .filter(|&(edge, nx, ny)| {
+                        nx < width
+                            && ny < height
+                            && !self
+                                .adjacency
+                                .allows(self.tiles[y][x], edge, self.tiles[ny][nx])
+                    })
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Fails with the first cell, in row-major order, that doesn't connect
+    /// to one of its neighbors.
+    pub fn check_contradiction(&self) -> Result<(), WfcError> {
+        for (y, row) in self.tiles.iter().enumerate() {
+            for (x, &tile) in row.iter().enumerate() {
+                if self.conflicts_at(x, y, tile) > 0 {
+                    return Err(WfcError::Contradiction { x, y });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The tile index of every cell, row by row. Some neighbors may not
+    /// connect until [`AnnealingModel::run`] succeeds.
+    pub fn to_indices(&self) -> Vec<Vec<usize>> {
+        self.tiles.clone()
+    }
+
+    fn fill(&mut self) {
+        let count = self.tileset.tiles.len();
+        for y in 0..self.options.height {
+            for x in 0..self.options.width {
+                let fits = |tile: usize| {
+                    let above = y > 0
+                        && !self
+                            .adjacency
+                            .allows(self.tiles[y - 1][x], Edge::Bottom, tile);
+                    let left = x > 0
+                        && !self
+                            .adjacency
+                            .allows(self.tiles[y][x - 1], Edge::Right, tile);
+                    above as usize + left as usize
+                };
+                let fewest = (0..count).map(fits).min().unwrap_or(0);
+                let best = (0..count)
+                    .filter(|&tile| fits(tile) == fewest)
+                    .collect::<Vec<_>>();
+                self.tiles[y][x] = match best
+                    .choose_weighted(&mut self.rng, |&tile| self.tileset.tiles[tile].weight())
+                {
+                    Ok(&tile) => tile,
+                    Err(_) => *best.choose(&mut self.rng).unwrap(),
+                };
+            }
+        }
+    }
+
+    /// A tile picked at random by weight.
+    fn random_tile(&mut self) -> usize {
+        let tiles = &self.tileset.tiles;
+        let indices = (0..tiles.len()).collect::<Vec<_>>();
+        match indices.choose_weighted(&mut self.rng, |&tile| tiles[tile].weight()) {
+            Ok(&tile) => tile,
+            Err(_) => self.rng.gen_range(0..tiles.len()),
+        }
+    }
+
+    /// How many of `(x, y)`'s neighbors wouldn't connect to `tile` there.
+    fn conflicts_at(&self, x: usize, y: usize, tile: usize) -> usize {
+        let (width, height) = (self.options.width as isize, self.options.height as isize);
+        EDGES
+            .iter()
+            .filter(|&&edge| {
+                let (dx, dy) = match edge {
+                    Edge::Top => (0, -1),
+                    Edge::Right => (1, 0),
+                    Edge::Bottom => (0, 1),
+                    Edge::Left => (-1, 0),
+                };
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                (0..width).contains(&nx)
+                    && (0..height).contains(&ny)
+                    && !self
+                        .adjacency
+                        .allows(tile, edge, self.tiles[ny as usize][nx as usize])
+            })
+            .count()
+    }
+}
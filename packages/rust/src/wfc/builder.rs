@@ -0,0 +1,236 @@
+use super::{
+    BiasField, DistanceRule, Edge, Heuristic, Model, NoiseBias, Options, Propagator, RecoveryMode,
+    RetryPolicy, SockName, StartPoint, Tileset, WfcError,
+};
+use std::sync::{atomic::AtomicBool, Arc};
+
+/// Builds a [`Model`] one setting at a time, starting from the defaults of
+/// [`Options`]. Settings that take a list add to it on every call.
+#[must_use]
+pub struct ModelBuilder<'t, T = serde_json::Value> {
+    tileset: &'t Tileset<T>,
+    options: Options,
+}
+
+impl<'t, T> ModelBuilder<'t, T> {
+    pub fn new(tileset: &'t Tileset<T>) -> Self {
+        ModelBuilder {
+            tileset,
+            options: Options::default(),
+        }
+    }
+
+    /// Validates the settings and builds the model, failing as
+    /// [`Model::new`] does.
+    pub fn build(self) -> Result<Model<'t, T>, WfcError> {
+        Model::new(self.tileset, self.options)
+    }
+
+    /// The settings gathered so far, for models built from [`Options`]
+    /// rather than by [`ModelBuilder::build`].
+    pub fn into_options(self) -> Options {
+        self.options
+    }
+
+    pub fn width(mut self, width: usize) -> Self {
+        self.options.width = Some(width);
+        self
+    }
+
+    pub fn height(mut self, height: usize) -> Self {
+        self.options.height = Some(height);
+        self
+    }
+
+    pub fn framerate(mut self, framerate: u32) -> Self {
+        self.options.framerate = Some(framerate);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.options.seed = Some(seed);
+        self
+    }
+
+    pub fn propagator(mut self, propagator: Propagator) -> Self {
+        self.options.propagator = Some(propagator);
+        self
+    }
+
+    /// See [`Options::forbidden`].
+    pub fn forbid(mut self, tile: usize, other: usize, edge: Edge) -> Self {
+        self.options.forbidden.push((tile, other, edge));
+        self
+    }
+
+    /// See [`Options::stop_at`].
+    pub fn stop_at(mut self, fraction: f64) -> Self {
+        self.options.stop_at = Some(fraction);
+        self
+    }
+
+    /// See [`Options::cancel`].
+    pub fn cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    pub fn recovery(mut self, recovery: RecoveryMode) -> Self {
+        self.options.recovery = Some(recovery);
+        self
+    }
+
+    pub fn max_backtrack_depth(mut self, depth: usize) -> Self {
+        self.options.max_backtrack_depth = Some(depth);
+        self
+    }
+
+    pub fn reset_radius(mut self, radius: usize) -> Self {
+        self.options.reset_radius = Some(radius);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.options.retry = Some(retry);
+        self
+    }
+
+    /// See [`Options::bias_field`].
+    pub fn bias_field(
+        mut self,
+        field: impl Fn(usize, usize) -> Vec<(usize, f64)> + Send + Sync + 'static,
+    ) -> Self {
+        self.options.bias_field = Some(Box::new(field) as Box<BiasField>);
+        self
+    }
+
+    pub fn start(mut self, start: StartPoint) -> Self {
+        self.options.start = Some(start);
+        self
+    }
+
+    pub fn heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.options.heuristic = Some(heuristic);
+        self
+    }
+
+    /// See [`Options::deterministic`].
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// Replaces the base weight of `tile` for this run only.
+    pub fn weight(mut self, tile: usize, weight: f64) -> Self {
+        self.options.weight_overrides.insert(tile, weight);
+        self
+    }
+
+    /// See [`Options::pair_weights`].
+    pub fn pair_weight(mut self, tile: usize, other: usize, multiplier: f64) -> Self {
+        self.options.pair_weights.push((tile, other, multiplier));
+        self
+    }
+
+    /// See [`Options::weight_maps`].
+    pub fn weight_map(mut self, tile: usize, map: Vec<Vec<f64>>) -> Self {
+        self.options.weight_maps.insert(tile, map);
+        self
+    }
+
+    pub fn noise(mut self, noise: NoiseBias) -> Self {
+        self.options.noise.push(noise);
+        self
+    }
+
+    pub fn wrap(mut self, wrap: bool) -> Self {
+        self.options.wrap = wrap;
+        self
+    }
+
+    /// See [`Options::periodic`].
+    pub fn periodic(mut self, periodic: bool) -> Self {
+        self.options.periodic = periodic;
+        self
+    }
+
+    /// See [`Options::borders`].
+    pub fn border<S: Into<SockName>>(
+        mut self,
+        edge: Edge,
+        socks: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let socks = socks.into_iter().map(Into::into).collect();
+        self.options.borders.push((edge, socks));
+        self
+    }
+
+    pub fn pin(mut self, x: usize, y: usize, tile: usize) -> Self {
+        self.options.pins.push((x, y, tile));
+        self
+    }
+
+    /// See [`Options::connected`].
+    pub fn connected(mut self, tag: impl Into<String>) -> Self {
+        self.options.connected.push(tag.into());
+        self
+    }
+
+    /// See [`Options::min_region`].
+    pub fn min_region(mut self, tag: impl Into<String>, cells: usize) -> Self {
+        self.options.min_region.push((tag.into(), cells));
+        self
+    }
+
+    /// See [`Options::tag_counts`].
+    pub fn tag_count(
+        mut self,
+        tag: impl Into<String>,
+        min: Option<usize>,
+        max: Option<usize>,
+    ) -> Self {
+        self.options.tag_counts.push((tag.into(), min, max));
+        self
+    }
+
+    /// See [`Options::tag_pins`].
+    pub fn tag_pin(mut self, x: usize, y: usize, tag: impl Into<String>) -> Self {
+        self.options.tag_pins.push((x, y, tag.into()));
+        self
+    }
+
+    /// See [`Options::tag_borders`].
+    pub fn tag_border(mut self, edge: Edge, tag: impl Into<String>) -> Self {
+        self.options.tag_borders.push((edge, tag.into()));
+        self
+    }
+
+    /// See [`Options::required`].
+    pub fn require(mut self, name: impl Into<String>) -> Self {
+        self.options.required.push(name.into());
+        self
+    }
+
+    pub fn distance(mut self, rule: DistanceRule) -> Self {
+        self.options.distances.push(rule);
+        self
+    }
+
+    /// See [`Options::ground`].
+    pub fn ground(mut self, name: impl Into<String>) -> Self {
+        self.options.ground = Some(name.into());
+        self
+    }
+
+    /// See [`Options::allowed`].
+    pub fn allowed(mut self, allowed: Vec<Vec<Vec<usize>>>) -> Self {
+        self.options.allowed = Some(allowed);
+        self
+    }
+
+    /// See [`Options::mask`].
+    pub fn mask(mut self, mask: Vec<Vec<bool>>) -> Self {
+        self.options.mask = Some(mask);
+        self
+    }
+}
@@ -0,0 +1,129 @@
+use super::{Model, Options, Tileset, WfcError};
+use std::collections::HashMap;
+
+/// An endless world of square chunks, each generated the first time it is
+/// asked for. A new chunk is solved with a one-cell frame around it, and every
+/// frame cell that falls in an existing chunk is pinned to that chunk's tile,
+/// so the seams between chunks match whatever order they're generated in.
+pub struct ChunkedModel<'t, T = serde_json::Value> {
+    size: usize,
+    seed: u64,
+    /// A model `size + 2` cells square that each chunk's model is copied from.
+    template: Model<'t, T>,
+    chunks: HashMap<(i64, i64), Vec<Vec<usize>>>,
+}
+
+impl<'t, T> ChunkedModel<'t, T> {
+    /// Generates chunks `size` cells square with the rest of `options`. Each
+    /// chunk's seed is derived from `options.seed` and the chunk's position,
+    /// but a chunk is also pinned to whichever neighbors came before it, so
+    /// exploring a seeded world in a different order can fill its chunks
+    /// differently. Only the seams are sure to match.
+    pub fn new(tileset: &'t Tileset<T>, size: usize, options: Options) -> Result<Self, WfcError> {
+        if size == 0 {
+            return Err(WfcError::InvalidGrid(
+                "chunks must be at least one cell across".to_string(),
+            ));
+        }
+        let seed = options.seed.unwrap_or_else(rand::random);
+        let template = Model::new(
+            tileset,
+            Options {
+                width: Some(size + 2),
+                height: Some(size + 2),
+                ..options
+            },
+        )?;
+
+        Ok(ChunkedModel {
+            size,
+            seed,
+            template,
+            chunks: HashMap::new(),
+        })
+    }
+
+    /// How many cells across and down each chunk is.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// How many chunks have been generated so far.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn is_generated(&self, cx: i64, cy: i64) -> bool {
+        self.chunks.contains_key(&(cx, cy))
+    }
+
+    /// The tile index of every cell in chunk `(cx, cy)`, row by row,
+    /// generating it first if need be. Fails if the chunk can't be fitted to
+    /// its neighbors, leaving it ungenerated.
+    pub fn chunk(&mut self, cx: i64, cy: i64) -> Result<&Vec<Vec<usize>>, WfcError> {
+        if !self.chunks.contains_key(&(cx, cy)) {
+            let chunk = self.generate(cx, cy)?;
+            self.chunks.insert((cx, cy), chunk);
+        }
+        Ok(&self.chunks[&(cx, cy)])
+    }
+
+    /// The tile index at world position `(x, y)`, generating its chunk first
+    /// if need be.
+    pub fn tile_at(&mut self, x: i64, y: i64) -> Result<usize, WfcError> {
+        let size = self.size as i64;
+        let chunk = self.chunk(x.div_euclid(size), y.div_euclid(size))?;
+        Ok(chunk[y.rem_euclid(size) as usize][x.rem_euclid(size) as usize])
+    }
+
+    /// The tile already generated at world position `(x, y)`, if any.
+    fn generated_at(&self, x: i64, y: i64) -> Option<usize> {
+        let size = self.size as i64;
+        let chunk = self.chunks.get(&(x.div_euclid(size), y.div_euclid(size)))?;
+        Some(chunk[y.rem_euclid(size) as usize][x.rem_euclid(size) as usize])
+    }
+
+    fn generate(&self, cx: i64, cy: i64) -> Result<Vec<Vec<usize>>, WfcError> {
+        let size = self.size;
+        let mut model = self.template.reseeded(Some(chunk_seed(self.seed, cx, cy)));
+
+        let (left, top) = (cx * size as i64 - 1, cy * size as i64 - 1);
+        for fy in 0..size + 2 {
+            for fx in 0..size + 2 {
+                let frame = fx == 0 || fy == 0 || fx == size + 1 || fy == size + 1;
+                if !frame {
+                    continue;
+                }
+                if let Some(tile) = self.generated_at(left + fx as i64, top + fy as i64) {
+                    model.pin(fx, fy, tile)?;
+                }
+            }
+        }
+
+        model.run();
+        model.grid.check_contradiction()?;
+        let indices = model
+            .grid
+            .to_indices()
+            .ok_or(WfcError::Contradiction { x: 0, y: 0 })?;
+        Ok(indices[1..=size]
+            .iter()
+            .map(|row| row[1..=size].to_vec())
+            .collect())
+    }
+}
+
+/// Mixes a chunk's position into the world seed, SplitMix64-style, so that
+/// neighboring chunks get unrelated seeds.
+fn chunk_seed(seed: u64, cx: i64, cy: i64) -> u64 {
+    let mut z = seed
+        ^ (cx as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (cy as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,97 @@
+use super::Grid;
+use serde_json::json;
+
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+impl<'t, T> Grid<'t, T> {
+    /// Exports the grid as a Tiled map, with a single tile layer and an
+    /// embedded image-collection tileset named `tileset_name`. Global tile
+    /// IDs are 1-based tile indices, with rotations and reflections expressed
+    /// through Tiled's flip flags. Uncollapsed and transparent cells are left
+    /// empty (gid 0).
+    pub fn to_tiled_json(&self, tileset_name: &str) -> String {
+        let size = self.tileset.size;
+        let data = self
+            .cells
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, cell)| (x, y, cell)))
+            .map(|(x, y, cell)| {
+                let Some(&tile) = cell.possibilities.first().filter(|_| cell.is_collapsed()) else {
+                    return 0;
+                };
+                let config = &self.tileset.tiles[tile];
+                if config.transparent {
+                    return 0;
+                }
+
+                let flags = match (config.mirrored, self.rotation_at(x, y).unwrap_or(0)) {
+                    (false, 1) => FLIPPED_HORIZONTALLY | FLIPPED_DIAGONALLY,
+                    (false, 2) => FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY,
+                    (false, 3) => FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY,
+                    (true, 0) => FLIPPED_HORIZONTALLY,
+                    (true, 1) => FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY,
+                    (true, 2) => FLIPPED_VERTICALLY,
+                    (true, 3) => FLIPPED_DIAGONALLY,
+                    _ => 0,
+                };
+                (tile as u32 + 1) | flags
+            })
+            .collect::<Vec<_>>();
+        let tiles = self
+            .tileset
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, config)| !config.transparent)
+            .map(|(id, config)| {
+                json!({
+                    "id": id,
+                    "image": config.image,
+                    "imagewidth": size,
+                    "imageheight": size,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "type": "map",
+            "version": "1.10",
+            "orientation": "orthogonal",
+            "renderorder": "right-down",
+            "infinite": false,
+            "width": self.options.width,
+            "height": self.options.height,
+            "tilewidth": size,
+            "tileheight": size,
+            "nextlayerid": 2,
+            "nextobjectid": 1,
+            "layers": [{
+                "id": 1,
+                "name": "wfc",
+                "type": "tilelayer",
+                "x": 0,
+                "y": 0,
+                "width": self.options.width,
+                "height": self.options.height,
+                "opacity": 1,
+                "visible": true,
+                "data": data,
+            }],
+            "tilesets": [{
+                "firstgid": 1,
+                "name": tileset_name,
+                "tilewidth": size,
+                "tileheight": size,
+                "tilecount": self.tileset.tiles.len(),
+                "columns": 0,
+                "margin": 0,
+                "spacing": 0,
+                "tiles": tiles,
+            }],
+        })
+        .to_string()
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Model, RunStats, Step, Steps};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+impl<'t, T> Model<'t, T> {
+    /// Like [`Model::run`], but hands control back to the executor after
+    /// every step, so generation never blocks an async runtime for long.
+    pub async fn run_async(&mut self) -> RunStats {
+        let mut stats = RunStats::default();
+        loop {
+            while self.steps().next().is_some() {
+                YieldNow(false).await;
+            }
+            if self.end_attempt(&mut stats) {
+                return stats;
+            }
+        }
+    }
+
+    /// Like [`Model::steps`], but yields to the executor before each step.
+    pub fn steps_async(&mut self) -> AsyncSteps<'_, 't, T> {
+        AsyncSteps {
+            steps: self.steps(),
+        }
+    }
+}
+
+/// Steps a model's grid one collapse at a time from async code, like a
+/// stream of [`Step`]s. See [`Model::steps_async`].
+///
+/// It deliberately doesn't implement `futures::Stream`: std has no stream
+/// trait yet, and the `async` feature adds no dependencies. Wrap it with
+/// `futures::stream::unfold` where a stream is wanted.
+pub struct AsyncSteps<'m, 't, T = serde_json::Value> {
+    steps: Steps<'m, 't, T>,
+}
+
+impl<'t, T> AsyncSteps<'_, 't, T> {
+    /// The next step, or `None` once the grid has finished.
+    pub async fn next(&mut self) -> Option<Step> {
+        YieldNow(false).await;
+        self.steps.next()
+    }
+
+    /// The grid as the last step left it.
+    pub fn grid(&self) -> &super::Grid<'t, T> {
+        self.steps.grid()
+    }
+}
+
+/// Returns pending once, asking to be polled again straight away, so other
+/// tasks get a turn.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if std::mem::replace(&mut self.0, true) {
+            return Poll::Ready(());
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
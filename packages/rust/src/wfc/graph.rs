@@ -0,0 +1,296 @@
+use super::{valid_weight, wave::Wave, Sock, WfcError};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Any arrangement of cells as a graph: nodes joined by edges, where each end
+/// of an edge is one of its node's numbered ports. Tiles list a socket per
+/// port, and two nodes joined port to port must have tiles whose sockets on
+/// those ports match.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    /// `edges[node]` lists every `(port, neighbor, neighbor_port)` of the
+    /// node.
+    edges: Vec<Vec<(usize, usize, usize)>>,
+}
+
+impl Topology {
+    /// A graph of `nodes` nodes and no edges.
+    pub fn new(nodes: usize) -> Self {
+        Topology {
+            edges: vec![Vec::new(); nodes],
+        }
+    }
+
+    /// The square grid as a graph, with node `y * width + x` for each cell
+    /// and ports numbered like [`Edge`](super::Edge): top, right, bottom,
+    /// then left. This is the graph a [`Grid`](super::Grid) runs on.
+    pub fn square(width: usize, height: usize) -> Self {
+        Topology::grid(width, height, false)
+    }
+
+    /// Like [`Topology::square`], but each edge of the grid is joined to the
+    /// opposite one, as with [`Options::wrap`](super::Options::wrap).
+    pub fn torus(width: usize, height: usize) -> Self {
+        Topology::grid(width, height, true)
+    }
+
+    fn grid(width: usize, height: usize, wrap: bool) -> Self {
+        let mut topology = Topology::new(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let node = &mut topology.edges[y * width + x];
+                if y > 0 || wrap {
+                    node.push((0, (y + height - 1) % height * width + x, 2));
+                }
+                if x + 1 < width || wrap {
+                    node.push((1, y * width + (x + 1) % width, 3));
+                }
+                if y + 1 < height || wrap {
+                    node.push((2, (y + 1) % height * width + x, 0));
+                }
+                if x > 0 || wrap {
+                    node.push((3, y * width + (x + width - 1) % width, 1));
+                }
+            }
+        }
+        topology
+    }
+
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// Joins port `port` of node `a` to port `other_port` of node `b`. Each
+    /// port takes at most one edge.
+    pub fn connect(
+        &mut self,
+        a: usize,
+        port: usize,
+        b: usize,
+        other_port: usize,
+    ) -> Result<(), WfcError> {
+        for node in [a, b] {
+            if node >= self.len() {
+                return Err(WfcError::InvalidGrid(format!(
+                    "node {} is past the end of a graph of {}",
+                    node,
+                    self.len()
+                )));
+            }
+        }
+        for (node, port) in [(a, port), (b, other_port)] {
+            if self.edges[node].iter().any(|&(used, ..)| used == port) {
+                return Err(WfcError::InvalidGrid(format!(
+                    "port {} of node {} is already connected",
+                    port, node
+                )));
+            }
+        }
+
+        self.edges[a].push((port, b, other_port));
+        self.edges[b].push((other_port, a, port));
+        Ok(())
+    }
+
+    /// Every `(port, neighbor, neighbor_port)` of `node`.
+    pub fn edges(&self, node: usize) -> &[(usize, usize, usize)] {
+        &self.edges[node]
+    }
+
+    /// The node reached from `node` by leaving through each of `ports` in
+    /// turn, or `None` if one of them has no edge. On a square grid, leaving
+    /// through the top and then the left port finds the cell diagonally up
+    /// and to the left.
+    pub fn walk(&self, node: usize, ports: &[usize]) -> Option<usize> {
+        ports.iter().try_fold(node, |node, &port| {
+            self.edges[node]
+                .iter()
+                .find(|&&(used, ..)| used == port)
+                .map(|&(_, neighbor, _)| neighbor)
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "RawGraphTile")]
+pub struct GraphTile {
+    image: String,
+    /// Socks listed clockwise along the edge on each port, in port order.
+    sockets: Vec<Vec<Sock>>,
+    weight: f64,
+}
+
+#[derive(Deserialize)]
+struct RawGraphTile {
+    image: String,
+    sockets: Vec<Vec<Sock>>,
+    #[serde(default)]
+    weight: Option<f64>,
+}
+
+impl TryFrom<RawGraphTile> for GraphTile {
+    type Error = String;
+
+    fn try_from(raw: RawGraphTile) -> Result<Self, Self::Error> {
+        let weight = raw.weight.unwrap_or(1.0);
+        if !valid_weight(weight) {
+            return Err(format!(
+                "graph tile `{}` has weight {}, but weights must be finite and at least 0",
+                raw.image, weight
+            ));
+        }
+
+        Ok(GraphTile {
+            image: raw.image,
+            sockets: raw.sockets,
+            weight,
+        })
+    }
+}
+
+impl GraphTile {
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Whether `other` may sit across an edge joining this tile's `port` to
+    /// its `other_port`, with the same reversed-socks rule as square tiles.
+    pub fn connects_to(&self, port: usize, other: &GraphTile, other_port: usize) -> bool {
+        match (self.sockets.get(port), other.sockets.get(other_port)) {
+            (Some(socks), Some(other_socks)) => socks.iter().eq(other_socks.iter().rev()),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct GraphTileset {
+    pub size: u32,
+    #[serde(deserialize_with = "super::labelled")]
+    pub tiles: Vec<GraphTile>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GraphOptions {
+    pub seed: Option<u64>,
+}
+
+/// The tiled model on any [`Topology`].
+#[derive(Debug)]
+pub struct GraphModel<'t> {
+    tileset: &'t GraphTileset,
+    nodes: usize,
+    wave: Wave,
+}
+
+impl<'t> GraphModel<'t> {
+    /// Fails if some tile has no socket for a port the topology uses.
+    pub fn new(
+        tileset: &'t GraphTileset,
+        topology: &Topology,
+        options: GraphOptions,
+    ) -> Result<Self, WfcError> {
+        // Each distinct pair of ports an edge joins is one relation.
+        let mut relations = HashMap::new();
+        let neighbors = (0..topology.len())
+            .map(|node| {
+                topology
+                    .edges(node)
+                    .iter()
+                    .map(|&(port, neighbor, other_port)| {
+                        let count = relations.len();
+                        let relation = *relations.entry((port, other_port)).or_insert(count);
+                        (neighbor, relation)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if let Some(&(port, _)) = relations.keys().max_by_key(|(port, _)| *port) {
+            if let Some(tile) = tileset.tiles.iter().find(|tile| tile.sockets.len() <= port) {
+                return Err(WfcError::InvalidTileset(format!(
+                    "graph tile `{}` has {} sockets, but the topology uses port {}",
+                    tile.image,
+                    tile.sockets.len(),
+                    port
+                )));
+            }
+        }
+
+        let mut ports = vec![(0, 0); relations.len()];
+        for (&pair, &relation) in &relations {
+            ports[relation] = pair;
+        }
+        let compatible = tileset
+            .tiles
+            .iter()
+            .map(|tile| {
+                ports
+                    .iter()
+                    .map(|&(port, other_port)| {
+                        (0..tileset.tiles.len())
+                            .filter(|&other| {
+                                tile.connects_to(port, &tileset.tiles[other], other_port)
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        let weights = tileset.tiles.iter().map(GraphTile::weight).collect();
+
+        Ok(GraphModel {
+            tileset,
+            nodes: topology.len(),
+            wave: Wave::new(weights, compatible, neighbors, options.seed),
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.wave.is_finished()
+    }
+
+    pub fn step(&mut self) {
+        self.wave.step();
+    }
+
+    /// Steps until every node is decided, failing with the first
+    /// contradiction left once restarts run out.
+    pub fn run(&mut self) -> Result<(), WfcError> {
+        while !self.wave.is_finished() {
+            self.wave.step();
+        }
+        self.check_contradiction()
+    }
+
+    /// Fails with the first node that no tile fits, given as `x`, with `y`
+    /// always 0.
+    pub fn check_contradiction(&self) -> Result<(), WfcError> {
+        match self.wave.contradiction() {
+            Some(node) => Err(WfcError::Contradiction { x: node, y: 0 }),
+            None => Ok(()),
+        }
+    }
+
+    /// The tile at `node`, once it has collapsed.
+    pub fn tile_at(&self, node: usize) -> Option<&'t GraphTile> {
+        let tile = self.wave.collapsed(node)?;
+        Some(&self.tileset.tiles[tile])
+    }
+
+    /// The tile index of every node, or `None` if any node has not collapsed
+    /// to a single tile.
+    pub fn to_indices(&self) -> Option<Vec<usize>> {
+        (0..self.nodes)
+            .map(|node| self.wave.collapsed(node))
+            .collect()
+    }
+}
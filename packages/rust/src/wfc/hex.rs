@@ -0,0 +1,210 @@
+use super::{valid_weight, wave::Wave, Sock, WfcError};
+use serde::Deserialize;
+
+/// The six directions from a pointy-topped hex, clockwise from the upper
+/// right, as axial `(q, r)` offsets. A hex tile lists one socket per
+/// direction in this order.
+pub(super) const DIRECTIONS: [(isize, isize); 6] =
+    [(1, -1), (1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1)];
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "RawHexTile")]
+pub struct HexTile {
+    image: String,
+    /// Socks listed clockwise along each of the six edges, in the order of
+    /// the directions they face, starting from the upper right.
+    sockets: [Vec<Sock>; 6],
+    weight: f64,
+}
+
+#[derive(Deserialize)]
+struct RawHexTile {
+    image: String,
+    sockets: Vec<Vec<Sock>>,
+    #[serde(default)]
+    weight: Option<f64>,
+}
+
+impl TryFrom<RawHexTile> for HexTile {
+    type Error = String;
+
+    fn try_from(raw: RawHexTile) -> Result<Self, Self::Error> {
+        let count = raw.sockets.len();
+        let sockets = raw.sockets.try_into().map_err(|_| {
+            format!(
+                "hex tile `{}` has {} sockets, but needs one per edge (6)",
+                raw.image, count
+            )
+        })?;
+        let weight = raw.weight.unwrap_or(1.0);
+        if !valid_weight(weight) {
+            return Err(format!(
+                "hex tile `{}` has weight {}, but weights must be finite and at least 0",
+                raw.image, weight
+            ));
+        }
+
+        Ok(HexTile {
+            image: raw.image,
+            sockets,
+            weight,
+        })
+    }
+}
+
+impl HexTile {
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Whether `other` may sit past edge `direction` of this tile, with the
+    /// same reversed-socks rule as square tiles.
+    pub(super) fn connects_to(&self, other: &HexTile, direction: usize) -> bool {
+        self.sockets[direction]
+            .iter()
+            .eq(other.sockets[(direction + 3) % 6].iter().rev())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct HexTileset {
+    pub size: u32,
+    #[serde(deserialize_with = "super::labelled")]
+    pub tiles: Vec<HexTile>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HexOptions {
+    pub width: usize,
+    pub height: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for HexOptions {
+    fn default() -> Self {
+        HexOptions {
+            width: super::DEFAULT_WIDTH,
+            height: super::DEFAULT_HEIGHT,
+            seed: None,
+        }
+    }
+}
+
+/// The tiled model on a rectangular map of pointy-topped hexes, where every
+/// cell has six neighbors. Odd rows are shifted half a hex to the right.
+///
+/// Cells are addressed by axial coordinates `(q, r)`: `r` is the row, and `q`
+/// increases to the right but drifts half a hex left per row, so the first
+/// cell of row `r` has `q = -(r / 2)`.
+#[derive(Debug)]
+pub struct HexModel<'t> {
+    tileset: &'t HexTileset,
+    options: HexOptions,
+    wave: Wave,
+}
+
+impl<'t> HexModel<'t> {
+    pub fn new(tileset: &'t HexTileset, options: HexOptions) -> Result<Self, WfcError> {
+        let compatible = tileset
+            .tiles
+            .iter()
+            .map(|tile| {
+                (0..6)
+                    .map(|direction| {
+                        (0..tileset.tiles.len())
+                            .filter(|&other| tile.connects_to(&tileset.tiles[other], direction))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (width, height) = (options.width, options.height);
+        let neighbors = (0..width * height)
+            .map(|i| {
+                let (q, r) = axial(width, i);
+                DIRECTIONS
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(direction, (dq, dr))| {
+                        Some((index(width, height, q + dq, r + dr)?, direction))
+                    })
+                    .collect()
+            })
+            .collect();
+        let weights = tileset.tiles.iter().map(HexTile::weight).collect();
+
+        Ok(HexModel {
+            tileset,
+            wave: Wave::new(weights, compatible, neighbors, options.seed),
+            options,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.wave.is_finished()
+    }
+
+    pub fn step(&mut self) {
+        self.wave.step();
+    }
+
+    /// Steps until every cell is decided, failing with the first
+    /// contradiction left once restarts run out.
+    pub fn run(&mut self) -> Result<(), WfcError> {
+        while !self.wave.is_finished() {
+            self.wave.step();
+        }
+        self.check_contradiction()
+    }
+
+    /// Fails with the first cell, by row and then column, that no tile fits.
+    /// Its position is given as `x` for the column and `y` for the row.
+    pub fn check_contradiction(&self) -> Result<(), WfcError> {
+        match self.wave.contradiction() {
+            Some(i) => Err(WfcError::Contradiction {
+                x: i % self.options.width,
+                y: i / self.options.width,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The tile at axial `(q, r)`, once it has collapsed.
+    pub fn tile_at(&self, q: isize, r: isize) -> Option<&'t HexTile> {
+        let i = index(self.options.width, self.options.height, q, r)?;
+        let tile = self.wave.collapsed(i)?;
+        Some(&self.tileset.tiles[tile])
+    }
+
+    /// The tile index of every cell, row by row from the leftmost hex, or
+    /// `None` if any cell has not collapsed to a single tile.
+    pub fn to_indices(&self) -> Option<Vec<Vec<usize>>> {
+        (0..self.options.height)
+            .map(|row| {
+                (0..self.options.width)
+                    .map(|column| self.wave.collapsed(row * self.options.width + column))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// The axial coordinates of the cell at `i`, stored row by row in a map
+/// `width` hexes across.
+fn axial(width: usize, i: usize) -> (isize, isize) {
+    let (column, row) = ((i % width) as isize, (i / width) as isize);
+    (column - row / 2, row)
+}
+
+/// Where the cell at axial `(q, r)` is stored, if it's on the map.
+fn index(width: usize, height: usize, q: isize, r: isize) -> Option<usize> {
+    let (width, height) = (width as isize, height as isize);
+    let column = q + r.div_euclid(2);
+    ((0..height).contains(&r) && (0..width).contains(&column))
+        .then(|| (r * width + column) as usize)
+}
@@ -0,0 +1,97 @@
+use super::{Model, Options, Tileset, WfcError};
+
+/// Generation in two passes: a coarse layout from one tileset, then a detail
+/// grid from another, `scale` cells across for every coarse cell. Each
+/// detail cell may only take the detail tiles that fit on the coarse tile
+/// above it, by [`TileConfig::fits_on`](super::TileConfig::fits_on), so the
+/// coarse layout lays out continents and the detail tiles fill them in.
+pub struct HierarchicalModel<'t, T = serde_json::Value> {
+    coarse: Model<'t, T>,
+    detail: &'t Tileset<T>,
+    scale: usize,
+}
+
+impl<'t, T> HierarchicalModel<'t, T> {
+    pub fn new(
+        coarse: Model<'t, T>,
+        detail: &'t Tileset<T>,
+        scale: usize,
+    ) -> Result<Self, WfcError> {
+        if scale == 0 {
+            return Err(WfcError::InvalidGrid(
+                "each coarse cell must be at least one detail cell across".to_string(),
+            ));
+        }
+
+        Ok(HierarchicalModel {
+            coarse,
+            detail,
+            scale,
+        })
+    }
+
+    pub fn coarse(&self) -> &Model<'t, T> {
+        &self.coarse
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    /// Runs the coarse model, if it hasn't finished already, then builds and
+    /// runs the detail model with the rest of `options`, whose size and
+    /// allowed tiles come from the coarse layout. Fails with whichever
+    /// contradiction either pass couldn't resolve, or if some coarse tile in
+    /// the layout has no detail tile that fits on it.
+    pub fn run(&mut self, options: Options) -> Result<Model<'t, T>, WfcError> {
+        if !self.coarse.grid.is_finished() {
+            self.coarse.run();
+        }
+        self.coarse.grid.check_contradiction()?;
+        let layout = self
+            .coarse
+            .grid
+            .to_indices()
+            .ok_or(WfcError::Contradiction { x: 0, y: 0 })?;
+
+        let coarse_tiles = &self.coarse.tileset.tiles;
+        let fitting: Vec<Vec<usize>> = coarse_tiles
+            .iter()
+            .map(|below| {
+                (0..self.detail.tiles.len())
+                    .filter(|&tile| self.detail.tiles[tile].fits_on(below))
+                    .collect()
+            })
+            .collect();
+        if let Some(&tile) = layout
+            .iter()
+            .flatten()
+            .find(|&&tile| fitting[tile].is_empty())
+        {
+            return Err(WfcError::InvalidTileset(format!(
+                "no detail tile fits on coarse tile `{}`",
+                coarse_tiles[tile].image
+            )));
+        }
+
+        let allowed = (0..layout.len() * self.scale)
+            .map(|y| {
+                (0..layout[0].len() * self.scale)
+                    .map(|x| fitting[layout[y / self.scale][x / self.scale]].clone())
+                    .collect()
+            })
+            .collect::<Vec<Vec<Vec<usize>>>>();
+        let mut detail = Model::new(
+            self.detail,
+            Options {
+                width: Some(allowed.first().map_or(0, Vec::len)),
+                height: Some(allowed.len()),
+                allowed: Some(allowed),
+                ..options
+            },
+        )?;
+        detail.run();
+        detail.grid.check_contradiction()?;
+        Ok(detail)
+    }
+}
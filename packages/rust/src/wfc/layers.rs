@@ -0,0 +1,314 @@
+use super::{Grid, Model, Options, RunStats, Tileset, WfcError};
+
+/// Decides whether `tile` on `layer` may sit in the same cell as `other_tile`
+/// on `other_layer`, as `((layer, tile), (other_layer, other_tile))`. It is
+/// only asked about pairs where `layer < other_layer`.
+pub type LayerConstraint = dyn Fn((usize, usize), (usize, usize)) -> bool + Send + Sync;
+
+/// Several grids of the same size, stacked so that each cell has one tile per
+/// layer, solved together under a shared [`LayerConstraint`].
+pub struct LayeredModel<'t, T = serde_json::Value> {
+    layers: Vec<Model<'t, T>>,
+    constraint: Box<LayerConstraint>,
+    /// Finishes each layer before starting on the one above, instead of
+    /// collapsing the lowest-entropy cell across all of them.
+    sequential: bool,
+    finished: bool,
+}
+
+impl<'t, T> LayeredModel<'t, T> {
+    /// Builds one layer per tileset and options pair, bottom layer first.
+    /// Every layer must resolve to the same width and height.
+    pub fn new(
+        layers: Vec<(&'t Tileset<T>, Options)>,
+        constraint: impl Fn((usize, usize), (usize, usize)) -> bool + Send + Sync + 'static,
+    ) -> Result<Self, WfcError> {
+        let layers = layers
+            .into_iter()
+            .map(|(tileset, options)| Model::new(tileset, options))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(first) = layers.first() {
+            let size = (first.settings.width, first.settings.height);
+            if let Some(layer) = layers
+                .iter()
+                .position(|layer| (layer.settings.width, layer.settings.height) != size)
+            {
+                return Err(WfcError::InvalidGrid(format!(
+                    "layer {} is {}x{}, but layer 0 is {}x{}",
+                    layer,
+                    layers[layer].settings.width,
+                    layers[layer].settings.height,
+                    size.0,
+                    size.1
+                )));
+            }
+        }
+
+        let mut model = LayeredModel {
+            layers,
+            constraint: Box::new(constraint),
+            sequential: false,
+            finished: false,
+        };
+        model.propagate_between();
+        Ok(model)
+    }
+
+    /// Stacks `options.depth` copies of one grid into a volume, bottom layer
+    /// first, where each tile's upper face must fit the lower face of the
+    /// tile above it. Each layer after the first is seeded one past the last.
+    pub fn stacked(tileset: &'t Tileset<T>, options: Options) -> Result<Self, WfcError> {
+        let depth = options.depth.unwrap_or(1);
+        let seed = options.seed;
+        let base = Model::new(
+            tileset,
+            Options {
+                depth: None,
+                ..options
+            },
+        )?;
+        let layers = (0..depth)
+            .map(|z| base.reseeded(seed.map(|seed| seed.wrapping_add(z as u64))))
+            .collect::<Vec<_>>();
+
+        let fits = tileset
+            .tiles
+            .iter()
+            .map(|below| {
+                tileset
+                    .tiles
+                    .iter()
+                    .map(|above| {
+                        let (up, down) = (&below.faces.0, &above.faces.1);
+                        below.transparent
+                            || above.transparent
+                            || up.is_wildcard()
+                            || down.is_wildcard()
+                            || up == down
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut model = LayeredModel {
+            layers,
+            constraint: Box::new(move |(layer, tile), (other, other_tile)| {
+                other != layer + 1 || fits[tile][other_tile]
+            }),
+            sequential: false,
+            finished: false,
+        };
+        model.propagate_between();
+        Ok(model)
+    }
+
+    /// Builds one layer per tileset and options pair, bottom layer first, and
+    /// generates them in that order. Each tile may only sit on a tile in the
+    /// layer below that carries one of the tags it lists as `on`, so a tree
+    /// can ask for grass.
+    pub fn sequenced(layers: Vec<(&'t Tileset<T>, Options)>) -> Result<Self, WfcError> {
+        let tilesets = layers
+            .iter()
+            .map(|(tileset, _)| *tileset)
+            .collect::<Vec<_>>();
+        let fits = tilesets
+            .windows(2)
+            .map(|pair| {
+                pair[1]
+                    .tiles
+                    .iter()
+                    .map(|above| {
+                        pair[0]
+                            .tiles
+                            .iter()
+                            .map(|below| above.fits_on(below))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut model = LayeredModel::new(layers, move |(layer, tile), (other, other_tile)| {
+            other != layer + 1 || fits[layer][other_tile][tile]
+        })?;
+        model.sequential = true;
+        Ok(model)
+    }
+
+    pub fn grid(&self, layer: usize) -> Option<&Grid<'t, T>> {
+        self.layers.get(layer).map(Model::grid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Collapses the lowest-entropy cell across every layer, preferring lower
+    /// layers on ties, then propagates within and between layers. A
+    /// [sequenced](LayeredModel::sequenced) model only looks at the lowest
+    /// layer with cells left to collapse. A layer left with a contradiction
+    /// recovers according to its own [`RecoveryMode`](super::RecoveryMode).
+    pub fn step(&mut self) {
+        let candidates = self
+            .layers
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(layer, model)| {
+                let (x, y) = model.grid.next_lowest_entropy()?;
+                Some((model.grid.entropy_at(x, y), layer, x, y))
+            });
+        let next = if self.sequential {
+            candidates.min_by_key(|candidate| candidate.1)
+        } else {
+            candidates.min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+        };
+        let Some((_, layer, x, y)) = next else {
+            self.finished = true;
+            for model in &mut self.layers {
+                model.grid.finished = true;
+            }
+            return;
+        };
+
+        let grid = &mut self.layers[layer].grid;
+        grid.trail.clear();
+        let tile = grid.collapse_at(x, y);
+        grid.record(x, y, tile);
+        self.propagate_between();
+        self.recover();
+    }
+
+    /// Steps until every layer is decided. A layer that ends in a
+    /// contradiction is started over according to its own
+    /// [`RetryPolicy`](super::RetryPolicy), fitted to the layers around it.
+    pub fn run(&mut self) {
+        let mut stats = vec![RunStats::default(); self.layers.len()];
+        loop {
+            while !self.finished {
+                self.step();
+            }
+
+            let mut retried = false;
+            for (model, stats) in self.layers.iter_mut().zip(&mut stats) {
+                if model.grid.check_contradiction().is_err() && !model.end_attempt(stats) {
+                    retried = true;
+                }
+            }
+            if !retried {
+                return;
+            }
+            self.finished = false;
+            self.propagate_between();
+        }
+    }
+
+    /// The tile index of every cell, layer by layer and then row by row, or
+    /// `None` if any cell has not collapsed to a single tile. For a stacked
+    /// model that is `[z][y][x]`.
+    pub fn to_indices(&self) -> Option<Vec<Vec<Vec<usize>>>> {
+        self.layers
+            .iter()
+            .map(|model| model.grid.to_indices())
+            .collect()
+    }
+
+    /// Fails with the first contradiction in any layer.
+    pub fn check_contradiction(&self) -> Result<(), WfcError> {
+        self.layers
+            .iter()
+            .try_for_each(|model| model.grid.check_contradiction())
+    }
+
+    /// Recovers each layer left with a contradiction, then fits the layers
+    /// to one another again.
+    fn recover(&mut self) {
+        for layer in 0..self.layers.len() {
+            let grid = &mut self.layers[layer].grid;
+            if grid.check_contradiction().is_ok() {
+                continue;
+            }
+            grid.recover();
+            self.propagate_between();
+        }
+    }
+
+    /// Removes every possibility that no tile left in another layer's copy of
+    /// the same cell permits, re-propagating within each layer it touches,
+    /// until nothing changes. What a layer loses follows from the decisions
+    /// made so far, so it's folded into that layer's latest decision, to be
+    /// restored if the decision is undone.
+    fn propagate_between(&mut self) {
+        let Some(first) = self.layers.first() else {
+            return;
+        };
+        let (width, height) = (first.settings.width, first.settings.height);
+
+        loop {
+            let mut changed = vec![false; self.layers.len()];
+
+            for y in 0..height {
+                for x in 0..width {
+                    for (layer, changed) in changed.iter_mut().enumerate() {
+                        for other in 0..self.layers.len() {
+                            if layer == other {
+                                continue;
+                            }
+
+                            let others = &self.layers[other].grid.cells[y][x].possibilities;
+                            if others.is_empty() {
+                                continue;
+                            }
+                            let unsupported: Vec<usize> = self.layers[layer].grid.cells[y][x]
+                                .possibilities
+                                .iter()
+                                .copied()
+                                .filter(|&tile| {
+                                    !others.iter().any(|&other_tile| {
+                                        if layer < other {
+                                            (self.constraint)((layer, tile), (other, other_tile))
+                                        } else {
+                                            (self.constraint)((other, other_tile), (layer, tile))
+                                        }
+                                    })
+                                })
+                                .collect();
+
+                            let grid = &mut self.layers[layer].grid;
+                            for tile in unsupported {
+                                grid.remove(x, y, tile);
+                                *changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !changed.contains(&true) {
+                break;
+            }
+            for (model, changed) in self.layers.iter_mut().zip(changed) {
+                if changed {
+                    model.grid.propagate();
+                    model.grid.orient_collapsed();
+                }
+            }
+        }
+
+        for model in &mut self.layers {
+            let grid = &mut model.grid;
+            let removed = std::mem::take(&mut grid.trail);
+            if let Some(latest) = grid.history.last_mut() {
+                latest.removed.extend(removed);
+            }
+        }
+    }
+}
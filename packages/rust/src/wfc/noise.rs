@@ -0,0 +1,82 @@
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Scales the weight of every tile with `tag` by a smooth noise field, so that
+/// tagged tiles clump into large blobs, like continents or biomes, instead of
+/// scattering evenly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseBias {
+    pub tag: String,
+    /// How strongly the noise sways the weight. Where the noise is `n`, from
+    /// -1 to 1, tagged tiles weigh `1 + amplitude * n` times as much, but never
+    /// less than nothing.
+    pub amplitude: f64,
+    /// How many noise features span each cell. Lower frequencies make bigger
+    /// blobs.
+    pub frequency: f64,
+}
+
+impl Default for NoiseBias {
+    fn default() -> Self {
+        NoiseBias {
+            tag: String::new(),
+            amplitude: 1.0,
+            frequency: 0.1,
+        }
+    }
+}
+
+impl NoiseBias {
+    /// The weight multiplier for tagged tiles where `noise` reads `n`.
+    pub(super) fn multiplier(&self, n: f64) -> f64 {
+        (1.0 + self.amplitude * n).max(0.0)
+    }
+}
+
+/// Two-dimensional Perlin noise, shuffled by a seed.
+#[derive(Clone, Debug)]
+pub(super) struct Perlin {
+    /// A permutation of 0 to 255, written out twice so lookups never wrap.
+    perm: Vec<u8>,
+}
+
+impl Perlin {
+    pub(super) fn new(seed: u64) -> Self {
+        let mut perm: Vec<u8> = (0..=255).collect();
+        perm.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+        perm.extend_from_within(..);
+        Perlin { perm }
+    }
+
+    /// The noise at `(x, y)`, from -1 to 1, and 0 on every integer point.
+    pub(super) fn at(&self, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (i, j) = ((x0 as i64 & 255) as usize, (y0 as i64 & 255) as usize);
+        let (dx, dy) = (x - x0, y - y0);
+
+        let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+        let corner = |ci: usize, cj: usize, gx: f64, gy: f64| {
+            let hash = self.perm[self.perm[i + ci] as usize + j + cj];
+            match hash & 7 {
+                0 => gx + gy,
+                1 => gx - gy,
+                2 => -gx + gy,
+                3 => -gx - gy,
+                4 => gx,
+                5 => -gx,
+                6 => gy,
+                _ => -gy,
+            }
+        };
+
+        let (u, v) = (fade(dx), fade(dy));
+        let top = lerp(u, corner(0, 0, dx, dy), corner(1, 0, dx - 1.0, dy));
+        let bottom = lerp(
+            u,
+            corner(0, 1, dx, dy - 1.0),
+            corner(1, 1, dx - 1.0, dy - 1.0),
+        );
+        lerp(v, top, bottom).clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,212 @@
+use super::{wave::Wave, Edge, WfcError, EDGES};
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct OverlapOptions {
+    /// The side of each square pattern taken from the sample, in pixels.
+    pub n: usize,
+    pub width: usize,
+    pub height: usize,
+    pub seed: Option<u64>,
+    /// Lets patterns wrap around the sample's edges, for samples that tile.
+    pub periodic_input: bool,
+}
+
+impl Default for OverlapOptions {
+    fn default() -> Self {
+        OverlapOptions {
+            n: 3,
+            width: 48,
+            height: 48,
+            seed: None,
+            periodic_input: false,
+        }
+    }
+}
+
+/// The overlapping model: instead of a tileset, it learns every NxN pattern in
+/// a sample image along with how often it occurs, then synthesizes an output
+/// in which every NxN window is one of those patterns. Two patterns may sit
+/// side by side when they agree on every pixel they overlap.
+#[derive(Debug)]
+pub struct OverlappingModel {
+    options: OverlapOptions,
+    /// Each pattern's pixels, row by row.
+    patterns: Vec<Vec<Rgba<u8>>>,
+    /// One cell per pattern position, `(width - n + 1)` by `(height - n + 1)`,
+    /// row by row, weighted by how often each pattern occurs in the sample.
+    /// Each pattern covers the pixels below and to the right of its position.
+    wave: Wave,
+}
+
+impl OverlappingModel {
+    pub fn new(sample: &RgbaImage, options: OverlapOptions) -> Result<Self, WfcError> {
+        let n = options.n;
+        let (sample_width, sample_height) = (sample.width() as usize, sample.height() as usize);
+        if n == 0 || n > sample_width || n > sample_height {
+            return Err(WfcError::InvalidGrid(format!(
+                "a {}x{} sample has no {}x{} patterns",
+                sample_width, sample_height, n, n
+            )));
+        }
+        if n > options.width || n > options.height {
+            return Err(WfcError::InvalidGrid(format!(
+                "a {}x{} output can't hold a {}x{} pattern",
+                options.width, options.height, n, n
+            )));
+        }
+
+        let (columns, rows) = if options.periodic_input {
+            (sample_width, sample_height)
+        } else {
+            (sample_width - n + 1, sample_height - n + 1)
+        };
+        let mut patterns = Vec::new();
+        let mut weights = Vec::new();
+        let mut index = HashMap::new();
+        for y in 0..rows {
+            for x in 0..columns {
+                let pattern = (0..n * n)
+                    .map(|i| {
+                        let px = (x + i % n) % sample_width;
+                        let py = (y + i / n) % sample_height;
+                        *sample.get_pixel(px as u32, py as u32)
+                    })
+                    .collect::<Vec<_>>();
+                let id = *index.entry(pattern.clone()).or_insert_with(|| {
+                    patterns.push(pattern);
+                    weights.push(0.0);
+                    patterns.len() - 1
+                });
+                weights[id] += 1.0;
+            }
+        }
+
+        let compatible = patterns
+            .iter()
+            .map(|pattern| {
+                EDGES
+                    .iter()
+                    .map(|&edge| {
+                        (0..patterns.len())
+                            .filter(|&other| agrees(pattern, &patterns[other], n, edge))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (columns, rows) = (options.width - n + 1, options.height - n + 1);
+        let neighbors = (0..columns * rows)
+            .map(|i| {
+                let (x, y) = (i % columns, i / columns);
+                [
+                    (y > 0).then(|| i - columns),
+                    (x + 1 < columns).then(|| i + 1),
+                    (y + 1 < rows).then(|| i + columns),
+                    (x > 0).then(|| i - 1),
+                ]
+                .into_iter()
+                .enumerate()
+                .filter_map(|(edge, neighbor)| Some((neighbor?, edge)))
+                .collect()
+            })
+            .collect();
+
+        let wave = Wave::new(weights, compatible, neighbors, options.seed);
+        Ok(OverlappingModel {
+            options,
+            patterns,
+            wave,
+        })
+    }
+
+    /// How many distinct patterns the sample holds.
+    pub fn pattern_count(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.wave.is_finished()
+    }
+
+    /// Collapses the lowest-entropy position to a pattern, weighted by how
+    /// often each occurs in the sample, and propagates the result. A
+    /// contradiction starts the output over, up to a limit.
+    pub fn step(&mut self) {
+        self.wave.step();
+    }
+
+    /// Steps until every position is decided, failing with the first
+    /// contradiction left once restarts run out.
+    pub fn run(&mut self) -> Result<(), WfcError> {
+        while !self.wave.is_finished() {
+            self.wave.step();
+        }
+        self.check_contradiction()
+    }
+
+    /// Fails with the first pattern position, in row-major order, that no
+    /// pattern fits.
+    pub fn check_contradiction(&self) -> Result<(), WfcError> {
+        match self.wave.contradiction() {
+            Some(i) => {
+                let columns = self.positions().0;
+                Err(WfcError::Contradiction {
+                    x: i % columns,
+                    y: i / columns,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The output image, or `None` until every position is decided without a
+    /// contradiction.
+    pub fn render(&self) -> Option<RgbaImage> {
+        let n = self.options.n;
+        let (columns, rows) = self.positions();
+        let patterns = (0..columns * rows)
+            .map(|i| self.wave.collapsed(i))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(RgbaImage::from_fn(
+            self.options.width as u32,
+            self.options.height as u32,
+            |x, y| {
+                // Positions stop short of the right and bottom edges, so
+                // those pixels come from the last pattern that covers them.
+                let (x, y) = (x as usize, y as usize);
+                let (cx, cy) = (x.min(columns - 1), y.min(rows - 1));
+                self.patterns[patterns[cy * columns + cx]][(y - cy) * n + (x - cx)]
+            },
+        ))
+    }
+
+    /// How many pattern positions fit across and down the output.
+    fn positions(&self) -> (usize, usize) {
+        let n = self.options.n;
+        (self.options.width - n + 1, self.options.height - n + 1)
+    }
+}
+
+/// Whether `other`, shifted one pixel past `edge` of `pattern`, matches it on
+/// every pixel they share.
+fn agrees(pattern: &[Rgba<u8>], other: &[Rgba<u8>], n: usize, edge: Edge) -> bool {
+    let (dx, dy): (isize, isize) = match edge {
+        Edge::Top => (0, -1),
+        Edge::Right => (1, 0),
+        Edge::Bottom => (0, 1),
+        Edge::Left => (-1, 0),
+    };
+    let n = n as isize;
+    (0..n).all(|y| {
+        (0..n).all(|x| {
+            let (ox, oy) = (x - dx, y - dy);
+            !(0..n).contains(&ox)
+                || !(0..n).contains(&oy)
+                || pattern[(y * n + x) as usize] == other[(oy * n + ox) as usize]
+        })
+    })
+}
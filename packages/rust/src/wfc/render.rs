@@ -0,0 +1,162 @@
+use super::{Grid, ImageResolver, RgbaImage, WfcError};
+use image::Rgba;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Uncollapsed cells are drawn as an even blend of their candidates.
+    #[default]
+    Flat,
+    /// Uncollapsed cells are drawn as a blend of their candidates, each
+    /// contributing in proportion to its weight.
+    Weighted,
+}
+
+impl<'t, T> Grid<'t, T> {
+    /// Composites the grid into a single image, using `images` (indexed by
+    /// tile, as returned by [`super::Tileset::load_images`]) for each tile.
+    /// Collapsed cells are drawn as-is, transparent tiles are skipped, and
+    /// cells with no possibilities are left empty.
+    pub fn render(&self, images: &[RgbaImage], mode: RenderMode) -> RgbaImage {
+        let size = self.tileset.size;
+        let mut output = RgbaImage::new(
+            self.options.width as u32 * size,
+            self.options.height as u32 * size,
+        );
+
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let candidates = cell
+                    .possibilities
+                    .iter()
+                    .map(|&tile| {
+                        let weight = match mode {
+                            RenderMode::Flat => 1.0,
+                            RenderMode::Weighted => self.weight(tile),
+                        };
+                        // Transparent tiles take their share of the blend,
+                        // but draw nothing whatever their image.
+                        let image = (!self.tileset.tiles[tile].transparent).then(|| &images[tile]);
+                        (image, weight)
+                    })
+                    .collect::<Vec<_>>();
+                let total: f64 = candidates.iter().map(|(_, weight)| weight).sum();
+                if candidates.is_empty() || total <= 0.0 {
+                    continue;
+                }
+                // Tiles turned on the fly are stored unturned in `images`.
+                let turn = self.turns[y][x]
+                    .filter(|_| cell.is_collapsed())
+                    .unwrap_or(0);
+
+                for py in 0..size {
+                    for px in 0..size {
+                        let mut pixel = [0.0; 4];
+                        for (image, weight) in &candidates {
+                            let Some(image) = image else {
+                                continue;
+                            };
+                            let (sx, sy) = match turn {
+                                1 => (py, size - 1 - px),
+                                2 => (size - 1 - px, size - 1 - py),
+                                3 => (size - 1 - py, px),
+                                _ => (px, py),
+                            };
+                            let Rgba(channels) = image.get_pixel(sx, sy);
+                            for (sum, &channel) in pixel.iter_mut().zip(channels) {
+                                *sum += channel as f64 * weight / total;
+                            }
+                        }
+
+                        output.put_pixel(
+                            x as u32 * size + px,
+                            y as u32 * size + py,
+                            Rgba(pixel.map(|channel| channel.round() as u8)),
+                        );
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Loads the tile images through `resolver`, then renders the grid with
+    /// them. Prefer [`Grid::render`] with images loaded once when rendering
+    /// repeatedly.
+    pub fn render_with(
+        &self,
+        resolver: &impl ImageResolver,
+        mode: RenderMode,
+    ) -> Result<RgbaImage, WfcError> {
+        let images = self.tileset.load_images_with(resolver)?;
+        Ok(self.render(&images, mode))
+    }
+
+    /// Renders the grid like [`Grid::render`], then prints each cell's
+    /// remaining possibility count in its top-left corner.
+    pub fn render_annotated(&self, images: &[RgbaImage], mode: RenderMode) -> RgbaImage {
+        let mut output = self.render(images, mode);
+        let size = self.tileset.size;
+        let scale = (size / 16).max(1);
+
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let label = cell.possibilities.len().to_string();
+                let width = (label.len() as u32 * (GLYPH_WIDTH + 1) + 1) * scale;
+                let height = (GLYPH_HEIGHT + 2) * scale;
+                let (left, top) = (x as u32 * size, y as u32 * size);
+
+                let mut plot = |px: u32, py: u32, color: Rgba<u8>| {
+                    if px < size && py < size {
+                        output.put_pixel(left + px, top + py, color);
+                    }
+                };
+
+                for py in 0..height {
+                    for px in 0..width {
+                        plot(px, py, Rgba([0, 0, 0, 255]));
+                    }
+                }
+                for (i, digit) in label.bytes().enumerate() {
+                    let glyph = DIGITS[(digit - b'0') as usize];
+                    let offset = i as u32 * (GLYPH_WIDTH + 1) + 1;
+                    for (gy, bits) in glyph.iter().enumerate() {
+                        for gx in 0..GLYPH_WIDTH {
+                            if bits >> (GLYPH_WIDTH - 1 - gx) & 1 == 0 {
+                                continue;
+                            }
+                            for sy in 0..scale {
+                                for sx in 0..scale {
+                                    plot(
+                                        (offset + gx) * scale + sx,
+                                        (gy as u32 + 1) * scale + sy,
+                                        Rgba([255, 255, 255, 255]),
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        output
+    }
+}
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// A 3x5 bitmap font for the digits 0-9, one row of bits per line.
+const DIGITS: [[u8; GLYPH_HEIGHT as usize]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
@@ -0,0 +1,103 @@
+use super::{RgbaImage, WfcError};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Turns a tile's `image` string into pixels.
+pub trait ImageResolver {
+    fn resolve(&self, image: &str) -> Result<RgbaImage, WfcError>;
+}
+
+/// Reads images from the filesystem, relative to the tileset's directory.
+#[derive(Clone, Debug)]
+pub struct FileResolver {
+    dir: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        FileResolver {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl ImageResolver for FileResolver {
+    fn resolve(&self, image: &str) -> Result<RgbaImage, WfcError> {
+        Ok(image::open(self.dir.join(image))?.into_rgba8())
+    }
+}
+
+/// Decodes images held in memory, such as ones embedded with
+/// `include_bytes!`, keyed by their `image` string.
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddedResolver {
+    images: HashMap<String, Vec<u8>>,
+}
+
+impl EmbeddedResolver {
+    pub fn insert(&mut self, image: impl Into<String>, bytes: impl Into<Vec<u8>>) -> &mut Self {
+        self.images.insert(image.into(), bytes.into());
+        self
+    }
+}
+
+impl ImageResolver for EmbeddedResolver {
+    fn resolve(&self, image: &str) -> Result<RgbaImage, WfcError> {
+        let bytes = self.images.get(image).ok_or_else(|| missing(image))?;
+        Ok(image::load_from_memory(bytes)?.into_rgba8())
+    }
+}
+
+/// Crops square images out of one atlas, keyed by their `image` string and
+/// located by the pixel offset of their top-left corner.
+#[derive(Clone, Debug)]
+pub struct AtlasResolver {
+    atlas: RgbaImage,
+    size: u32,
+    offsets: HashMap<String, (u32, u32)>,
+}
+
+impl AtlasResolver {
+    pub fn new(atlas: RgbaImage, size: u32) -> Self {
+        AtlasResolver {
+            atlas,
+            size,
+            offsets: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, image: impl Into<String>, offset: (u32, u32)) -> &mut Self {
+        self.offsets.insert(image.into(), offset);
+        self
+    }
+}
+
+impl ImageResolver for AtlasResolver {
+    fn resolve(&self, image: &str) -> Result<RgbaImage, WfcError> {
+        let &(x, y) = self.offsets.get(image).ok_or_else(|| missing(image))?;
+        // Offsets near `u32::MAX` would overflow, and run off any atlas.
+        let fits = |start: u32, extent: u32| {
+            start
+                .checked_add(self.size)
+                .is_some_and(|end| end <= extent)
+        };
+        if !fits(x, self.atlas.width()) || !fits(y, self.atlas.height()) {
+            return Err(WfcError::InvalidTileset(format!(
+                "image `{}` at ({}, {}) runs off the {}x{} atlas",
+                image,
+                x,
+                y,
+                self.atlas.width(),
+                self.atlas.height()
+            )));
+        }
+
+        Ok(image::imageops::crop_imm(&self.atlas, x, y, self.size, self.size).to_image())
+    }
+}
+
+fn missing(image: &str) -> WfcError {
+    WfcError::InvalidTileset(format!("no image found for `{}`", image))
+}
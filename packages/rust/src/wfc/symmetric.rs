@@ -0,0 +1,176 @@
+use super::{Adjacency, Edge, Grid, Model, Options, Reflection, TileConfig, Tileset, WfcError};
+
+/// Output that is its own mirror image. Only half of the grid, or a quarter
+/// for [`Reflection::Both`], is generated, and the rest is filled with the
+/// mirror image of each tile. Cells on the seam are held to tiles that
+/// connect to their own mirror image, and tiles without one are left out.
+pub struct SymmetricModel<'t, T = serde_json::Value> {
+    tileset: &'t Tileset<T>,
+    reflect: Reflection,
+    width: usize,
+    height: usize,
+    /// The generated part of the grid, in the top left.
+    part: Model<'t, T>,
+    /// Each tile's mirror image left to right, and top to bottom, if the
+    /// tileset has one.
+    across: Vec<Option<usize>>,
+    down: Vec<Option<usize>>,
+}
+
+impl<'t, T: Clone> SymmetricModel<'t, T> {
+    /// Builds a model for the whole grid that `options` describes, mirrored
+    /// by `reflect`. `options.allowed` is replaced by the tiles each
+    /// generated cell may take. Tiles can't be turned on the fly.
+    pub fn new(
+        tileset: &'t Tileset<T>,
+        options: Options,
+        reflect: Reflection,
+    ) -> Result<Self, WfcError> {
+        if let Some(tile) = tileset.tiles.iter().find(|tile| tile.rotatable) {
+            return Err(WfcError::InvalidTileset(format!(
+                "tile `{}` is rotatable, which symmetric output doesn't support",
+                tile.image
+            )));
+        }
+
+        let width = options.width.unwrap_or(super::DEFAULT_WIDTH);
+        let height = options.height.unwrap_or(super::DEFAULT_HEIGHT);
+        let (flip_x, flip_y) = match reflect {
+            Reflection::Horizontal => (true, false),
+            Reflection::Vertical => (false, true),
+            Reflection::Both => (true, true),
+        };
+        let across = mirrors(tileset, |tile| tile.mirrored());
+        let down = mirrors(tileset, |tile| tile.mirrored().rotated().rotated());
+        let adjacency = Adjacency::new(tileset, &options.forbidden)?;
+
+        // Every tile needs a mirror image to fill the rest of the grid, and
+        // on a seam it also needs to fit against it, or be it when the seam
+        // is the middle of an odd-sized grid.
+        let fits = |tile: usize, mirror: Option<usize>, edge: Edge, odd: bool| match mirror {
+            None => false,
+            Some(_) if odd => mirror == Some(tile),
+            Some(mirror) => adjacency.allows(tile, edge, mirror),
+        };
+        let part_width = if flip_x { width.div_ceil(2) } else { width };
+        let part_height = if flip_y { height.div_ceil(2) } else { height };
+        let allowed = (0..part_height)
+            .map(|y| {
+                (0..part_width)
+                    .map(|x| {
+                        (0..tileset.tiles.len())
+                            .filter(|&tile| {
+                                let seam_x = flip_x && x + 1 == part_width;
+                                let seam_y = flip_y && y + 1 == part_height;
+                                (!flip_x || across[tile].is_some())
+                                    && (!flip_y || down[tile].is_some())
+                                    && (!(flip_x && flip_y)
+                                        || across[tile].and_then(|mirror| down[mirror]).is_some())
+                                    && (!seam_x
+                                        || fits(tile, across[tile], Edge::Right, width % 2 == 1))
+                                    && (!seam_y
+                                        || fits(tile, down[tile], Edge::Bottom, height % 2 == 1))
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let part = Model::new(
+            tileset,
+            Options {
+                width: Some(part_width),
+                height: Some(part_height),
+                allowed: Some(allowed),
+                ..options
+            },
+        )?;
+
+        Ok(SymmetricModel {
+            tileset,
+            reflect,
+            width,
+            height,
+            part,
+            across,
+            down,
+        })
+    }
+
+    /// The part of the grid that is generated rather than mirrored.
+    pub fn part(&self) -> &Model<'t, T> {
+        &self.part
+    }
+
+    pub fn reflect(&self) -> Reflection {
+        self.reflect
+    }
+
+    /// Generates the part of the grid, then mirrors it into a whole grid.
+    /// Fails with the first contradiction the part was left with.
+    pub fn run(&mut self) -> Result<Grid<'t, T>, WfcError> {
+        self.part.run();
+        self.part.grid.check_contradiction()?;
+        let part = self
+            .part
+            .grid
+            .to_indices()
+            .ok_or(WfcError::Contradiction { x: 0, y: 0 })?;
+
+        let (part_width, part_height) = (part.first().map_or(0, Vec::len), part.len());
+        let indices: Vec<Vec<usize>> = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        let (flip_x, flip_y) = (x >= part_width, y >= part_height);
+                        let px = if flip_x { self.width - 1 - x } else { x };
+                        let py = if flip_y { self.height - 1 - y } else { y };
+                        let mut tile = part[py][px];
+                        if flip_x {
+                            tile = self.across[tile].expect("generated tiles have mirrors");
+                        }
+                        if flip_y {
+                            tile = self.down[tile].expect("generated tiles have mirrors");
+                        }
+                        tile
+                    })
+                    .collect()
+            })
+            .collect();
+        Grid::from_indices(self.tileset, &indices)
+    }
+}
+
+/// For every tile, the tile among its variants whose sockets are those of
+/// `flip` applied to it, if there is one.
+fn mirrors<T: Clone>(
+    tileset: &Tileset<T>,
+    flip: impl Fn(&TileConfig<T>) -> TileConfig<T>,
+) -> Vec<Option<usize>> {
+    tileset
+        .tiles
+        .iter()
+        .enumerate()
+        .map(|(index, tile)| {
+            if tile.transparent {
+                return Some(index);
+            }
+            let flipped = flip(tile);
+            let candidates = (0..tileset.tiles.len()).filter(|&other| {
+                let other = &tileset.tiles[other];
+                other.image == tile.image && other.sockets == flipped.sockets
+            });
+            // Prefer the variant drawn as the mirror image, then the tile
+            // itself, whose sockets are then symmetric.
+            candidates
+                .clone()
+                .find(|&other| {
+                    let other = &tileset.tiles[other];
+                    (other.mirrored, other.rotation) == (flipped.mirrored, flipped.rotation)
+                })
+                .or_else(|| candidates.clone().find(|&other| other == index))
+                .or_else(|| candidates.clone().next())
+        })
+        .collect()
+}
@@ -0,0 +1,3573 @@
+use super::*;
+
+const COAST: &str = include_str!("../../tests/fixtures/coast.json");
+/// Tiles whose sockets are arc consistent but routinely dead-end.
+const TANGLE: &str = include_str!("../../tests/fixtures/tangle.json");
+const PIXIE: &str = include_str!("../../../../tilesets/pixie/tileset.json");
+
+/// Grass above a single coastline, with water and lilies mixed below it.
+const COAST_SEED: u64 = 8;
+const COAST_GOLDEN: [[usize; 6]; 4] = [
+    [0, 0, 0, 0, 0, 0],
+    [1, 1, 1, 1, 1, 1],
+    [3, 2, 3, 2, 3, 3],
+    [3, 2, 2, 3, 3, 3],
+];
+
+fn golden() -> Vec<Vec<usize>> {
+    COAST_GOLDEN.map(Vec::from).to_vec()
+}
+
+fn tileset(json: &str) -> Tileset {
+    serde_json::from_str(json).unwrap()
+}
+
+fn model(tileset: &Tileset, width: usize, height: usize, seed: u64) -> Model<'_> {
+    model_with(tileset, width, height, seed, Propagator::Worklist)
+}
+
+fn model_with(
+    tileset: &Tileset,
+    width: usize,
+    height: usize,
+    seed: u64,
+    propagator: Propagator,
+) -> Model<'_> {
+    Model::builder(tileset)
+        .width(width)
+        .height(height)
+        .seed(seed)
+        .propagator(propagator)
+        .build()
+        .unwrap()
+}
+
+fn possibilities(grid: &Grid) -> Vec<BTreeSet<usize>> {
+    grid.cells
+        .iter()
+        .flatten()
+        .map(|cell| cell.possibilities.clone())
+        .collect()
+}
+
+#[test]
+fn coast_matches_golden() {
+    let tileset = tileset(COAST);
+    let mut model = model(&tileset, 6, 4, COAST_SEED);
+    model.run();
+
+    assert!(model.grid().is_finished());
+    assert_eq!(model.grid().to_indices(), Some(golden()));
+}
+
+#[test]
+fn coast_matches_golden_with_support_counts() {
+    let tileset = tileset(COAST);
+    let mut model = model_with(&tileset, 6, 4, COAST_SEED, Propagator::SupportCount);
+    model.run();
+
+    assert_eq!(model.grid().to_indices(), Some(golden()));
+}
+
+#[test]
+fn symmetry_generates_distinct_rotations() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]], "symmetry": "X" },
+                { "image": "road.png", "sockets": [[1], [0], [1], [0]], "symmetry": "I" },
+                { "image": "bend.png", "sockets": [[1], [1], [0], [0]], "symmetry": "L" },
+                { "image": "slash.png", "sockets": [[2], [3], [3], [2]], "symmetry": "\\" },
+                { "image": "zigzag.png", "sockets": [[4, 5], [0], [4, 5], [0]], "symmetry": "S" },
+                { "image": "flag.png", "sockets": [[0], [0], [1], [2]], "symmetry": "F" }
+            ]
+        }"#,
+    );
+
+    let rotations = tileset
+        .tiles
+        .iter()
+        .map(TileConfig::rotation)
+        .collect::<Vec<_>>();
+    assert_eq!(rotations[..9], [0, 0, 1, 0, 1, 2, 3, 0, 1]);
+
+    let orientations = |image: &str| {
+        tileset
+            .tiles
+            .iter()
+            .filter(|tile| tile.image == image)
+            .map(|tile| (tile.is_mirrored(), tile.rotation()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        orientations("zigzag.png"),
+        [(false, 0), (false, 1), (true, 0), (true, 3)]
+    );
+    assert_eq!(orientations("flag.png").len(), 8);
+
+    let sockets = |tile: &TileConfig| {
+        let (top, right, bottom, left) = &tile.sockets;
+        [top, right, bottom, left].map(|Socket(socks)| socks[0].0)
+    };
+    assert_eq!(sockets(&tileset.tiles[2]), [0, 1, 0, 1]);
+    assert_eq!(sockets(&tileset.tiles[4]), [0, 1, 1, 0]);
+    assert_eq!(sockets(&tileset.tiles[6]), [1, 0, 0, 1]);
+    assert_eq!(sockets(&tileset.tiles[8]), [2, 2, 3, 3]);
+}
+
+#[test]
+fn rotations_generate_turned_variants() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]], "rotations": false },
+                { "image": "bend.png", "sockets": [[1], [1], [0], [0]], "rotations": true },
+                { "image": "road.png", "sockets": [[1], [0], [1], [0]], "rotations": 2 }
+            ]
+        }"#,
+    );
+
+    let rotations = tileset
+        .tiles
+        .iter()
+        .map(|tile| (tile.image.as_str(), tile.rotation()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rotations,
+        [
+            ("grass.png", 0),
+            ("bend.png", 0),
+            ("bend.png", 1),
+            ("bend.png", 2),
+            ("bend.png", 3),
+            ("road.png", 0),
+            ("road.png", 1)
+        ]
+    );
+    assert_eq!(tileset.tiles[3].sockets.0, Socket(vec![Sock(0)]));
+    assert_eq!(tileset.tiles[3].sockets.2, Socket(vec![Sock(1)]));
+
+    let error = |tile: &str| {
+        let json = format!(r#"{{ "size": 16, "tiles": [{}] }}"#, tile);
+        serde_json::from_str::<Tileset>(&json)
+            .unwrap_err()
+            .to_string()
+    };
+    assert!(error(r#"{ "image": "a.png", "rotations": 5 }"#)
+        .contains("tile `a.png`: `rotations` must be a boolean or a count from 1 to 4"));
+    assert!(
+        error(r#"{ "image": "a.png", "rotations": 4, "symmetry": "L" }"#)
+            .contains("tile `a.png` declares both `symmetry` and `rotations`")
+    );
+}
+
+#[test]
+fn reflections_generate_mirrored_variants() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "bend.png", "sockets": [[1, 2], [3], [4], [5]], "reflect": "both" },
+                { "image": "flag.png", "sockets": [[0], [0], [1], [2]], "rotations": true, "reflect": "both" }
+            ]
+        }"#,
+    );
+
+    let socks = |tile: &TileConfig| {
+        let (top, right, bottom, left) = &tile.sockets;
+        [top, right, bottom, left]
+            .map(|Socket(socks)| socks.iter().map(|sock| sock.0).collect::<Vec<_>>())
+    };
+    let bends = &tileset.tiles[..3];
+    assert_eq!(
+        bends
+            .iter()
+            .map(|tile| (tile.is_mirrored(), tile.rotation()))
+            .collect::<Vec<_>>(),
+        [(false, 0), (true, 0), (true, 2)]
+    );
+    assert_eq!(socks(&bends[1]), [vec![2, 1], vec![5], vec![4], vec![3]]);
+    assert_eq!(socks(&bends[2]), [vec![4], vec![3], vec![2, 1], vec![5]]);
+
+    // Every rotation and its mirror image, with no vertical flip repeated.
+    assert_eq!(tileset.tiles.len(), 3 + 8);
+}
+
+#[test]
+fn tiles_sharing_an_image_stay_distinct() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "stripe.png", "sockets": [[0], [1], [0], [2]] },
+                { "image": "stripe.png", "sockets": [[0], [2], [0], [1]] }
+            ]
+        }"#,
+    );
+    assert_ne!(tileset.tiles[0], tileset.tiles[1]);
+
+    let mut model = model(&tileset, 6, 6, 0);
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    let used: BTreeSet<usize> = indices.iter().flatten().copied().collect();
+    assert_eq!(used, BTreeSet::from([0, 1]));
+}
+
+#[test]
+fn malformed_sockets_name_their_tile_and_edge() {
+    let error = |sockets: &str| {
+        let json = format!(
+            r#"{{ "size": 16, "tiles": [{{ "image": "road.png", "sockets": {} }}] }}"#,
+            sockets
+        );
+        serde_json::from_str::<Tileset>(&json)
+            .unwrap_err()
+            .to_string()
+    };
+
+    let message = error("[[0], [0], [0]]");
+    assert!(
+        message.contains("tile `road.png` has 3 sockets"),
+        "{}",
+        message
+    );
+    let message = error("[[0], [0], [0, -1], [0]]");
+    assert!(
+        message.contains("tile `road.png`, edge 2 (Bottom)"),
+        "{}",
+        message
+    );
+    let message = error("[[0], 1, [0], [0]]");
+    assert!(
+        message.contains("edge 1 (Right): expected an array"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn collapse_follows_tile_weights() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "weight": 9 },
+                { "image": "flowers.png" }
+            ]
+        }"#,
+    );
+    let mut model = model(&tileset, 20, 20, 0);
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    let grass = indices.iter().flatten().filter(|&&tile| tile == 0).count();
+    assert!((300..400).contains(&grass), "{} grass tiles", grass);
+}
+
+#[test]
+fn invalid_weights_are_rejected() {
+    let negative = tileset(r#"{ "size": 16, "tiles": [{ "image": "a.png", "weight": -1 }] }"#);
+    let result = Model::new(&negative, Options::default());
+    assert!(matches!(result, Err(WfcError::InvalidTileset(_))));
+
+    let tileset = tileset(COAST);
+    let options = |tile, weight| Options {
+        weight_overrides: HashMap::from([(tile, weight)]),
+        ..Default::default()
+    };
+    assert!(matches!(
+        Model::new(&tileset, options(9, 1.0)),
+        Err(WfcError::UnknownTile(9))
+    ));
+    assert!(matches!(
+        Model::new(&tileset, options(0, f64::NAN)),
+        Err(WfcError::InvalidTileset(_))
+    ));
+}
+
+#[test]
+fn shannon_entropy_accounts_for_weights() {
+    let cell = Cell {
+        possibilities: BTreeSet::from([0, 1]),
+    };
+    let even = cell.shannon_entropy(&[1.0, 1.0]);
+    let skewed = cell.shannon_entropy(&[9.0, 1.0]);
+    assert!((even - 2f64.ln()).abs() < 1e-12);
+    assert!(skewed < even && skewed > 0.0);
+    assert_eq!(cell.shannon_entropy(&[0.0, 5.0]), 0.0);
+
+    // Two candidates, one dominant, are less uncertain than three even ones.
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "a.png", "weight": 20 },
+                { "image": "b.png" },
+                { "image": "c.png", "weight": 20 }
+            ]
+        }"#,
+    );
+    let mut model = model(&tileset, 2, 1, 0);
+    let grid = model.grid_mut();
+    grid.cells[0][0].possibilities = BTreeSet::from([0, 1, 2]);
+    grid.cells[0][1].possibilities = BTreeSet::from([0, 1]);
+    assert_eq!(grid.next_lowest_entropy(), Some((1, 0)));
+}
+
+#[test]
+fn normalized_weights_sum_to_one() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "a.png", "weight": 3 },
+                { "image": "b.png" }
+            ]
+        }"#,
+    );
+    assert_eq!(tileset.weight_sum(), 4.0);
+    assert_eq!(tileset.normalized_weights(), vec![0.75, 0.25]);
+}
+
+#[test]
+fn rotatable_tiles_turn_to_fit() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "blank.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "corner.png", "sockets": [[1], [1], [0], [0]], "rotatable": true, "weight": 3 }
+            ]
+        }"#,
+    );
+    for seed in 0..8 {
+        let options = Options {
+            width: Some(8),
+            height: Some(8),
+            seed: Some(seed),
+            recovery: Some(RecoveryMode::Restart),
+            ..Default::default()
+        };
+        let mut model = Model::new(&tileset, options).unwrap();
+        model.run();
+        let grid = model.grid();
+        assert!(grid.check_contradiction().is_ok());
+
+        let indices = grid.to_indices().unwrap();
+        let mut turns = BTreeSet::new();
+        for (y, row) in indices.iter().enumerate() {
+            for (x, &tile) in row.iter().enumerate() {
+                let turn = grid.rotation_at(x, y).unwrap();
+                if tile == 1 {
+                    turns.insert(turn);
+                }
+                for (edge, nx, ny) in grid.neighbors(x, y) {
+                    let other = (indices[ny][nx], grid.rotation_at(nx, ny).unwrap());
+                    assert!(grid.adjacency.oriented_strength((tile, turn), edge, other) > 0.0);
+                }
+            }
+        }
+        assert!(turns.len() > 1, "seed {}: {:?}", seed, turns);
+    }
+}
+
+#[test]
+fn indices_round_trip() {
+    let tileset = tileset(COAST);
+    let grid = Grid::from_indices(&tileset, &golden()).unwrap();
+
+    assert!(grid.is_finished());
+    assert_eq!(grid.to_indices(), Some(golden()));
+    assert!(matches!(
+        Grid::from_indices(&tileset, &[vec![0, 1], vec![4, 0]]),
+        Err(WfcError::UnknownTile(4))
+    ));
+    assert!(matches!(
+        Grid::from_indices(&tileset, &[vec![0, 1], vec![0]]),
+        Err(WfcError::InvalidGrid(_))
+    ));
+}
+
+#[test]
+fn resolvers_load_from_memory_and_atlases() {
+    let tileset = tileset(
+        r#"{
+            "size": 2,
+            "tiles": [
+                { "image": "red.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "blue.png", "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    let red = image::Rgba([255, 0, 0, 255]);
+    let blue = image::Rgba([0, 0, 255, 255]);
+
+    let mut atlas = RgbaImage::from_pixel(4, 2, red);
+    for (x, y) in [(2, 0), (3, 0), (2, 1), (3, 1)] {
+        atlas.put_pixel(x, y, blue);
+    }
+    let mut resolver = AtlasResolver::new(atlas.clone(), 2);
+    resolver
+        .insert("red.png", (0, 0))
+        .insert("blue.png", (2, 0));
+    let images = tileset.load_images_with(&resolver).unwrap();
+    assert_eq!(images[0].get_pixel(1, 1), &red);
+    assert_eq!(images[1].get_pixel(0, 0), &blue);
+    let mut overflowing = AtlasResolver::new(atlas.clone(), 2);
+    overflowing.insert("red.png", (u32::MAX - 1, 0));
+    assert!(matches!(
+        overflowing.resolve("red.png"),
+        Err(WfcError::InvalidTileset(message)) if message.contains("runs off")
+    ));
+
+    let mut png = std::io::Cursor::new(Vec::new());
+    atlas.write_to(&mut png, image::ImageFormat::Png).unwrap();
+    let mut resolver = EmbeddedResolver::default();
+    resolver.insert("red.png", png.get_ref().clone());
+    assert!(matches!(
+        tileset.load_images_with(&resolver),
+        Err(WfcError::InvalidTileset(_))
+    ));
+    resolver.insert("blue.png", png.into_inner());
+    // The whole 4x2 atlas doesn't fit a 2x2 tileset.
+    assert!(matches!(
+        tileset.load_images_with(&resolver),
+        Err(WfcError::InvalidTileset(message)) if message.contains("is 4x2")
+    ));
+}
+
+#[test]
+fn annotated_render_prints_possibility_counts() {
+    let tileset = tileset(COAST);
+    let images = vec![RgbaImage::from_pixel(16, 16, image::Rgba([0, 128, 0, 255])); 4];
+    let mut model = model(&tileset, 2, 1, COAST_SEED);
+
+    // An uncollapsed cell reads "4": its left column is lit only on the top
+    // three rows of the glyph.
+    let output = model.grid().render_annotated(&images, RenderMode::Flat);
+    let lit = |x, y| output.get_pixel(x, y).0 == [255, 255, 255, 255];
+    assert!((1..4).all(|y| lit(1, y)));
+    assert!((4..6).all(|y| !lit(1, y)));
+    assert!(lit(3, 5) && !lit(8, 8));
+
+    model.run();
+    let output = model.grid().render_annotated(&images, RenderMode::Flat);
+    assert_eq!(output.get_pixel(0, 0).0, [0, 0, 0, 255]);
+    assert!((1..6).all(|y| output.get_pixel(2, y).0 == [255, 255, 255, 255]));
+}
+
+#[test]
+fn tiled_export_uses_one_based_gids() {
+    let tileset = tileset(COAST);
+    let mut model = model(&tileset, 6, 4, COAST_SEED);
+    model.grid_mut().step();
+    let partial: serde_json::Value =
+        serde_json::from_str(&model.grid().to_tiled_json("coast")).unwrap();
+    assert!(partial["layers"][0]["data"]
+        .as_array()
+        .unwrap()
+        .contains(&0.into()));
+
+    model.run();
+    let map: serde_json::Value =
+        serde_json::from_str(&model.grid().to_tiled_json("coast")).unwrap();
+    let data = map["layers"][0]["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|gid| gid.as_u64().unwrap() as usize - 1)
+        .collect::<Vec<_>>();
+
+    assert_eq!(map["width"], 6);
+    assert_eq!(map["tilesets"][0]["name"], "coast");
+    assert_eq!(data, golden().concat());
+}
+
+#[test]
+fn fit_intersects_known_neighbors() {
+    let tileset = tileset(COAST);
+    let model = model(&tileset, 1, 1, COAST_SEED);
+    assert_eq!(model.fit([None; 4]), vec![0, 1, 2, 3]);
+    assert_eq!(model.fit([Some(0), None, None, None]), vec![0, 1]);
+    assert_eq!(model.fit([Some(0), None, Some(2), None]), vec![1]);
+    assert_eq!(
+        model.fit([Some(2), None, Some(0), None]),
+        Vec::<usize>::new()
+    );
+    assert_eq!(model.fit([Some(9), None, None, None]), Vec::<usize>::new());
+}
+
+#[test]
+fn prompt_cells_are_kept() {
+    let tileset = tileset(COAST);
+    let prompt = "[
+        [null, null, null, null, null],
+        [null, null, null, null, null],
+        [null, null, 3, null, null],
+        [null, null, null, null, null]
+    ]";
+    let options = || Options {
+        seed: Some(COAST_SEED),
+        recovery: Some(RecoveryMode::Restart),
+        ..Default::default()
+    };
+    let model = Model::from_prompt(&tileset, prompt, options()).unwrap();
+    let indices = model.grid().to_indices().unwrap();
+    assert_eq!((indices.len(), indices[0].len()), (4, 5));
+    assert_eq!(indices[2][2], 3);
+
+    // Grass can't sit right above water.
+    let result = Model::from_prompt(&tileset, "[[0], [2]]", options());
+    assert!(matches!(result, Err(WfcError::Contradiction { .. })));
+    let result = Model::from_prompt(&tileset, "[[0, null], [2]]", options());
+    assert!(matches!(result, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn contradiction_is_reported() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "island.png", "sockets": [[5], [6], [7], [8]] }
+            ]
+        }"#,
+    );
+    let mut model = model(&tileset, 2, 1, 0);
+
+    assert!(matches!(
+        model.grid_mut().collapse_cell(0, 0, 2),
+        Err(WfcError::UnknownTile(2))
+    ));
+    assert!(matches!(
+        model.grid_mut().collapse_cell(2, 0, 0),
+        Err(WfcError::OutOfBounds { x: 2, y: 0 })
+    ));
+    assert!(matches!(
+        model.grid_mut().collapse_cell(0, 0, 1),
+        Err(WfcError::Contradiction { .. })
+    ));
+    model.run();
+    assert!(model.grid().is_finished());
+}
+
+#[test]
+fn local_reset_recovers_from_contradictions() {
+    let tileset = tileset(TANGLE);
+    let contradictions = |recovery| {
+        (0..10)
+            .filter(|&seed| {
+                let mut model = Model::new(
+                    &tileset,
+                    Options {
+                        width: Some(8),
+                        height: Some(8),
+                        seed: Some(seed),
+                        recovery: Some(recovery),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                model.run();
+                model.grid().check_contradiction().is_err()
+            })
+            .count()
+    };
+
+    assert!(contradictions(RecoveryMode::None) > 0);
+    assert_eq!(contradictions(RecoveryMode::LocalReset), 0);
+}
+
+#[test]
+fn local_reset_keeps_cells_outside_its_radius() {
+    let tileset = tileset(PIXIE);
+    for radius in [0, 2] {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(9),
+                height: Some(9),
+                seed: Some(1),
+                recovery: Some(RecoveryMode::LocalReset),
+                reset_radius: Some(radius),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        let before = model.grid().to_indices().unwrap();
+
+        let grid = model.grid_mut();
+        grid.empty(4, 4);
+        grid.recover();
+        assert!(grid.check_contradiction().is_ok());
+        for (y, row) in grid.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if x.abs_diff(4) > radius || y.abs_diff(4) > radius {
+                    assert!(cell.possibilities.iter().eq([&before[y][x]]));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn pins_hold_through_generation() {
+    let tileset = tileset(COAST);
+    let mut model = Model::new(
+        &tileset,
+        Options {
+            width: Some(6),
+            height: Some(4),
+            seed: Some(COAST_SEED),
+            recovery: Some(RecoveryMode::Restart),
+            pins: vec![(0, 3, 3)],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.pin(4, 1, 1).unwrap();
+    assert!(!model.undo());
+
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    assert_eq!((indices[3][0], indices[1][4]), (3, 1));
+
+    assert!(matches!(
+        model.pin(9, 0, 0),
+        Err(WfcError::OutOfBounds { .. })
+    ));
+    assert!(matches!(model.pin(0, 0, 9), Err(WfcError::UnknownTile(9))));
+    let options = Options {
+        pins: vec![(0, 0, 0), (0, 1, 2)],
+        ..Default::default()
+    };
+    assert!(matches!(
+        Model::new(&tileset, options),
+        Err(WfcError::Contradiction { .. })
+    ));
+}
+
+#[test]
+fn tile_counts_stay_within_their_limits() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "flowers.png", "sockets": [[0], [0], [0], [0]], "weight": 0.05, "min_count": 4 },
+                { "image": "spawn.png", "sockets": [[0], [0], [0], [0]], "min_count": 1, "max_count": 1 }
+            ]
+        }"#,
+    );
+
+    for seed in 0..8 {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(5),
+                height: Some(5),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backtrack),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+
+        let indices = model.grid().to_indices().unwrap();
+        let count = |tile| indices.iter().flatten().filter(|&&t| t == tile).count();
+        assert!(count(1) >= 4, "seed {}", seed);
+        assert_eq!(count(2), 1, "seed {}", seed);
+    }
+
+    let invalid =
+        r#"{ "size": 16, "tiles": [{ "image": "a.png", "min_count": 2, "max_count": 1 }] }"#;
+    let invalid = self::tileset(invalid);
+    let error = Model::new(&invalid, Options::default());
+    assert!(matches!(error, Err(WfcError::InvalidTileset(_))));
+}
+
+#[test]
+fn tag_constraints_cover_every_tagged_tile() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]], "weight": 4 },
+                { "image": "road.png", "sockets": [[0], [0], [0], [0]], "tags": ["road"] },
+                { "image": "bridge.png", "sockets": [[0], [0], [0], [0]], "tags": ["road"] },
+                { "image": "water.png", "sockets": [[0], [0], [0], [0]], "tags": ["water"] },
+                { "image": "lily.png", "sockets": [[0], [0], [0], [0]], "tags": ["water"] }
+            ]
+        }"#,
+    );
+
+    for seed in 0..6 {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(5),
+                height: Some(5),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backtrack),
+                tag_counts: vec![("road".to_string(), Some(2), Some(4))],
+                tag_pins: vec![(4, 4, "road".to_string())],
+                tag_borders: vec![(Edge::Left, "water".to_string())],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+
+        let indices = model.grid().to_indices().unwrap();
+        let has = |x: usize, y: usize, tag| tileset.tiles[indices[y][x]].has_tag(tag);
+        assert!((0..5).all(|y| has(0, y, "water")), "seed {}", seed);
+        assert!(has(4, 4, "road"), "seed {}", seed);
+        let roads = (0..25).filter(|i| has(i % 5, i / 5, "road")).count();
+        assert!((2..=4).contains(&roads), "seed {}", seed);
+    }
+
+    let error = Model::new(
+        &tileset,
+        Options {
+            tag_pins: vec![(0, 0, "lava".to_string())],
+            ..Default::default()
+        },
+    );
+    assert!(matches!(error, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn exclusive_tags_never_share_an_output() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "snow.png", "sockets": [[0], [0], [0], [0]], "tags": ["winter"] },
+                { "image": "ice.png", "sockets": [[0], [0], [0], [0]], "tags": ["winter"] },
+                { "image": "flowers.png", "sockets": [[0], [0], [0], [0]], "tags": ["summer"] }
+            ],
+            "exclusive": [["winter", "summer"]]
+        }"#,
+    );
+
+    let mut seasons = Vec::new();
+    for seed in 0..8 {
+        let mut model = model(&tileset, 6, 6, seed);
+        model.run();
+        let indices = model.grid().to_indices().unwrap();
+        let has = |tag| {
+            indices
+                .iter()
+                .flatten()
+                .any(|&tile| tileset.tiles[tile].has_tag(tag))
+        };
+        assert!(!(has("winter") && has("summer")), "seed {}", seed);
+        seasons.push(has("winter"));
+    }
+    assert!(seasons.contains(&true) && seasons.contains(&false));
+}
+
+#[test]
+fn required_tiles_always_turn_up() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "floor.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "exit.png", "sockets": [[0], [0], [0], [0]], "weight": 0.01, "max_count": 1 },
+                { "image": "chest.png", "sockets": [[0], [0], [0], [0]], "weight": 0.01, "tags": ["loot"] }
+            ]
+        }"#,
+    );
+
+    for seed in 0..8 {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(5),
+                height: Some(5),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backtrack),
+                required: vec!["exit.png".to_string(), "loot".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        let indices = model.grid().to_indices().unwrap();
+        let count = |tile| indices.iter().flatten().filter(|&&t| t == tile).count();
+        assert_eq!(count(1), 1, "seed {}", seed);
+        assert!(count(2) >= 1, "seed {}", seed);
+    }
+}
+
+#[test]
+fn distance_rules_hold_between_tags() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "road.png", "sockets": [[0], [0], [0], [0]], "tags": ["road"], "weight": 0.3 },
+                { "image": "house.png", "sockets": [[0], [0], [0], [0]], "tags": ["house"] },
+                { "image": "boss.png", "sockets": [[0], [0], [0], [0]], "tags": ["boss"], "weight": 0.5 }
+            ]
+        }"#,
+    );
+
+    for seed in 0..6 {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(7),
+                height: Some(7),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backtrack),
+                distances: vec![
+                    DistanceRule::Near {
+                        tag: "house".to_string(),
+                        other: "road".to_string(),
+                        distance: 1,
+                    },
+                    DistanceRule::Apart {
+                        tag: "boss".to_string(),
+                        other: "boss".to_string(),
+                        distance: 3,
+                    },
+                ],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        assert!(model.grid().check_contradiction().is_ok(), "seed {}", seed);
+
+        let indices = model.grid().to_indices().unwrap();
+        let cells = |tile| {
+            (0..49)
+                .map(|i| (i % 7, i / 7))
+                .filter(|&(x, y)| indices[y][x] == tile)
+                .collect::<Vec<(usize, usize)>>()
+        };
+        let steps =
+            |(x, y): (usize, usize), (ox, oy): (usize, usize)| x.abs_diff(ox) + y.abs_diff(oy);
+        let (roads, houses, bosses) = (cells(1), cells(2), cells(3));
+        assert!(!houses.is_empty());
+        for &house in &houses {
+            assert!(
+                roads.iter().any(|&road| steps(house, road) <= 1),
+                "seed {}",
+                seed
+            );
+        }
+        for &a in &bosses {
+            assert!(
+                bosses.iter().all(|&b| a == b || steps(a, b) > 3),
+                "seed {}",
+                seed
+            );
+        }
+    }
+}
+
+#[test]
+fn regions_never_fall_below_their_minimum() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]], "weight": 2 },
+                { "image": "water.png", "sockets": [[0], [0], [0], [0]], "tags": ["water"] }
+            ]
+        }"#,
+    );
+
+    for seed in 0..6 {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(8),
+                height: Some(8),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backtrack),
+                min_region: vec![("water".to_string(), 5)],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        assert!(model.grid().check_contradiction().is_ok(), "seed {}", seed);
+
+        let indices = model.grid().to_indices().unwrap();
+        let mut seen = [[false; 8]; 8];
+        for (y, x) in (0..64).map(|i| (i / 8, i % 8)) {
+            if indices[y][x] != 1 || seen[y][x] {
+                continue;
+            }
+            let (mut size, mut queue) = (0, vec![(x, y)]);
+            seen[y][x] = true;
+            while let Some((cx, cy)) = queue.pop() {
+                size += 1;
+                let next = [
+                    (cx + 1, cy),
+                    (cx.wrapping_sub(1), cy),
+                    (cx, cy + 1),
+                    (cx, cy.wrapping_sub(1)),
+                ];
+                for (nx, ny) in next {
+                    if nx < 8 && ny < 8 && indices[ny][nx] == 1 && !seen[ny][nx] {
+                        seen[ny][nx] = true;
+                        queue.push((nx, ny));
+                    }
+                }
+            }
+            assert!(
+                size >= 5,
+                "seed {}: region of {} at ({}, {})",
+                seed,
+                size,
+                x,
+                y
+            );
+        }
+    }
+}
+
+#[test]
+fn connected_tags_form_one_region() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "road.png", "sockets": [[0], [0], [0], [0]], "tags": ["road"] },
+                { "image": "bridge.png", "sockets": [[0], [0], [0], [0]], "tags": ["road"], "weight": 0.5 }
+            ]
+        }"#,
+    );
+
+    for seed in 0..8 {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(7),
+                height: Some(7),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backtrack),
+                connected: vec!["road".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        let indices = model.grid().to_indices().unwrap();
+
+        let road: Vec<(usize, usize)> = (0..49)
+            .map(|i| (i % 7, i / 7))
+            .filter(|&(x, y)| tileset.tiles[indices[y][x]].has_tag("road"))
+            .collect();
+        let mut reached = vec![road[0]];
+        let mut queue = vec![road[0]];
+        while let Some((x, y)) = queue.pop() {
+            for next in [
+                (x + 1, y),
+                (x.wrapping_sub(1), y),
+                (x, y + 1),
+                (x, y.wrapping_sub(1)),
+            ] {
+                if road.contains(&next) && !reached.contains(&next) {
+                    reached.push(next);
+                    queue.push(next);
+                }
+            }
+        }
+        assert_eq!(reached.len(), road.len(), "seed {}", seed);
+    }
+}
+
+#[test]
+fn ground_fills_only_the_bottom_row() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "sky.png", "sockets": [[0], [0], [0], [0]], "weight": 4 },
+                { "image": "cloud.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "dirt.png", "sockets": [[0], [0], [0], [0]], "tags": ["floor"] },
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]], "tags": ["floor"] }
+            ]
+        }"#,
+    );
+
+    for ground in ["floor", "dirt.png"] {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(6),
+                height: Some(4),
+                seed: Some(1),
+                ground: Some(ground.to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+
+        let indices = model.grid().to_indices().unwrap();
+        for (y, row) in indices.iter().enumerate() {
+            for &tile in row {
+                assert_eq!(tileset.tiles[tile].is_named(ground), y == 3, "{}", ground);
+            }
+        }
+    }
+
+    let error = Model::new(
+        &tileset,
+        Options {
+            ground: Some("lava".to_string()),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(error, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn backtracking_resolves_contradictions() {
+    let tileset = tileset(TANGLE);
+    let run = |seed, max_backtrack_depth| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(8),
+                height: Some(8),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backtrack),
+                max_backtrack_depth,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        model.grid().check_contradiction().is_ok()
+    };
+
+    assert!((0..10).all(|seed| run(seed, None)));
+    assert!((0..10).any(|seed| !run(seed, Some(0))));
+}
+
+#[test]
+fn backjumping_resolves_contradictions() {
+    let tileset = tileset(TANGLE);
+    let run = |seed, max_backtrack_depth| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(8),
+                height: Some(8),
+                seed: Some(seed),
+                recovery: Some(RecoveryMode::Backjump),
+                max_backtrack_depth,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        model.grid().check_contradiction().is_ok() && model.grid().is_finished()
+    };
+
+    assert!((0..10).all(|seed| run(seed, None)));
+    assert!((0..10).any(|seed| !run(seed, Some(0))));
+}
+
+#[test]
+fn retry_policy_reruns_failed_grids() {
+    let tileset = tileset(TANGLE);
+    let run = |seed, retry| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(8),
+                height: Some(8),
+                seed: Some(seed),
+                retry,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let stats = model.run();
+        assert_eq!(stats.succeeded, model.grid().check_contradiction().is_ok());
+        stats
+    };
+
+    let retry = RetryPolicy {
+        max_attempts: 20,
+        reseed: true,
+    };
+    let stats: Vec<RunStats> = (0..10).map(|seed| run(seed, Some(retry))).collect();
+    assert!(stats.iter().all(|stats| stats.succeeded));
+    assert!(stats.iter().any(|stats| stats.attempts > 1));
+
+    let failed = (0..10)
+        .map(|seed| run(seed, None))
+        .find(|stats| !stats.succeeded)
+        .unwrap();
+    assert_eq!(failed.attempts, 1);
+}
+
+#[test]
+fn bias_field_steers_collapse() {
+    let tileset = tileset(COAST);
+    let mut model = Model::new(
+        &tileset,
+        Options {
+            width: Some(6),
+            height: Some(4),
+            seed: Some(COAST_SEED),
+            bias_field: Some(Box::new(|_, _| vec![(3, 0.0)])),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.run();
+
+    let indices = model.grid().to_indices().unwrap();
+    assert!(indices.iter().flatten().all(|&tile| tile != 3));
+}
+
+#[test]
+fn weight_maps_scale_weights_by_position() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "water.png", "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    // Water is ruled out of the top half and certain in the bottom half.
+    let ramp: Vec<Vec<f64>> = (0..8)
+        .map(|y| vec![if y < 4 { 0.0 } else { 1.0 }; 8])
+        .collect();
+    let grass: Vec<Vec<f64>> = ramp
+        .iter()
+        .map(|row| row.iter().map(|w| 1.0 - w).collect())
+        .collect();
+    let mut model = Model::new(
+        &tileset,
+        Options {
+            width: Some(8),
+            height: Some(8),
+            seed: Some(3),
+            weight_maps: HashMap::from([(0, grass), (1, ramp.clone())]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.run();
+
+    let indices = model.grid().to_indices().unwrap();
+    for (y, row) in indices.iter().enumerate() {
+        assert!(
+            row.iter().all(|&tile| tile == (y >= 4) as usize),
+            "row {}",
+            y
+        );
+    }
+
+    let image = RgbaImage::from_fn(3, 2, |x, _| {
+        image::Rgba([x as u8 * 127, x as u8 * 127, x as u8 * 127, 255])
+    });
+    let map = luma_map(&image);
+    assert_eq!(map.len(), 2);
+    assert!(map[0][0] == 0.0 && map[1][2] > 0.99);
+
+    let error = Model::new(
+        &tileset,
+        Options {
+            width: Some(4),
+            height: Some(8),
+            weight_maps: HashMap::from([(1, ramp)]),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(error, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn noise_clumps_tagged_tiles_together() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "sea.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "land.png", "sockets": [[0], [0], [0], [0]], "tags": ["land"] }
+            ]
+        }"#,
+    );
+    // How often horizontal neighbors agree on land or sea.
+    let agreement = |noise: Vec<NoiseBias>| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(32),
+                height: Some(32),
+                seed: Some(6),
+                noise,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        let indices = model.grid().to_indices().unwrap();
+        let same = indices
+            .iter()
+            .flat_map(|row| row.windows(2))
+            .filter(|pair| pair[0] == pair[1])
+            .count();
+        same as f64 / (31 * 32) as f64
+    };
+
+    let noise = vec![NoiseBias {
+        tag: "land".to_string(),
+        amplitude: 8.0,
+        frequency: 0.08,
+    }];
+    assert!(agreement(noise) > agreement(Vec::new()) + 0.15);
+}
+
+#[test]
+fn corner_tiles_agree_on_every_shared_corner() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "corners": [0, 0, 0, 0] },
+                { "image": "water.png", "corners": [1, 1, 1, 1] },
+                { "image": "shore.png", "corners": [0, 0, 1, 1], "rotations": true },
+                { "image": "cove.png", "corners": [0, 0, 0, 1], "rotations": true },
+                { "image": "cape.png", "corners": [1, 1, 1, 0], "rotations": true }
+            ]
+        }"#,
+    );
+    let mut model = Model::new(
+        &tileset,
+        Options {
+            width: Some(8),
+            height: Some(6),
+            seed: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.run();
+
+    let grid = model.grid();
+    let corners = grid.to_corners().unwrap();
+    assert_eq!((corners.len(), corners[0].len()), (7, 9));
+    let indices = grid.to_indices().unwrap();
+    for (y, row) in indices.iter().enumerate() {
+        for (x, &tile) in row.iter().enumerate() {
+            let expected = [
+                corners[y][x],
+                corners[y][x + 1],
+                corners[y + 1][x + 1],
+                corners[y + 1][x],
+            ];
+            assert_eq!(
+                tileset.tiles[tile].corners(),
+                Some(expected),
+                "({}, {})",
+                x,
+                y
+            );
+        }
+    }
+    assert!(corners.iter().flatten().any(|&terrain| terrain == 1));
+    assert!(corners.iter().flatten().any(|&terrain| terrain == 0));
+
+    let invalid = r#"{ "size": 16, "tiles": [{ "image": "a.png", "sockets": [[0], [0], [0], [0]], "corners": [0, 0, 0, 0] }] }"#;
+    assert!(serde_json::from_str::<Tileset>(invalid).is_err());
+}
+
+#[test]
+fn corner_sockets_constrain_diagonal_neighbors() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "water.png", "sockets": [[0], [0], [0], [0]], "corner_sockets": [1, 1, 1, 1] },
+                { "image": "lava.png", "sockets": [[0], [0], [0], [0]], "corner_sockets": [2, 2, 2, 2] }
+            ]
+        }"#,
+    );
+
+    for propagator in [Propagator::Worklist, Propagator::SupportCount] {
+        let mut model = model_with(&tileset, 7, 5, 4, propagator);
+        model.run();
+
+        let indices = model.grid().to_indices().unwrap();
+        for y in 0..4 {
+            for x in 0..6 {
+                assert_eq!(indices[y][x], indices[y + 1][x + 1], "({}, {})", x, y);
+                assert_eq!(indices[y][x + 1], indices[y + 1][x], "({}, {})", x, y);
+            }
+        }
+    }
+
+    let tile = &tileset.tiles[0];
+    assert!(tile.fits_diagonally(2, tile) && !tile.fits_diagonally(2, &tileset.tiles[1]));
+}
+
+#[test]
+fn detail_tiles_follow_the_coarse_layout() {
+    let coarse = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "ocean.png", "sockets": [[0], [0], [0], [0]], "tags": ["sea"] },
+                { "image": "continent.png", "sockets": [[0], [0], [0], [0]], "tags": ["land"] }
+            ]
+        }"#,
+    );
+    let detail = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "water.png", "sockets": [[0], [0], [0], [0]], "on": ["sea"] },
+                { "image": "reef.png", "sockets": [[0], [0], [0], [0]], "on": ["sea"] },
+                { "image": "forest.png", "sockets": [[0], [0], [0], [0]], "on": ["land"] },
+                { "image": "hills.png", "sockets": [[0], [0], [0], [0]], "on": ["land"] }
+            ]
+        }"#,
+    );
+
+    let mut hierarchy = HierarchicalModel::new(model(&coarse, 4, 3, 1), &detail, 3).unwrap();
+    let result = hierarchy
+        .run(Options {
+            seed: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let layout = hierarchy.coarse().grid().to_indices().unwrap();
+    let indices = result.grid().to_indices().unwrap();
+    assert_eq!((indices.len(), indices[0].len()), (9, 12));
+    for (y, row) in indices.iter().enumerate() {
+        for (x, &tile) in row.iter().enumerate() {
+            let land = layout[y / 3][x / 3] == 1;
+            assert_eq!(tile >= 2, land, "({}, {})", x, y);
+        }
+    }
+
+    assert!(HierarchicalModel::new(model(&coarse, 4, 3, 1), &detail, 0).is_err());
+}
+
+#[test]
+fn soft_matches_are_legal_but_rare() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "sand.png", "sockets": [[1], [1], [1], [1]] }
+            ],
+            "soft": [{ "socks": [0, 1], "strength": 0.05 }]
+        }"#,
+    );
+    let adjacency = Adjacency::new(&tileset, &[]).unwrap();
+    assert_eq!(adjacency.strength(0, Edge::Right, 1), 0.05);
+    assert_eq!(adjacency.strength(1, Edge::Top, 0), 0.05);
+
+    let mut model = model(&tileset, 16, 16, 0);
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    let mismatches = indices
+        .iter()
+        .flat_map(|row| row.windows(2))
+        .filter(|pair| pair[0] != pair[1])
+        .count();
+    assert!(mismatches < 16 * 15 / 4);
+}
+
+#[test]
+fn possibilities_snapshot_narrows_each_step() {
+    let tileset = tileset(COAST);
+    let mut model = model(&tileset, 6, 4, COAST_SEED);
+    let count = |snapshot: &Vec<Vec<Vec<usize>>>| snapshot.iter().flatten().flatten().count();
+
+    let mut snapshot = model.possibilities_snapshot();
+    assert_eq!(snapshot.len(), 4);
+    assert!(snapshot.iter().all(|row| row.len() == 6));
+    assert_eq!(count(&snapshot), 6 * 4 * tileset.tiles.len());
+
+    while !model.grid().is_finished() {
+        model.grid_mut().step();
+        let next = model.possibilities_snapshot();
+        assert!(count(&next) <= count(&snapshot));
+        snapshot = next;
+    }
+    let collapsed: Vec<Vec<usize>> = snapshot
+        .iter()
+        .map(|row| row.iter().map(|cell| cell[0]).collect())
+        .collect();
+    assert_eq!(collapsed, golden());
+}
+
+#[test]
+fn degenerate_dimensions_solve() {
+    let tileset = tileset(COAST);
+    for (width, height) in [(0, 0), (0, 5), (5, 0), (1, 1), (1, 8), (8, 1)] {
+        for propagator in [Propagator::Worklist, Propagator::SupportCount] {
+            let mut model = model_with(&tileset, width, height, COAST_SEED, propagator);
+            if width == 0 || height == 0 {
+                assert!(model.grid().is_finished());
+            }
+
+            model.run();
+            let indices = model.grid().to_indices().unwrap();
+            assert_eq!(indices.iter().flatten().count(), width * height);
+            assert!(model.grid().check_contradiction().is_ok());
+        }
+    }
+}
+
+#[test]
+fn layers_respect_cross_layer_constraints() {
+    let terrain = tileset(COAST);
+    let objects = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "empty.png", "transparent": true },
+                { "image": "tree.png", "sockets": [[0], [0], [0], [0]], "weight": 0.1 }
+            ]
+        }"#,
+    );
+    let options = || Options {
+        width: Some(6),
+        height: Some(4),
+        seed: Some(3),
+        ..Default::default()
+    };
+
+    // Trees (object 1) only grow on grass (terrain 0).
+    let mut model = LayeredModel::new(
+        vec![(&terrain, options()), (&objects, options())],
+        |(_, ground), (_, object)| object == 0 || ground == 0,
+    )
+    .unwrap();
+    model.run();
+    assert!(model.is_finished());
+    assert!(model.check_contradiction().is_ok());
+
+    let ground = model.grid(0).unwrap().to_indices().unwrap();
+    let trees = model.grid(1).unwrap().to_indices().unwrap();
+    let cells = ground.iter().flatten().zip(trees.iter().flatten());
+    assert!(cells.clone().any(|(_, &tree)| tree == 1));
+    assert!(cells.clone().any(|(&ground, _)| ground != 0));
+    assert!(cells
+        .filter(|(_, &tree)| tree == 1)
+        .all(|(&ground, _)| ground == 0));
+}
+
+#[test]
+fn layers_recover_and_retry_on_their_own_terms() {
+    let tileset = tileset(TANGLE);
+    let options = |seed, recovery, retry| Options {
+        width: Some(8),
+        height: Some(8),
+        seed: Some(seed),
+        recovery: Some(recovery),
+        retry,
+        ..Default::default()
+    };
+    // The same tile never sits on itself.
+    let solved = |recovery, retry| {
+        (0..10)
+            .filter(|&seed| {
+                let mut model = LayeredModel::new(
+                    vec![
+                        (&tileset, options(seed, recovery, retry)),
+                        (&tileset, options(seed + 10, recovery, retry)),
+                    ],
+                    |(_, below), (_, above)| below != above,
+                )
+                .unwrap();
+                model.run();
+                assert!(model.is_finished());
+                model.check_contradiction().is_ok()
+            })
+            .count()
+    };
+
+    assert!(solved(RecoveryMode::None, None) < 10);
+    assert_eq!(solved(RecoveryMode::Backtrack, None), 10);
+    let retry = RetryPolicy {
+        max_attempts: 20,
+        reseed: true,
+    };
+    assert_eq!(solved(RecoveryMode::None, Some(retry)), 10);
+}
+
+#[test]
+fn layers_must_share_dimensions() {
+    let tileset = tileset(COAST);
+    let options = |width| Options {
+        width: Some(width),
+        ..Default::default()
+    };
+    let result = LayeredModel::new(
+        vec![(&tileset, options(4)), (&tileset, options(5))],
+        |_, _| true,
+    );
+    assert!(matches!(result, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn total_entropy_falls_to_zero() {
+    let tileset = tileset(COAST);
+    let mut model = model(&tileset, 6, 4, COAST_SEED);
+    let mut entropy = model.grid().total_entropy();
+    assert_eq!(entropy, 6 * 4 * (tileset.tiles.len() - 1));
+
+    while !model.grid().is_finished() {
+        model.grid_mut().step();
+        let next = model.grid().total_entropy();
+        assert!(next <= entropy);
+        entropy = next;
+    }
+    assert_eq!(entropy, 0);
+}
+
+#[test]
+fn weight_overrides_apply_to_one_run() {
+    let tileset = tileset(COAST);
+    let count_water = |weight_overrides: HashMap<usize, f64>| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(12),
+                height: Some(12),
+                seed: Some(COAST_SEED),
+                weight_overrides,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        let indices = model.grid().to_indices().unwrap();
+        indices.iter().flatten().filter(|&&tile| tile == 2).count()
+    };
+
+    let zero = HashMap::from([(2, 0.0)]);
+    let heavy = HashMap::from([(2, 50.0), (0, 0.1)]);
+    assert!(count_water(heavy) > count_water(HashMap::new()));
+    assert_eq!(count_water(zero), 0);
+    assert_eq!(tileset.tiles[2].weight(), 1.0);
+}
+
+#[test]
+fn undo_and_redo_restore_possibilities() {
+    let tileset = tileset(COAST);
+    let mut model = model(&tileset, 6, 4, COAST_SEED);
+    let initial = possibilities(model.grid());
+
+    model.grid_mut().collapse_cell(2, 1, 1).unwrap();
+    let collapsed = possibilities(model.grid());
+    assert_ne!(initial, collapsed);
+
+    assert!(model.undo());
+    assert_eq!(possibilities(model.grid()), initial);
+    assert!(!model.undo());
+
+    assert!(model.redo());
+    assert_eq!(possibilities(model.grid()), collapsed);
+    assert!(!model.redo());
+
+    // Undoing the last step of a run reopens the grid, and redoing it
+    // finishes the grid again.
+    model.run();
+    let finished = possibilities(model.grid());
+    assert!(model.grid().is_finished());
+    assert!(model.undo());
+    assert!(!model.grid().is_finished());
+    assert!(model.redo());
+    assert!(model.grid().is_finished());
+    assert_eq!(possibilities(model.grid()), finished);
+}
+
+#[test]
+fn forbidden_pairs_never_touch() {
+    let tileset = tileset(COAST);
+    for seed in 0..8 {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(6),
+                height: Some(4),
+                seed: Some(seed),
+                forbidden: vec![(0, 1, Edge::Bottom)],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+
+        let indices = model.grid().to_indices().unwrap();
+        for rows in indices.windows(2) {
+            assert!(!rows[0].iter().zip(&rows[1]).any(|pair| pair == (&0, &1)));
+        }
+    }
+}
+
+#[test]
+fn stop_at_fills_remaining_cells() {
+    let tileset = tileset(PIXIE);
+    let mut model = Model::new(
+        &tileset,
+        Options {
+            width: Some(12),
+            height: Some(12),
+            seed: Some(0),
+            stop_at: Some(0.5),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.run();
+
+    assert!(model.grid().is_finished());
+    assert!(model.grid().to_indices().is_some());
+}
+
+#[test]
+fn rng_state_resumes_identically() {
+    let tileset = tileset(PIXIE);
+    let mut model = model(&tileset, 8, 8, 3);
+    for _ in 0..5 {
+        model.grid_mut().step();
+    }
+    let json = serde_json::to_string(&model.grid().rng_state()).unwrap();
+    let decisions = model.grid().history.len();
+
+    model.run();
+    let expected = model.grid().to_indices();
+    while model.grid().history.len() > decisions {
+        model.undo();
+    }
+
+    let state: RngState = serde_json::from_str(&json).unwrap();
+    model.grid_mut().set_rng_state(&state);
+    model.run();
+    assert_eq!(model.grid().to_indices(), expected);
+}
+
+#[test]
+fn start_point_picks_first_collapse() {
+    let tileset = tileset(PIXIE);
+    for (start, expected) in [
+        (StartPoint::Corner, (0, 0)),
+        (StartPoint::Center, (4, 3)),
+        (StartPoint::At(7, 5), (7, 5)),
+    ] {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(9),
+                height: Some(7),
+                seed: Some(0),
+                start: Some(start),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.grid_mut().step();
+
+        let first = &model.grid().history[0];
+        assert_eq!((first.x, first.y), expected);
+    }
+}
+
+#[test]
+fn run_until_stops_when_predicate_holds() {
+    let tileset = tileset(PIXIE);
+    let mut model = model(&tileset, 8, 8, 0);
+
+    assert!(model.run_until(|grid| grid.collapsed_fraction() >= 0.5));
+    assert!(model.grid().collapsed_fraction() >= 0.5);
+    assert!(!model.grid().is_finished());
+
+    assert!(!model.run_until(|_| false));
+    assert!(model.grid().is_finished());
+}
+
+#[test]
+fn deterministic_runs_ignore_the_seed() {
+    let tileset = tileset(COAST);
+    let run = |seed| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(6),
+                height: Some(4),
+                seed: Some(seed),
+                start: Some(StartPoint::Random),
+                deterministic: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        model.grid().to_indices().unwrap()
+    };
+
+    let indices = run(1);
+    assert_eq!(indices, run(2));
+    assert_eq!(indices[0][0], 0);
+}
+
+#[test]
+fn seeded_runs_are_reproducible() {
+    let tileset = tileset(PIXIE);
+    let run = |seed| {
+        let mut model = model(&tileset, 12, 12, seed);
+        model.run();
+        possibilities(model.grid())
+    };
+
+    assert_eq!(run(3), run(3));
+    assert_ne!(run(3), run(4));
+}
+
+#[test]
+fn batch_is_reproducible() {
+    let tileset = tileset(COAST);
+    let model = model(&tileset, 6, 4, 0);
+    let batch = model.generate_batch(&[COAST_SEED, 9, COAST_SEED]);
+    let indices = batch
+        .iter()
+        .map(|grid| grid.as_ref().unwrap().to_indices())
+        .collect::<Vec<_>>();
+
+    assert_eq!(indices[0], indices[2]);
+    assert_eq!(indices[0], Some(golden()));
+}
+
+#[test]
+fn batches_keep_pins() {
+    let tileset = tileset(COAST);
+    let pinned = Model::builder(&tileset)
+        .width(6)
+        .height(4)
+        .pin(0, 3, 3)
+        .recovery(RecoveryMode::Restart)
+        .retry(RetryPolicy {
+            max_attempts: 5,
+            reseed: true,
+        })
+        .build()
+        .unwrap();
+
+    let seeds = (0..5).collect::<Vec<_>>();
+    for grid in pinned.generate_batch(&seeds) {
+        assert_eq!(grid.unwrap().to_indices().unwrap()[3][0], 3);
+    }
+}
+
+#[test]
+fn opposing_edges_must_share_an_arity() {
+    // Two socks across the top and bottom, one across the sides.
+    let mixed = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "wall.png", "sockets": [[0, 1], [2], [1, 0], [2]] },
+                { "image": "gate.png", "sockets": [[0, 0], [2], [0, 0], [2]] }
+            ]
+        }"#,
+    );
+    let (wall, gate) = (&mixed.tiles[0], &mixed.tiles[1]);
+    assert_eq!(wall.connects_to(wall, Edge::Top, &[]), 1.0);
+    assert_eq!(wall.connects_to(gate, Edge::Top, &[]), 0.0);
+    assert_eq!(wall.connects_to(gate, Edge::Right, &[]), 1.0);
+    assert!(Model::new(&mixed, Options::default()).is_ok());
+
+    // A bottom edge of three socks can never meet a top edge of two.
+    let mismatched = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "wall.png", "sockets": [[0, 1], [2], [1, 0], [2]] },
+                { "image": "tall.png", "sockets": [[0, 0], [2], [0, 0, 0], [2]] }
+            ]
+        }"#,
+    );
+    let expected = "tile `tall.png` has 3 socks on its Bottom edge, but opposing edges have 2";
+    assert!(matches!(
+        Model::new(&mismatched, Options::default()),
+        Err(WfcError::InvalidTileset(message)) if message == expected
+    ));
+    assert!(matches!(
+        Grid::from_indices(&mismatched, &[vec![0]]),
+        Err(WfcError::InvalidTileset(message)) if message == expected
+    ));
+}
+
+#[test]
+fn transparent_tiles_render_as_nothing_and_fit_anywhere() {
+    let sparse = tileset(
+        r#"{
+            "size": 1,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "water.png", "sockets": [[1], [1], [1], [1]] },
+                { "image": "empty.png", "transparent": true }
+            ]
+        }"#,
+    );
+    // The empty tile's image is drawn red, to show it's never used.
+    let images = [
+        RgbaImage::from_pixel(1, 1, image::Rgba([0, 255, 0, 255])),
+        RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 255, 255])),
+        RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255])),
+    ];
+    let mut strip = model(&sparse, 3, 1, 0);
+
+    // Grass rules water out next door, but not the empty tile.
+    strip.grid_mut().collapse_cell(0, 0, 0).unwrap();
+    assert_eq!(possibilities(strip.grid())[1], BTreeSet::from([0, 2]));
+    let open = strip.grid().render(&images, RenderMode::Flat);
+    assert_eq!(open.get_pixel(1, 0), &image::Rgba([0, 128, 0, 128]));
+
+    // Only the empty tile fits between grass and water.
+    strip.grid_mut().collapse_cell(2, 0, 1).unwrap();
+    assert_eq!(possibilities(strip.grid())[1], BTreeSet::from([2]));
+    let image = strip.grid().render(&images, RenderMode::Flat);
+    assert_eq!(image.get_pixel(0, 0), &image::Rgba([0, 255, 0, 255]));
+    assert_eq!(image.get_pixel(1, 0), &image::Rgba([0, 0, 0, 0]));
+    assert_eq!(image.get_pixel(2, 0), &image::Rgba([0, 0, 255, 255]));
+    strip.run();
+    assert!(strip.grid().is_finished());
+}
+
+#[test]
+fn weighted_render_blends_candidates_by_weight() {
+    let tileset = tileset(
+        r#"{
+            "size": 1,
+            "tiles": [
+                { "image": "red.png", "sockets": [[0], [0], [0], [0]], "weight": 3 },
+                { "image": "blue.png", "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    let images = [
+        RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255])),
+        RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 255, 255])),
+    ];
+    let mut blended = model(&tileset, 2, 1, 0);
+    blended.grid_mut().collapse_cell(0, 0, 1).unwrap();
+
+    // The collapsed cell is drawn as-is, and the open one three parts red
+    // to one part blue, or half and half when flat.
+    let weighted = blended.grid().render(&images, RenderMode::Weighted);
+    assert_eq!(weighted.get_pixel(0, 0), &image::Rgba([0, 0, 255, 255]));
+    assert_eq!(weighted.get_pixel(1, 0), &image::Rgba([191, 0, 64, 255]));
+    let flat = blended.grid().render(&images, RenderMode::Flat);
+    assert_eq!(flat.get_pixel(1, 0), &image::Rgba([128, 0, 128, 255]));
+}
+
+#[test]
+fn resized_models_start_over_at_the_new_size() {
+    let coast = tileset(COAST);
+    let mut resized = model(&coast, 6, 4, COAST_SEED);
+    resized.run();
+    resized.resize(8, 5);
+    let fresh = possibilities(resized.grid());
+    assert_eq!(fresh.len(), 8 * 5);
+    assert!(fresh.iter().all(|cell| cell.len() == coast.tiles.len()));
+    resized.run();
+    let indices = resized.grid().to_indices().unwrap();
+    assert_eq!((indices[0].len(), indices.len()), (8, 5));
+
+    // The same size and seed make the same grid again.
+    resized.resize(6, 4);
+    resized.run();
+    assert_eq!(resized.grid().to_indices(), Some(golden()));
+
+    // Weight maps don't stretch: cells past them keep their base weights.
+    let fields = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "forest.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "desert.png", "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    let mut weighted = Model::new(
+        &fields,
+        Options {
+            width: Some(4),
+            height: Some(4),
+            seed: Some(3),
+            weight_maps: HashMap::from([(0, vec![vec![0.0; 4]; 4])]),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    weighted.resize(8, 8);
+    weighted.run();
+    let indices = weighted.grid().to_indices().unwrap();
+    for (y, row) in indices.iter().enumerate() {
+        for (x, &tile) in row.iter().enumerate() {
+            if x < 4 && y < 4 {
+                assert_eq!(tile, 1, "({}, {})", x, y);
+            }
+        }
+    }
+    assert!(indices.iter().flatten().any(|&tile| tile == 0));
+}
+
+#[test]
+fn support_count_matches_worklist() {
+    let tileset = tileset(PIXIE);
+
+    for seed in 0..3 {
+        let mut worklist = model_with(&tileset, 12, 12, seed, Propagator::Worklist);
+        let mut supports = model_with(&tileset, 12, 12, seed, Propagator::SupportCount);
+        worklist.run();
+        supports.run();
+
+        assert_eq!(
+            possibilities(worklist.grid()),
+            possibilities(supports.grid())
+        );
+    }
+}
+
+#[test]
+fn undo_rewinds_support_count_steps() {
+    let tileset = tileset(PIXIE);
+    let mut model = model_with(&tileset, 12, 12, 0, Propagator::SupportCount);
+    let initial = possibilities(model.grid());
+
+    let mut snapshots = Vec::new();
+    for _ in 0..20 {
+        snapshots.push(possibilities(model.grid()));
+        model.grid_mut().step();
+    }
+    while let Some(snapshot) = snapshots.pop() {
+        assert!(model.undo());
+        assert_eq!(possibilities(model.grid()), snapshot);
+    }
+    assert_eq!(possibilities(model.grid()), initial);
+}
+
+#[test]
+fn worklist_only_visits_cells_near_a_change() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [{ "image": "a.png" }, { "image": "b.png" }]
+        }"#,
+    );
+    let mut model = model(&tileset, 50, 50, 0);
+    model.grid_mut().collapse_cell(25, 25, 0).unwrap();
+    // Each of the four neighbors is checked against its own four neighbors,
+    // and nothing narrows, so propagation stops there.
+    assert!(model.grid().checks <= 4 * 4 * 2 * 2);
+}
+
+#[test]
+fn overlapping_model_reproduces_sample_patterns() {
+    let colors = [
+        image::Rgba([255, 0, 0, 255]),
+        image::Rgba([0, 0, 255, 255]),
+        image::Rgba([0, 255, 0, 255]),
+    ];
+    // A 6x6 checkerboard of red and blue, with one green pixel.
+    let sample = RgbaImage::from_fn(6, 6, |x, y| match (x, y) {
+        (2, 2) => colors[2],
+        _ => colors[((x + y) % 2) as usize],
+    });
+    let windows = |image: &RgbaImage| {
+        let mut windows = BTreeSet::new();
+        for y in 0..image.height() - 1 {
+            for x in 0..image.width() - 1 {
+                let window = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .map(|(dx, dy)| image.get_pixel(x + dx, y + dy).0);
+                windows.insert(window);
+            }
+        }
+        windows
+    };
+
+    let mut model = OverlappingModel::new(
+        &sample,
+        OverlapOptions {
+            n: 2,
+            width: 12,
+            height: 9,
+            seed: Some(3),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(model.pattern_count(), windows(&sample).len());
+    model.run().unwrap();
+
+    let output = model.render().unwrap();
+    assert_eq!(output.dimensions(), (12, 9));
+    assert!(windows(&output).is_subset(&windows(&sample)));
+
+    let error = OverlappingModel::new(
+        &sample,
+        OverlapOptions {
+            n: 7,
+            ..Default::default()
+        },
+    );
+    assert!(matches!(error, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn sequenced_layers_sit_on_tagged_tiles() {
+    let terrain = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]], "tags": ["grass"] },
+                { "image": "water.png", "sockets": [[0], [0], [0], [0]], "tags": ["water"] }
+            ]
+        }"#,
+    );
+    let decoration = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "tree.png", "sockets": [[0], [0], [0], [0]], "on": ["grass"], "weight": 4 },
+                { "image": "lily.png", "sockets": [[0], [0], [0], [0]], "on": ["water"], "weight": 4 },
+                { "transparent": true, "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    let options = |seed| Options {
+        width: Some(6),
+        height: Some(5),
+        seed: Some(seed),
+        ..Default::default()
+    };
+    let mut model =
+        LayeredModel::sequenced(vec![(&terrain, options(2)), (&decoration, options(3))]).unwrap();
+
+    model.step();
+    assert!(model.grid(0).unwrap().collapsed_fraction() > 0.0);
+    assert_eq!(model.grid(1).unwrap().collapsed_fraction(), 0.0);
+
+    model.run();
+    model.check_contradiction().unwrap();
+    let indices = model.to_indices().unwrap();
+    let pairs = indices[0].iter().flatten().zip(indices[1].iter().flatten());
+    for (&ground, &decor) in pairs {
+        assert!(decoration.tiles[decor].fits_on(&terrain.tiles[ground]));
+    }
+    assert!(indices[1].iter().flatten().any(|&decor| decor == 0));
+}
+
+#[test]
+fn sequenced_layers_recover_from_dead_ends() {
+    // The same tile never grows on itself.
+    let mut ground = tileset(TANGLE);
+    let mut tangle = tileset(TANGLE);
+    for tile in 0..ground.tiles.len() {
+        ground.tiles[tile].tags = vec![format!("tile{}", tile)];
+        tangle.tiles[tile].on = (0..ground.tiles.len())
+            .filter(|&other| other != tile)
+            .map(|other| format!("tile{}", other))
+            .collect();
+    }
+    let options = |seed, recovery| Options {
+        width: Some(8),
+        height: Some(8),
+        seed: Some(seed),
+        recovery: Some(recovery),
+        ..Default::default()
+    };
+    let solved = |recovery| {
+        (0..10)
+            .filter(|&seed| {
+                let mut model = LayeredModel::sequenced(vec![
+                    (&ground, options(seed, recovery)),
+                    (&tangle, options(seed + 10, recovery)),
+                ])
+                .unwrap();
+                model.run();
+                let Ok(()) = model.check_contradiction() else {
+                    return false;
+                };
+                let indices = model.to_indices().unwrap();
+                let pairs = indices[0].iter().flatten().zip(indices[1].iter().flatten());
+                for (&below, &above) in pairs {
+                    assert_ne!(below, above);
+                }
+                true
+            })
+            .count()
+    };
+
+    assert!(solved(RecoveryMode::None) < 10);
+    assert_eq!(solved(RecoveryMode::Backtrack), 10);
+}
+
+#[test]
+fn stacked_layers_recover_from_dead_ends() {
+    // The tangle, with the middle tile and the other two taking turns up the
+    // stack.
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "0.png", "sockets": [[1, 1], [2, 2], [1, 2], [0, 1], [0], [1]] },
+                { "image": "1.png", "sockets": [[0, 2], [1, 2], [1, 2], [2, 1], [1], [0]] },
+                { "image": "2.png", "sockets": [[2, 1], [1, 1], [2, 0], [1, 1], [0], [1]] }
+            ]
+        }"#,
+    );
+    let solved = |recovery| {
+        (0..10)
+            .filter(|&seed| {
+                let mut model = LayeredModel::stacked(
+                    &tileset,
+                    Options {
+                        width: Some(8),
+                        height: Some(8),
+                        depth: Some(3),
+                        seed: Some(seed),
+                        recovery: Some(recovery),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                model.run();
+                model.check_contradiction().is_ok()
+            })
+            .count()
+    };
+
+    assert!(solved(RecoveryMode::None) < 10);
+    assert_eq!(solved(RecoveryMode::Backtrack), 10);
+}
+
+#[test]
+fn stacked_layers_match_faces() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "air.png", "sockets": [[0], [0], [0], [0], [0], [0]] },
+                { "image": "block.png", "sockets": [[0], [0], [0], [0], [1], [1]] },
+                { "image": "roof.png", "sockets": [[0], [0], [0], [0], [0], [1]] }
+            ]
+        }"#,
+    );
+    let mut model = LayeredModel::stacked(
+        &tileset,
+        Options {
+            width: Some(5),
+            height: Some(4),
+            depth: Some(4),
+            seed: Some(1),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.run();
+    model.check_contradiction().unwrap();
+
+    let indices = model.to_indices().unwrap();
+    assert_eq!(indices.len(), 4);
+    for layers in indices.windows(2) {
+        let pairs = layers[0].iter().flatten().zip(layers[1].iter().flatten());
+        for (&below, &above) in pairs {
+            assert_eq!(tileset.tiles[below].faces.0, tileset.tiles[above].faces.1);
+        }
+    }
+
+    let error = Model::new(
+        &tileset,
+        Options {
+            depth: Some(2),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(error, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn hex_neighbors_connect_on_all_six_edges() {
+    let tileset: HexTileset = serde_json::from_str(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "land.png", "sockets": [[0], [0], [0], [0], [0], [0]] },
+                { "image": "ridge.png", "sockets": [[1], [0], [0], [1], [0], [0]], "weight": 3 }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let mut model = HexModel::new(
+        &tileset,
+        HexOptions {
+            width: 8,
+            height: 6,
+            seed: Some(2),
+        },
+    )
+    .unwrap();
+    model.run().unwrap();
+    assert_eq!(model.to_indices().unwrap().len(), 6);
+
+    let mut ridges = 0;
+    for r in 0..6 {
+        for q in -(r / 2)..8 - r / 2 {
+            let tile = model.tile_at(q, r).unwrap();
+            ridges += (tile.image() == "ridge.png") as usize;
+            for (direction, (dq, dr)) in hex::DIRECTIONS.iter().enumerate() {
+                if let Some(other) = model.tile_at(q + dq, r + dr) {
+                    assert!(tile.connects_to(other, direction), "({}, {})", q, r);
+                }
+            }
+        }
+    }
+    assert!(ridges > 0);
+    assert!(model.tile_at(-1, 0).is_none());
+
+    let error = serde_json::from_str::<HexTileset>(
+        r#"{ "size": 16, "tiles": [{ "image": "a.png", "sockets": [[0], [0], [0], [0]] }] }"#,
+    )
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("hex tile `a.png` has 4 sockets, but needs one per edge (6)"));
+}
+
+#[test]
+fn triangle_neighbors_connect_on_all_three_edges() {
+    let tileset: TriTileset = serde_json::from_str(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "plain.png", "sockets": [[0, 0], [0, 0], [0, 0]] },
+                { "image": "fork.png", "sockets": [[0, 1], [1, 0], [0, 0]], "weight": 2 },
+                { "image": "wave.png", "sockets": [[1, 0], [0, 1], [0, 0]], "weight": 2 }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let mut model = TriModel::new(
+        &tileset,
+        TriOptions {
+            width: 9,
+            height: 5,
+            seed: Some(4),
+        },
+    )
+    .unwrap();
+    model.run().unwrap();
+
+    let mut decorated = 0;
+    for y in 0..5 {
+        for x in 0..9 {
+            let tile = model.tile_at(x, y).unwrap();
+            decorated += (tile.image() != "plain.png") as usize;
+            let neighbors: [(isize, isize); 3] = if TriModel::points_up(x, y) {
+                [(1, 0), (0, 1), (-1, 0)]
+            } else {
+                [(-1, 0), (0, -1), (1, 0)]
+            };
+            for (edge, (dx, dy)) in neighbors.iter().enumerate() {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if nx < 0 || ny < 0 {
+                    continue;
+                }
+                if let Some(other) = model.tile_at(nx as usize, ny as usize) {
+                    assert!(tile.connects_to(other, edge), "({}, {})", x, y);
+                }
+            }
+        }
+    }
+    assert!(decorated > 0);
+    assert!(TriModel::points_up(0, 0) && !TriModel::points_up(1, 0));
+
+    let error = serde_json::from_str::<TriTileset>(
+        r#"{ "size": 16, "tiles": [{ "image": "a.png", "sockets": [[0], [0], [0], [0]] }] }"#,
+    )
+    .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("triangle tile `a.png` has 4 sockets, but needs one per edge (3)"));
+}
+
+#[test]
+fn graph_neighbors_connect_across_their_ports() {
+    let tileset: GraphTileset = serde_json::from_str(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "path.png", "sockets": [[1], [0], [1], [0]], "weight": 3 },
+                { "image": "corner.png", "sockets": [[1], [1], [0], [0]], "weight": 2 }
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    // A pentagon, which no square grid can make, with each node's port 0
+    // joined to its neighbor's port 2, and every node's port 1 joined to a
+    // hub. The hub touches five nodes, so it uses ports 0 to 4.
+    let mut ring = Topology::new(6);
+    for node in 0..5 {
+        ring.connect(node, 0, (node + 1) % 5, 2).unwrap();
+        ring.connect(node, 1, 5, node).unwrap();
+    }
+    assert!(ring.connect(0, 0, 3, 3).is_err());
+    assert!(ring.connect(0, 3, 6, 0).is_err());
+    assert_eq!(ring.walk(0, &[0, 0, 1]), Some(5));
+    assert_eq!(ring.walk(0, &[0, 3]), None);
+    // Up and then left from the top-left corner of a torus.
+    assert_eq!(Topology::torus(4, 3).walk(0, &[0, 3]), Some(11));
+
+    let error = GraphModel::new(&tileset, &ring, GraphOptions::default()).unwrap_err();
+    assert!(matches!(error, WfcError::InvalidTileset(message)
+        if message.contains("graph tile `grass.png` has 4 sockets, but the topology uses port 4")));
+
+    // A row of six cells that wraps around, right edge to left edge.
+    let mut row = Topology::new(6);
+    for node in 0..6 {
+        row.connect(node, 1, (node + 1) % 6, 3).unwrap();
+    }
+    for topology in [Topology::square(7, 5), Topology::torus(4, 3), row] {
+        let mut model =
+            GraphModel::new(&tileset, &topology, GraphOptions { seed: Some(2) }).unwrap();
+        model.run().unwrap();
+        assert_eq!(model.to_indices().unwrap().len(), topology.len());
+        for node in 0..topology.len() {
+            let tile = model.tile_at(node).unwrap();
+            for &(port, neighbor, other_port) in topology.edges(node) {
+                let other = model.tile_at(neighbor).unwrap();
+                assert!(tile.connects_to(port, other, other_port), "node {}", node);
+            }
+        }
+    }
+}
+
+#[test]
+fn symmetric_output_mirrors_across_its_seams() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "road.png", "sockets": [[1], [0], [1], [0]], "rotations": 2 },
+                { "image": "bend.png", "sockets": [[1], [1], [0], [0]], "rotations": true },
+                { "image": "flag.png", "sockets": [[0], [0], [2], [3]], "reflect": "both" }
+            ]
+        }"#,
+    );
+    let adjacency = Adjacency::new(&tileset, &[]).unwrap();
+
+    for (reflect, width, height) in [
+        (Reflection::Horizontal, 7, 5),
+        (Reflection::Vertical, 6, 6),
+        (Reflection::Both, 8, 7),
+    ] {
+        let mut model = SymmetricModel::new(
+            &tileset,
+            Options {
+                width: Some(width),
+                height: Some(height),
+                seed: Some(3),
+                recovery: Some(RecoveryMode::Backtrack),
+                ..Default::default()
+            },
+            reflect,
+        )
+        .unwrap();
+        let grid = model.run().unwrap();
+        let indices = grid.to_indices().unwrap();
+        assert_eq!((indices[0].len(), indices.len()), (width, height));
+
+        let tiles = &tileset.tiles;
+        for y in 0..height {
+            for x in 0..width {
+                let tile = indices[y][x];
+                if reflect != Reflection::Vertical {
+                    let mirror = &tiles[indices[y][width - 1 - x]];
+                    assert_eq!(mirror.image, tiles[tile].image);
+                    assert_eq!(mirror.sockets, tiles[tile].mirrored().sockets);
+                }
+                if reflect != Reflection::Horizontal {
+                    let mirror = &tiles[indices[height - 1 - y][x]];
+                    let flipped = tiles[tile].mirrored().rotated().rotated();
+                    assert_eq!(mirror.image, tiles[tile].image);
+                    assert_eq!(mirror.sockets, flipped.sockets);
+                }
+                if x + 1 < width {
+                    assert!(
+                        adjacency.allows(tile, Edge::Right, indices[y][x + 1]),
+                        "{:?} ({}, {})",
+                        reflect,
+                        x,
+                        y
+                    );
+                }
+                if y + 1 < height {
+                    assert!(
+                        adjacency.allows(tile, Edge::Bottom, indices[y + 1][x]),
+                        "{:?} ({}, {})",
+                        reflect,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn wrapped_grids_tile_seamlessly() {
+    let tileset = tileset(PIXIE);
+    let mut model = Model::new(
+        &tileset,
+        Options {
+            width: Some(8),
+            height: Some(6),
+            seed: Some(4),
+            recovery: Some(RecoveryMode::Backtrack),
+            wrap: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+
+    let tiles = &tileset.tiles;
+    for row in &indices {
+        let (last, first) = (row[7], row[0]);
+        assert!(tiles[last].connects_to(&tiles[first], Edge::Right, &tileset.soft) > 0.0);
+    }
+    for (&last, &first) in indices[5].iter().zip(&indices[0]) {
+        assert!(tiles[last].connects_to(&tiles[first], Edge::Bottom, &tileset.soft) > 0.0);
+    }
+}
+
+#[test]
+fn borders_restrict_edge_cells_before_the_first_step() {
+    let tileset = tileset(COAST);
+    let options = |borders| Options {
+        width: Some(6),
+        height: Some(4),
+        seed: Some(COAST_SEED),
+        borders,
+        ..Default::default()
+    };
+    let mut model = Model::new(
+        &tileset,
+        options(vec![
+            (Edge::Top, vec![SockName::Number(0)]),
+            (Edge::Bottom, vec![SockName::Number(1)]),
+        ]),
+    )
+    .unwrap();
+
+    let cells = model.possibilities_snapshot();
+    assert!(cells[0].iter().all(|cell| cell == &[0, 1]));
+    assert!(cells[3].iter().all(|cell| cell == &[1, 2, 3]));
+
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    assert!(indices[0].iter().all(|&tile| tile <= 1));
+    assert!(indices[3].iter().all(|&tile| tile >= 1));
+
+    let error = Model::new(
+        &tileset,
+        options(vec![(Edge::Left, vec![SockName::Number(7)])]),
+    );
+    assert!(matches!(error, Err(WfcError::InvalidGrid(_))));
+    for (width, height) in [(0, 3), (3, 0)] {
+        let mut empty = Model::builder(&tileset)
+            .width(width)
+            .height(height)
+            .border(Edge::Right, vec![0])
+            .border(Edge::Bottom, vec![1])
+            .build()
+            .unwrap();
+        assert!(empty.run().succeeded);
+    }
+}
+
+#[test]
+fn masked_out_cells_sit_out_generation() {
+    let tileset = tileset(COAST);
+    let mut shape = RgbaImage::new(5, 4);
+    for (x, y, pixel) in shape.enumerate_pixels_mut() {
+        if (x as i32 - 2).abs() + (y as i32 - 2).abs() <= 2 {
+            *pixel = image::Rgba([255, 255, 255, 255]);
+        }
+    }
+    let mask = alpha_mask(&shape);
+    assert_eq!((mask.len(), mask[0].len()), (4, 5));
+
+    for propagator in [Propagator::Worklist, Propagator::SupportCount] {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                seed: Some(COAST_SEED),
+                propagator: Some(propagator),
+                recovery: Some(RecoveryMode::Restart),
+                mask: Some(mask.clone()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        model.grid().check_contradiction().unwrap();
+        assert_eq!(model.grid().collapsed_fraction(), 1.0);
+        assert!(model.grid().to_indices().is_none());
+
+        let indices = model.grid().to_masked_indices().unwrap();
+        for (row, mask) in indices.iter().zip(&mask) {
+            for (tile, &inside) in row.iter().zip(mask) {
+                assert_eq!(tile.is_some(), inside);
+            }
+        }
+    }
+
+    let error = Model::new(
+        &tileset,
+        Options {
+            width: Some(6),
+            mask: Some(mask),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(error, Err(WfcError::InvalidGrid(_))));
+}
+
+#[test]
+fn resized_masks_keep_their_shape() {
+    let tileset = tileset(COAST);
+    let mut masked = Model::builder(&tileset)
+        .seed(COAST_SEED)
+        .recovery(RecoveryMode::Restart)
+        .mask(vec![vec![true; 4]; 4])
+        .build()
+        .unwrap();
+
+    for (width, height) in [(6, 6), (3, 2)] {
+        masked.resize(width, height);
+        masked.run();
+        masked.grid().check_contradiction().unwrap();
+        let indices = masked.grid().to_masked_indices().unwrap();
+        assert_eq!((indices[0].len(), indices.len()), (width, height));
+        for (y, row) in indices.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                assert_eq!(tile.is_some(), x < 4 && y < 4);
+            }
+        }
+    }
+}
+
+#[test]
+fn chunk_seams_match_in_any_order() {
+    let tileset = tileset(PIXIE);
+    let options = || Options {
+        seed: Some(9),
+        recovery: Some(RecoveryMode::Backtrack),
+        ..Default::default()
+    };
+    let mut world = ChunkedModel::new(&tileset, 5, options()).unwrap();
+    for (cx, cy) in [(1, 1), (-1, 0), (0, 0), (1, 0), (0, 1), (-1, 1)] {
+        world.chunk(cx, cy).unwrap();
+    }
+    assert_eq!(world.len(), 6);
+
+    let tiles = &tileset.tiles;
+    for y in 0..10 {
+        for x in -5..10 {
+            let tile = &tiles[world.tile_at(x, y).unwrap()];
+            if x + 1 < 10 {
+                let right = &tiles[world.tile_at(x + 1, y).unwrap()];
+                assert!(tile.connects_to(right, Edge::Right, &tileset.soft) > 0.0);
+            }
+            if y + 1 < 10 {
+                let below = &tiles[world.tile_at(x, y + 1).unwrap()];
+                assert!(tile.connects_to(below, Edge::Bottom, &tileset.soft) > 0.0);
+            }
+        }
+    }
+
+    let mut again = ChunkedModel::new(&tileset, 5, options()).unwrap();
+    assert_eq!(again.chunk(1, 1).unwrap(), world.chunk(1, 1).unwrap());
+}
+
+#[test]
+fn adjacency_rules_replace_sockets() {
+    let rules = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [{ "image": "light.png" }, { "image": "dark.png" }],
+            "adjacency": [
+                { "tile": "light.png", "edge": "right", "other": "dark.png" },
+                { "tile": "dark.png", "edge": "right", "other": "light.png" },
+                { "tile": "light.png", "edge": "bottom", "other": "light.png" },
+                { "tile": "dark.png", "edge": "bottom", "other": "dark.png" }
+            ]
+        }"#,
+    );
+    let mut model = model(&rules, 6, 4, 3);
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    let first = indices[0][0];
+    for row in &indices {
+        for (x, &tile) in row.iter().enumerate() {
+            assert_eq!(tile, (first + x) % 2);
+        }
+    }
+    assert_eq!(
+        rules.connection(&rules.tiles[0], &rules.tiles[1], Edge::Left),
+        1.0
+    );
+    assert_eq!(
+        rules.connection(&rules.tiles[0], &rules.tiles[1], Edge::Top),
+        0.0
+    );
+
+    let unknown = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [{ "image": "light.png" }],
+            "adjacency": [{ "tile": "light.png", "edge": "top", "other": "dusk.png" }]
+        }"#,
+    );
+    assert!(matches!(
+        Model::new(&unknown, Options::default()),
+        Err(WfcError::InvalidTileset(_))
+    ));
+}
+
+#[test]
+fn wildcard_sockets_match_anything() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "wall.png", "sockets": [[0, 0], [1], [0, 0], [1]] },
+                { "image": "floor.png", "sockets": [[2, 2], [3], [2, 2], [3]] },
+                { "image": "decal.png", "sockets": ["*", "*", "*", "*"] }
+            ]
+        }"#,
+    );
+    let tiles = &tileset.tiles;
+    assert_eq!(tiles[0].connects_to(&tiles[1], Edge::Right, &[]), 0.0);
+    for edge in EDGES {
+        assert_eq!(tiles[2].connects_to(&tiles[0], edge, &[]), 1.0);
+        assert_eq!(tiles[1].connects_to(&tiles[2], edge, &[]), 1.0);
+    }
+    assert!(Model::new(&tileset, Options::default()).is_ok());
+
+    let error = serde_json::from_str::<Tileset>(
+        r#"{ "size": 16, "tiles": [{ "image": "a.png", "sockets": [[4294967295], "*", "*", "*"] }] }"#,
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("edge 0 (Top)"));
+}
+
+#[test]
+fn pair_weights_favor_like_neighbors() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "forest.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "desert.png", "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    // Scanline order, so each cell is weighed against the cell before it,
+    // as it was before entropy ties were broken at random.
+    let alike = |pair_weights| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(16),
+                height: Some(16),
+                seed: Some(8),
+                heuristic: Some(Heuristic::Scanline),
+                pair_weights,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.run();
+        let indices = model.grid().to_indices().unwrap();
+        let pairs = indices
+            .iter()
+            .flat_map(|row| row.windows(2))
+            .filter(|pair| pair[0] == pair[1])
+            .count();
+        pairs as f64 / (16.0 * 15.0)
+    };
+
+    assert!(alike(Vec::new()) < 0.65);
+    assert!(alike(vec![(0, 0, 20.0), (1, 1, 20.0)]) > 0.8);
+    assert!(matches!(
+        Model::new(
+            &tileset,
+            Options {
+                pair_weights: vec![(0, 2, 1.0)],
+                ..Default::default()
+            }
+        ),
+        Err(WfcError::UnknownTile(2))
+    ));
+}
+
+#[test]
+fn annealing_repairs_a_greedy_fill() {
+    let tileset = tileset(PIXIE);
+    let mut model = AnnealingModel::new(
+        &tileset,
+        AnnealOptions {
+            width: 10,
+            height: 10,
+            seed: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let before = model.conflicts();
+    model.run().unwrap();
+    assert!(before > 0);
+    assert_eq!(model.conflicts(), 0);
+
+    let tiles = &tileset.tiles;
+    let indices = model.to_indices();
+    for row in &indices {
+        for pair in row.windows(2) {
+            assert!(tiles[pair[0]].connects_to(&tiles[pair[1]], Edge::Right, &tileset.soft) > 0.0);
+        }
+    }
+    for rows in indices.windows(2) {
+        for (&above, &below) in rows[0].iter().zip(&rows[1]) {
+            assert!(tiles[above].connects_to(&tiles[below], Edge::Bottom, &tileset.soft) > 0.0);
+        }
+    }
+}
+
+#[test]
+fn entropy_ties_are_broken_at_random() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "forest.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "desert.png", "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    let second = |seed, deterministic| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(8),
+                height: Some(8),
+                seed: Some(seed),
+                deterministic,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        model.grid_mut().step();
+        model.grid_mut().step();
+        let cells = model.possibilities_snapshot();
+        (0..64)
+            .map(|i| (i % 8, i / 8))
+            .find(|&(x, y)| (x, y) != (0, 0) && cells[y][x].len() == 1)
+            .unwrap()
+    };
+
+    let picks = (0..8)
+        .map(|seed| second(seed, false))
+        .collect::<std::collections::HashSet<_>>();
+    assert!(picks.len() > 1);
+    assert_eq!(second(0, true), (1, 0));
+}
+
+#[test]
+fn observations_steer_a_run_in_progress() {
+    let tileset = tileset(COAST);
+    let mut model = model(&tileset, 6, 4, COAST_SEED);
+    model.grid_mut().step();
+
+    let cells = model.possibilities_snapshot();
+    let (x, y) = (0..24)
+        .map(|i| (i % 6, i / 6))
+        .find(|&(x, y)| cells[y][x].len() > 1)
+        .unwrap();
+    let tile = *cells[y][x].last().unwrap();
+    model.observe(x, y, tile).unwrap();
+    let other = cells[y][x][0];
+    assert!(matches!(
+        model.observe(x, y, other),
+        Err(WfcError::Contradiction { .. })
+    ));
+
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    assert_eq!(indices[y][x], tile);
+    assert!(matches!(
+        model.observe(6, 0, 0),
+        Err(WfcError::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn bans_rule_tiles_out_until_undone() {
+    let tileset = tileset(COAST);
+    let mut model = model(&tileset, 6, 4, COAST_SEED);
+    let initial = possibilities(model.grid());
+
+    model.ban(0, 0, 0).unwrap();
+    let banned = possibilities(model.grid());
+    assert!(!model.possibilities_snapshot()[0][0].contains(&0));
+    model.ban(0, 0, 0).unwrap();
+    assert!(model.undo());
+    assert!(!model.undo());
+    assert_eq!(possibilities(model.grid()), initial);
+    assert!(model.redo());
+    assert_eq!(possibilities(model.grid()), banned);
+
+    model.ban(2, 2, 2).unwrap();
+    model.run();
+    let indices = model.grid().to_indices().unwrap();
+    assert_ne!(indices[2][2], 2);
+    assert!(matches!(model.ban(0, 0, 9), Err(WfcError::UnknownTile(9))));
+}
+
+#[test]
+fn heuristics_choose_the_cell_order() {
+    let tileset = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "forest.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "desert.png", "sockets": [[0], [0], [0], [0]] }
+            ]
+        }"#,
+    );
+    let collapsed_after = |heuristic, steps| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(5),
+                height: Some(5),
+                seed: Some(1),
+                heuristic: Some(heuristic),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        for _ in 0..steps {
+            model.grid_mut().step();
+        }
+        let cells = model.possibilities_snapshot();
+        (0..25)
+            .map(|i| (i % 5, i / 5))
+            .filter(|&(x, y)| cells[y][x].len() == 1)
+            .collect::<Vec<_>>()
+    };
+
+    let scanline = collapsed_after(Heuristic::Scanline, 7);
+    assert_eq!(scanline, (0..7).map(|i| (i % 5, i / 5)).collect::<Vec<_>>());
+
+    let mut spiral = collapsed_after(Heuristic::Spiral, 10);
+    spiral.retain(|&cell| cell != (0, 0));
+    assert_eq!(spiral.len(), 9);
+    assert!(spiral
+        .iter()
+        .all(|&(x, y)| (1..4).contains(&x) && (1..4).contains(&y)));
+    assert_eq!(
+        super::spiral(3, 2),
+        [(1, 1), (2, 1), (0, 1), (0, 0), (1, 0), (2, 0)]
+    );
+
+    let random = collapsed_after(Heuristic::Random, 7);
+    assert_eq!(random.len(), 7);
+    assert_ne!(random, scanline);
+}
+
+#[test]
+fn recollapse_rerolls_only_the_region() {
+    let tileset = tileset(PIXIE);
+    let mut model = Model::new(
+        &tileset,
+        Options {
+            width: Some(10),
+            height: Some(10),
+            seed: Some(3),
+            recovery: Some(RecoveryMode::Restart),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    model.run();
+    let before = model.grid().to_indices().unwrap();
+
+    let rect = Rect {
+        x: 2,
+        y: 3,
+        width: 4,
+        height: 3,
+    };
+    let rerolled = (0..5).any(|_| {
+        model.recollapse(rect).unwrap();
+        let after = model.grid().to_indices().unwrap();
+        for (y, (row, old)) in after.iter().zip(&before).enumerate() {
+            for (x, (tile, old)) in row.iter().zip(old).enumerate() {
+                let inside = (2..6).contains(&x) && (3..6).contains(&y);
+                assert!(inside || tile == old);
+            }
+        }
+        after != before
+    });
+    assert!(rerolled);
+
+    let tiles = &tileset.tiles;
+    let after = model.grid().to_indices().unwrap();
+    for row in &after {
+        for pair in row.windows(2) {
+            assert!(tiles[pair[0]].connects_to(&tiles[pair[1]], Edge::Right, &tileset.soft) > 0.0);
+        }
+    }
+    assert!(matches!(
+        model.recollapse(Rect {
+            x: 8,
+            width: 3,
+            ..rect
+        }),
+        Err(WfcError::OutOfBounds { x: 10, .. })
+    ));
+}
+
+#[test]
+fn periodic_output_tiles_seamlessly() {
+    let tileset = tileset(PIXIE);
+    let run = |periodic| {
+        let mut model = Model::new(
+            &tileset,
+            Options {
+                width: Some(8),
+                height: Some(8),
+                seed: Some(6),
+                recovery: Some(RecoveryMode::Backtrack),
+                periodic,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let stats = model.run();
+        (stats.succeeded, model.grid().is_seamless())
+    };
+
+    assert_eq!(run(true), (true, true));
+    assert_eq!(run(false), (true, false));
+}
+
+#[test]
+fn builder_matches_options() {
+    let tileset = tileset(PIXIE);
+    let mut built = Model::builder(&tileset)
+        .width(8)
+        .height(8)
+        .seed(6)
+        .recovery(RecoveryMode::Backtrack)
+        .pin(0, 0, 1)
+        .build()
+        .unwrap();
+    let mut from_options = Model::new(
+        &tileset,
+        Options {
+            width: Some(8),
+            height: Some(8),
+            seed: Some(6),
+            recovery: Some(RecoveryMode::Backtrack),
+            pins: vec![(0, 0, 1)],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    built.run();
+    from_options.run();
+
+    assert_eq!(built.grid().to_indices(), from_options.grid().to_indices());
+    assert!(matches!(
+        Model::builder(&tileset).pin(10, 0, 1).build(),
+        Err(WfcError::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn loading_and_empty_tilesets_fail_with_errors() {
+    assert!(matches!(
+        Tileset::from_json("{\"size\": 16}"),
+        Err(WfcError::InvalidTileset(_))
+    ));
+    assert!(matches!(
+        Tileset::load("no/such/tileset.json"),
+        Err(WfcError::Io(_))
+    ));
+
+    let empty = Tileset::from_json(r#"{"size": 16, "tiles": []}"#).unwrap();
+    assert!(matches!(
+        Model::builder(&empty).build(),
+        Err(WfcError::EmptyTileset)
+    ));
+}
+
+#[test]
+fn steps_yield_every_collapse_of_a_run() {
+    let tileset = tileset(COAST);
+    let mut stepped = model(&tileset, 6, 4, COAST_SEED);
+    let mut steps = stepped.steps();
+    let mut seen = Vec::new();
+    while let Some(step) = steps.next() {
+        assert_eq!(step.collapsed, steps.grid().collapsed_fraction());
+        assert!(!step.contradicted);
+        seen.push(step);
+    }
+
+    assert_eq!(seen.last().map(|step| step.collapsed), Some(1.0));
+    assert!(seen.iter().all(|step| step.tile.is_some()));
+    assert_eq!(stepped.grid().to_indices(), Some(golden()));
+    assert_eq!(stepped.steps().next(), None);
+}
+
+#[test]
+fn observers_see_every_collapse_and_elimination() {
+    use std::sync::Mutex;
+
+    let tileset = tileset(COAST);
+    let mut observed = model(&tileset, 6, 4, COAST_SEED);
+    let collapses = Arc::new(Mutex::new(Vec::new()));
+    let eliminations = Arc::new(Mutex::new(0));
+    {
+        let collapses = Arc::clone(&collapses);
+        observed.on_collapse(move |x, y, tile| collapses.lock().unwrap().push((x, y, tile)));
+        let eliminations = Arc::clone(&eliminations);
+        observed.on_eliminate(move |_, _, _| *eliminations.lock().unwrap() += 1);
+    }
+    observed.run();
+
+    let golden = golden();
+    let mut collapses = collapses.lock().unwrap().clone();
+    collapses.sort();
+    let mut expected = (0..4)
+        .flat_map(|y| (0..6).map(move |x| (x, y)))
+        .map(|(x, y)| (x, y, golden[y][x]))
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(collapses, expected);
+    assert_eq!(
+        *eliminations.lock().unwrap(),
+        24 * (tileset.tiles.len() - 1)
+    );
+}
+
+#[test]
+fn step_limits_spread_a_run_across_calls() {
+    let tileset = tileset(COAST);
+    let mut limited = model(&tileset, 6, 4, COAST_SEED);
+    assert!(!limited.run_with_limit(0));
+    assert!(!limited.run_with_limit(1));
+    assert!(limited.grid().collapsed_fraction() < 1.0);
+
+    let mut calls = 1;
+    while !limited.run_with_limit(2) {
+        calls += 1;
+    }
+    assert!(calls > 1);
+    assert_eq!(limited.grid().to_indices(), Some(golden()));
+}
+
+#[test]
+fn snapshots_resume_where_they_left_off() {
+    let tileset = tileset(COAST);
+    let mut first = model(&tileset, 6, 4, COAST_SEED);
+    first.run_with_limit(3);
+    let json = serde_json::to_string(&first.snapshot()).unwrap();
+
+    let mut second = model(&tileset, 6, 4, 1);
+    second.resume(serde_json::from_str(&json).unwrap()).unwrap();
+    second.run();
+    assert_eq!(second.grid().to_indices(), Some(golden()));
+    assert!(second.undo());
+
+    let mut small = model(&tileset, 5, 4, COAST_SEED);
+    assert!(matches!(
+        small.resume(first.snapshot()),
+        Err(WfcError::InvalidGrid(_))
+    ));
+}
+
+#[test]
+fn results_describe_every_collapsed_cell() {
+    let tileset = tileset(PIXIE);
+    let mut finished = Model::builder(&tileset)
+        .width(8)
+        .height(8)
+        .seed(6)
+        .recovery(RecoveryMode::Backtrack)
+        .build()
+        .unwrap();
+    assert_eq!(finished.result(), None);
+    finished.run();
+
+    let result = finished.result().unwrap();
+    let indices = finished.grid().to_indices().unwrap();
+    assert_eq!((result.width, result.height), (8, 8));
+    for (y, row) in result.cells.iter().enumerate() {
+        for (x, placed) in row.iter().enumerate() {
+            let placed = placed.unwrap();
+            let config = &tileset.tiles[indices[y][x]];
+            assert_eq!(placed.tile, indices[y][x]);
+            assert_eq!(placed.image, config.image);
+            assert_eq!(Some(placed.rotation), finished.grid().rotation_at(x, y));
+            assert_eq!(placed.mirrored, config.mirrored);
+        }
+    }
+}
+
+#[test]
+fn grid_cells_can_be_read_by_position() {
+    let tileset = tileset(COAST);
+    let mut finished = model(&tileset, 6, 4, COAST_SEED);
+    finished.run();
+    let grid = finished.grid();
+    let golden = golden();
+
+    assert_eq!(grid.get(2, 3).and_then(Cell::tile), Some(golden[3][2]));
+    assert!(grid.get(6, 0).is_none());
+    assert_eq!(grid[(2, 3)].tile(), Some(golden[3][2]));
+    assert_eq!(grid.iter().count(), 24);
+    assert!(grid
+        .iter()
+        .all(|(x, y, cell)| cell.possibilities() == &BTreeSet::from([golden[y][x]])));
+}
+
+#[test]
+fn progress_counts_cells_and_steps() {
+    let tileset = tileset(COAST);
+    let mut counted = model(&tileset, 6, 4, COAST_SEED);
+    assert_eq!(
+        counted.progress(),
+        Progress {
+            collapsed: 0,
+            total: 24,
+            contradictions: 0,
+            steps: 0,
+        }
+    );
+
+    counted.run_with_limit(2);
+    let partway = counted.progress();
+    assert_eq!(partway.steps, 2);
+    assert!(partway.collapsed >= 2 && partway.collapsed < 24);
+
+    counted.run();
+    let done = counted.progress();
+    assert_eq!((done.collapsed, done.contradictions), (24, 0));
+    assert!(done.steps >= partway.steps);
+}
+
+#[test]
+fn cancelled_runs_stop_between_steps() {
+    let tileset = tileset(PIXIE);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let mut cancelled = Model::builder(&tileset)
+        .width(12)
+        .height(12)
+        .seed(3)
+        .cancel(Arc::clone(&cancel))
+        .retry(RetryPolicy {
+            max_attempts: 5,
+            reseed: true,
+        })
+        .build()
+        .unwrap();
+    {
+        let cancel = Arc::clone(&cancel);
+        cancelled.on_collapse(move |_, _, _| cancel.store(true, Ordering::Relaxed));
+    }
+
+    let stats = cancelled.run();
+    assert!(stats.cancelled && !stats.succeeded);
+    assert_eq!(stats.attempts, 1);
+    assert_eq!(cancelled.progress().steps, 1);
+    assert!(cancelled.grid().collapsed_fraction() < 1.0);
+    assert!(matches!(cancelled.try_run(), Err(WfcError::Cancelled)));
+
+    cancel.store(false, Ordering::Relaxed);
+    cancelled.on_collapse(|_, _, _| {});
+    assert!(!cancelled.run().cancelled);
+    assert!(cancelled.grid().is_finished());
+}
+
+#[test]
+fn tile_data_reaches_the_result() {
+    #[derive(Deserialize, Clone, Default, PartialEq, Debug)]
+    struct Terrain {
+        #[serde(default)]
+        solid: bool,
+        #[serde(default)]
+        spawns: Vec<String>,
+    }
+
+    let json = |mud: &str| {
+        format!(
+            r#"{{
+                "size": 16,
+                "tiles": [
+                    {{ "image": "wall.png", "sockets": [[0], [1], [0], [1]], "data": {{ "solid": true }} }},
+                    {{ "image": "mud.png", "sockets": [[0], [1], [0], [1]], "symmetry": "I", "data": {} }},
+                    {{ "image": "air.png", "sockets": [[2], [2], [2], [2]] }}
+                ]
+            }}"#,
+            mud
+        )
+    };
+    let tileset = Tileset::<Terrain>::from_json_typed(&json(r#"{ "spawns": ["frog"] }"#)).unwrap();
+    assert_eq!(
+        tileset.tiles[0].data(),
+        &Terrain {
+            solid: true,
+            spawns: Vec::new()
+        }
+    );
+    // Variants carry their tile's data, and tiles without any get the
+    // default.
+    assert_eq!(tileset.tiles[2].data().spawns, ["frog"]);
+    assert_eq!(tileset.tiles[3].data(), &Terrain::default());
+    assert!(matches!(
+        Tileset::<Terrain>::from_json_typed(&json("3")),
+        Err(WfcError::InvalidTileset(_))
+    ));
+    // Untyped tilesets keep the data as raw JSON.
+    let raw = Tileset::from_json(&json("3")).unwrap();
+    assert_eq!(raw.tiles[2].data(), &serde_json::json!(3));
+    assert!(raw.tiles[3].data().is_null());
+
+    let mut walls = Model::builder(&tileset)
+        .width(3)
+        .height(3)
+        .seed(1)
+        .build()
+        .unwrap();
+    walls.run();
+    for placed in walls.result().unwrap().cells.iter().flatten().flatten() {
+        let data: &Terrain = placed.data;
+        assert_eq!(data, tileset.tiles[placed.tile].data());
+    }
+}
+
+#[test]
+fn layered_tile_data_reaches_the_result() {
+    #[derive(Deserialize, Clone, Default, PartialEq, Debug)]
+    struct Depth(u32);
+
+    let tileset = Tileset::<Depth>::from_json_typed(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "shallow.png", "sockets": [[0], [0], [0], [0]], "data": 1 },
+                { "image": "deep.png", "sockets": [[0], [0], [0], [0]], "data": 2 }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let options = |seed| Options {
+        width: Some(4),
+        height: Some(4),
+        seed: Some(seed),
+        ..Default::default()
+    };
+    // Deep water only lies under shallow water.
+    let mut model = LayeredModel::new(
+        vec![(&tileset, options(1)), (&tileset, options(2))],
+        |(_, below), (_, above)| below == 1 || above == 0,
+    )
+    .unwrap();
+    model.run();
+
+    let below = model.grid(0).unwrap().result().unwrap();
+    let above = model.grid(1).unwrap().result().unwrap();
+    let pairs = below
+        .cells
+        .iter()
+        .flatten()
+        .zip(above.cells.iter().flatten());
+    for (below, above) in pairs {
+        let (below, above): (&Depth, &Depth) = (below.unwrap().data, above.unwrap().data);
+        assert!(below == &Depth(2) || above == &Depth(1));
+    }
+}
+
+#[test]
+fn validation_reports_tileset_problems() {
+    assert!(tileset(COAST).validate().is_empty());
+    assert!(tileset(PIXIE).validate().is_empty());
+    assert_eq!(
+        tileset(r#"{ "size": 16, "tiles": [] }"#).validate(),
+        vec![TilesetProblem::Empty]
+    );
+
+    let broken = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]], "tags": ["summer"] },
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "cliff.png", "sockets": [[0], [0], [1, 2], [0]] }
+            ],
+            "exclusive": [["summer", "winter"]]
+        }"#,
+    );
+    let problems = broken.validate();
+    assert!(matches!(problems[0], TilesetProblem::Arity(_)));
+    assert_eq!(
+        problems[1..],
+        [
+            TilesetProblem::Duplicate { tile: 1, of: 0 },
+            TilesetProblem::Unmatched {
+                tile: 2,
+                edge: Edge::Bottom
+            },
+            TilesetProblem::UnknownTag("winter".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn labelled_socks_match_like_numbers() {
+    let labelled = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [["grass"], ["grass"], ["grass"], ["grass"]] },
+                { "image": "road.png", "sockets": [["grass"], ["road"], ["grass"], ["road"]] },
+                { "image": "mixed.png", "sockets": [[7], ["grass"], [7], ["grass"]] }
+            ]
+        }"#,
+    );
+    let numbered = tileset(
+        r#"{
+            "size": 16,
+            "tiles": [
+                { "image": "grass.png", "sockets": [[0], [0], [0], [0]] },
+                { "image": "road.png", "sockets": [[0], [1], [0], [1]] },
+                { "image": "mixed.png", "sockets": [[7], [0], [7], [0]] }
+            ]
+        }"#,
+    );
+    let tiles = |tileset: &Tileset| {
+        let adjacency = Adjacency::new(tileset, &[]).unwrap();
+        (0..3)
+            .flat_map(|tile| EDGES.map(|edge| adjacency.compatible[tile][edge as usize].clone()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(tiles(&labelled), tiles(&numbered));
+    assert!(labelled.validate().is_empty());
+
+    // Every tileset interns its own labels, from the same first id.
+    let other = tileset(
+        r#"{ "size": 16, "tiles": [{ "image": "road.png", "sockets": [["road"], [1], [1], [1]] }] }"#,
+    );
+    assert_eq!(labelled.labels.0.len(), 2);
+    assert_eq!(labelled.labels.0["grass"], 1 << 32);
+    assert_eq!(other.labels.0["road"], 1 << 32);
+
+    let mut bordered = Model::builder(&labelled)
+        .width(3)
+        .height(3)
+        .seed(1)
+        .border(Edge::Right, ["road"])
+        .build()
+        .unwrap();
+    bordered.run();
+    let indices = bordered.grid().to_indices().unwrap();
+    assert!(indices.iter().all(|row| row[2] == 1));
+    let error = Model::builder(&labelled)
+        .border(Edge::Top, ["lava"])
+        .build();
+    assert!(matches!(error, Err(WfcError::InvalidGrid(message)) if message.contains("[\"lava\"]")));
+}
+
+#[test]
+fn entropy_map_falls_as_cells_collapse() {
+    let tileset = tileset(COAST);
+    let mut mapped = model(&tileset, 6, 4, COAST_SEED);
+    let before = mapped.entropy_map();
+    assert_eq!(before.len(), 24);
+    assert!(before.iter().all(|&entropy| entropy > 0.0));
+
+    mapped.run_with_limit(1);
+    let after = mapped.entropy_map();
+    assert!(after
+        .iter()
+        .zip(&before)
+        .all(|(after, before)| after <= before));
+    assert!(after.contains(&0.0));
+
+    mapped.run();
+    assert!(mapped.entropy_map().iter().all(|&entropy| entropy == 0.0));
+}
+
+#[test]
+fn possibilities_show_what_could_still_go_in_a_cell() {
+    let tileset = tileset(COAST);
+    let mut inspected = model(&tileset, 6, 4, COAST_SEED);
+    assert_eq!(
+        inspected.possibilities(0, 0).collect::<Vec<_>>(),
+        (0..tileset.tiles.len()).collect::<Vec<_>>()
+    );
+    assert_eq!(inspected.possibilities(6, 0).count(), 0);
+
+    inspected.run();
+    assert_eq!(
+        inspected.possibilities(2, 3).collect::<Vec<_>>(),
+        [golden()[3][2]]
+    );
+}
+
+#[test]
+fn subscribers_hear_the_run_on_another_thread() {
+    let tileset = tileset(COAST);
+    let mut watched = model(&tileset, 6, 4, COAST_SEED);
+    let events = watched.subscribe();
+    let listener = std::thread::spawn(move || events.iter().collect::<Vec<_>>());
+    watched.run();
+    drop(watched);
+
+    let events = listener.join().unwrap();
+    let collapsed = events
+        .iter()
+        .filter(|event| matches!(event, WfcEvent::Collapsed { .. }))
+        .count();
+    let eliminated = events
+        .iter()
+        .filter(|event| matches!(event, WfcEvent::Eliminated { .. }))
+        .count();
+    assert_eq!(collapsed, 24);
+    assert_eq!(eliminated, 24 * (tileset.tiles.len() - 1));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, WfcEvent::Contradiction { .. })));
+    assert_eq!(events.last(), Some(&WfcEvent::Finished));
+}
+
+#[test]
+fn forks_try_collapses_without_touching_the_grid() {
+    use std::sync::atomic::AtomicUsize;
+
+    let tileset = tileset(COAST);
+    let mut searched = model(&tileset, 6, 4, COAST_SEED);
+    let collapses = Arc::new(AtomicUsize::new(0));
+    {
+        let collapses = Arc::clone(&collapses);
+        searched.on_collapse(move |_, _, _| {
+            collapses.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    let before = possibilities(searched.grid());
+
+    let mut fork = searched.grid().fork();
+    let other = (0..tileset.tiles.len())
+        .find(|&tile| tile != golden()[0][0])
+        .unwrap();
+    let _ = fork.collapse_cell(0, 0, other);
+    assert_eq!(fork.get(0, 0).and_then(Cell::tile), Some(other));
+    assert_eq!(possibilities(searched.grid()), before);
+
+    let mut fork = searched.grid().fork();
+    while !fork.is_finished() {
+        fork.step();
+    }
+    assert_eq!(collapses.load(Ordering::Relaxed), 0);
+    searched.run();
+    assert_eq!(collapses.load(Ordering::Relaxed), 24);
+    assert_eq!(fork.to_indices(), Some(golden()));
+    assert_eq!(searched.grid().to_indices(), Some(golden()));
+}
+
+/// Polls `future` on this thread until it's ready, for tests that don't
+/// want an async runtime.
+#[cfg(feature = "async")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Unpark(std::thread::Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_runs_match_blocking_ones() {
+    let tileset = tileset(COAST);
+    let mut blocking = model(&tileset, 6, 4, COAST_SEED);
+    let expected = blocking.run();
+
+    let mut awaited = model(&tileset, 6, 4, COAST_SEED);
+    let stats = block_on(awaited.run_async());
+    assert_eq!(stats.succeeded, expected.succeeded);
+    assert_eq!(stats.attempts, expected.attempts);
+    assert_eq!(awaited.grid().to_indices(), blocking.grid().to_indices());
+
+    let expected = model(&tileset, 6, 4, COAST_SEED)
+        .steps()
+        .collect::<Vec<_>>();
+    let mut stepped = model(&tileset, 6, 4, COAST_SEED);
+    let seen = block_on(async {
+        let mut steps = stepped.steps_async();
+        let mut seen = Vec::new();
+        while let Some(step) = steps.next().await {
+            seen.push(step);
+        }
+        seen
+    });
+    assert_eq!(seen, expected);
+    assert_eq!(stepped.grid().to_indices(), Some(golden()));
+}
@@ -0,0 +1,212 @@
+use super::{valid_weight, wave::Wave, Sock, WfcError};
+use serde::Deserialize;
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(try_from = "RawTriTile")]
+pub struct TriTile {
+    image: String,
+    /// Socks listed clockwise along each of the three edges, clockwise from
+    /// the apex of the tile as drawn pointing up: right, bottom, then left.
+    sockets: [Vec<Sock>; 3],
+    weight: f64,
+}
+
+#[derive(Deserialize)]
+struct RawTriTile {
+    image: String,
+    sockets: Vec<Vec<Sock>>,
+    #[serde(default)]
+    weight: Option<f64>,
+}
+
+impl TryFrom<RawTriTile> for TriTile {
+    type Error = String;
+
+    fn try_from(raw: RawTriTile) -> Result<Self, Self::Error> {
+        let count = raw.sockets.len();
+        let sockets = raw.sockets.try_into().map_err(|_| {
+            format!(
+                "triangle tile `{}` has {} sockets, but needs one per edge (3)",
+                raw.image, count
+            )
+        })?;
+        let weight = raw.weight.unwrap_or(1.0);
+        if !valid_weight(weight) {
+            return Err(format!(
+                "triangle tile `{}` has weight {}, but weights must be finite and at least 0",
+                raw.image, weight
+            ));
+        }
+
+        Ok(TriTile {
+            image: raw.image,
+            sockets,
+            weight,
+        })
+    }
+}
+
+impl TriTile {
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Whether `other` may sit past edge `edge` of this tile, with the same
+    /// reversed-socks rule as square tiles. Turning a tile to point down
+    /// carries each edge onto the one its neighbor shares with it, so edge
+    /// `edge` always meets the other tile's edge `edge`.
+    pub(super) fn connects_to(&self, other: &TriTile, edge: usize) -> bool {
+        self.sockets[edge]
+            .iter()
+            .eq(other.sockets[edge].iter().rev())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct TriTileset {
+    pub size: u32,
+    #[serde(deserialize_with = "super::labelled")]
+    pub tiles: Vec<TriTile>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TriOptions {
+    pub width: usize,
+    pub height: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for TriOptions {
+    fn default() -> Self {
+        TriOptions {
+            width: super::DEFAULT_WIDTH,
+            height: super::DEFAULT_HEIGHT,
+            seed: None,
+        }
+    }
+}
+
+/// The tiled model on rows of alternating up- and down-pointing triangles,
+/// where every cell has three neighbors. The top-left cell points up. Tiles
+/// are drawn pointing up, and turned half a turn to fill cells that point
+/// down.
+#[derive(Debug)]
+pub struct TriModel<'t> {
+    tileset: &'t TriTileset,
+    options: TriOptions,
+    wave: Wave,
+}
+
+impl<'t> TriModel<'t> {
+    pub fn new(tileset: &'t TriTileset, options: TriOptions) -> Result<Self, WfcError> {
+        let compatible = tileset
+            .tiles
+            .iter()
+            .map(|tile| {
+                (0..3)
+                    .map(|edge| {
+                        (0..tileset.tiles.len())
+                            .filter(|&other| tile.connects_to(&tileset.tiles[other], edge))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (width, height) = (options.width, options.height);
+        let neighbors = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as isize, (i / width) as isize);
+                let offsets = if TriModel::points_up(x as usize, y as usize) {
+                    [(1, 0), (0, 1), (-1, 0)]
+                } else {
+                    [(-1, 0), (0, -1), (1, 0)]
+                };
+                offsets
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(edge, (dx, dy))| {
+                        Some((index(width, height, x + dx, y + dy)?, edge))
+                    })
+                    .collect()
+            })
+            .collect();
+        let weights = tileset.tiles.iter().map(TriTile::weight).collect();
+
+        Ok(TriModel {
+            tileset,
+            wave: Wave::new(weights, compatible, neighbors, options.seed),
+            options,
+        })
+    }
+
+    /// Whether the triangle at `(x, y)` points up. Up and down alternate
+    /// along every row and column.
+    pub fn points_up(x: usize, y: usize) -> bool {
+        (x + y).is_multiple_of(2)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.wave.is_finished()
+    }
+
+    pub fn step(&mut self) {
+        self.wave.step();
+    }
+
+    /// Steps until every cell is decided, failing with the first
+    /// contradiction left once restarts run out.
+    pub fn run(&mut self) -> Result<(), WfcError> {
+        while !self.wave.is_finished() {
+            self.wave.step();
+        }
+        self.check_contradiction()
+    }
+
+    /// Fails with the first cell, by row and then column, that no tile fits.
+    pub fn check_contradiction(&self) -> Result<(), WfcError> {
+        match self.wave.contradiction() {
+            Some(i) => Err(WfcError::Contradiction {
+                x: i % self.options.width,
+                y: i / self.options.width,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The tile at `(x, y)`, once it has collapsed. Where
+    /// [`TriModel::points_up`] is false, it's turned half a turn from how
+    /// it's drawn.
+    pub fn tile_at(&self, x: usize, y: usize) -> Option<&'t TriTile> {
+        let i = index(
+            self.options.width,
+            self.options.height,
+            x as isize,
+            y as isize,
+        )?;
+        let tile = self.wave.collapsed(i)?;
+        Some(&self.tileset.tiles[tile])
+    }
+
+    /// The tile index of every cell, row by row, or `None` if any cell has
+    /// not collapsed to a single tile.
+    pub fn to_indices(&self) -> Option<Vec<Vec<usize>>> {
+        (0..self.options.height)
+            .map(|y| {
+                (0..self.options.width)
+                    .map(|x| self.wave.collapsed(y * self.options.width + x))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Where the cell at `(x, y)` is stored, if it's on the grid.
+fn index(width: usize, height: usize, x: isize, y: isize) -> Option<usize> {
+    let (width, height) = (width as isize, height as isize);
+    ((0..width).contains(&x) && (0..height).contains(&y)).then(|| (y * width + x) as usize)
+}
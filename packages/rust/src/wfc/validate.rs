@@ -0,0 +1,99 @@
+use super::{Adjacency, Edge, TileConfig, Tileset, WfcError, EDGES};
+use std::fmt;
+
+/// Something about a tileset that will make generation contradict, or that
+/// is most likely a mistake. See [`Tileset::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TilesetProblem {
+    /// There are no tiles, so no cell can ever be filled.
+    Empty,
+    /// Opposing edges disagree on how many socks a socket has.
+    Arity(String),
+    /// `tile` is drawn, turned, and connects exactly like the earlier tile
+    /// `of`, so one of them is redundant.
+    Duplicate { tile: usize, of: usize },
+    /// No tile, not even `tile` itself, may sit on `edge` of `tile`, so it
+    /// can only ever appear against that edge of the grid.
+    Unmatched { tile: usize, edge: Edge },
+    /// An adjacency rule names an image no tile has.
+    UnknownImage(String),
+    /// An exclusive set names a tag no tile carries.
+    UnknownTag(String),
+}
+
+impl fmt::Display for TilesetProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TilesetProblem::Empty => f.write_str("the tileset has no tiles"),
+            TilesetProblem::Arity(reason) => f.write_str(reason),
+            TilesetProblem::Duplicate { tile, of } => {
+                write!(f, "tile {} is a duplicate of tile {}", tile, of)
+            }
+            TilesetProblem::Unmatched { tile, edge } => {
+                write!(f, "nothing can sit on the {:?} edge of tile {}", edge, tile)
+            }
+            TilesetProblem::UnknownImage(image) => {
+                write!(f, "an adjacency rule names `{}`, but no tile has it", image)
+            }
+            TilesetProblem::UnknownTag(tag) => {
+                write!(f, "an exclusive set names `{}`, but no tile has it", tag)
+            }
+        }
+    }
+}
+
+impl<T> Tileset<T> {
+    /// Every problem found with the tileset, or none if it looks sound. A
+    /// sound tileset can still contradict, but one with problems usually
+    /// will, or hides a mistake.
+    pub fn validate(&self) -> Vec<TilesetProblem> {
+        if self.tiles.is_empty() {
+            return vec![TilesetProblem::Empty];
+        }
+
+        let mut problems = Vec::new();
+        if let Err(WfcError::InvalidTileset(reason)) = self.check_arity() {
+            problems.push(TilesetProblem::Arity(reason));
+        }
+
+        for (tile, config) in self.tiles.iter().enumerate() {
+            let same = |other: &TileConfig<T>| {
+                other.image == config.image
+                    && other.sockets == config.sockets
+                    && other.faces == config.faces
+                    && (other.mirrored, other.rotation) == (config.mirrored, config.rotation)
+            };
+            if let Some(of) = self.tiles[..tile].iter().position(same) {
+                problems.push(TilesetProblem::Duplicate { tile, of });
+            }
+        }
+
+        if let Ok(adjacency) = Adjacency::new(self, &[]) {
+            for tile in 0..self.tiles.len() {
+                for edge in EDGES {
+                    if adjacency.compatible[tile][edge as usize].is_empty() {
+                        problems.push(TilesetProblem::Unmatched { tile, edge });
+                    }
+                }
+            }
+        }
+
+        for rule in &self.adjacency {
+            for image in [&rule.tile, &rule.other] {
+                let known = self.tiles.iter().any(|tile| &tile.image == image);
+                let problem = TilesetProblem::UnknownImage(image.clone());
+                if !known && !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+        }
+        for tag in self.exclusive.iter().flatten() {
+            let problem = TilesetProblem::UnknownTag(tag.clone());
+            if !self.tiles.iter().any(|tile| tile.has_tag(tag)) && !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+
+        problems
+    }
+}
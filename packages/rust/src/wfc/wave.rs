@@ -0,0 +1,131 @@
+use super::{Cell, MAX_RESTARTS};
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::VecDeque;
+
+/// A bare solver over any topology, for the models that don't fit the tiled
+/// [`Grid`](super::Grid): the cells and each cell's neighbors are given up
+/// front, every neighbor tagged with a relation, such as the direction it
+/// lies in, along with which options may face each other across each
+/// relation.
+#[derive(Debug)]
+pub(super) struct Wave {
+    cells: Vec<Cell>,
+    weights: Vec<f64>,
+    /// `compatible[option][relation]` lists every option that may sit in
+    /// that relation to it.
+    compatible: Vec<Vec<Vec<usize>>>,
+    /// `neighbors[cell]` lists every `(neighbor, relation)` of the cell.
+    neighbors: Vec<Vec<(usize, usize)>>,
+    rng: ChaCha8Rng,
+    restarts: usize,
+    finished: bool,
+}
+
+impl Wave {
+    pub(super) fn new(
+        weights: Vec<f64>,
+        compatible: Vec<Vec<Vec<usize>>>,
+        neighbors: Vec<Vec<(usize, usize)>>,
+        seed: Option<u64>,
+    ) -> Self {
+        let rng = match seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let mut wave = Wave {
+            cells: Vec::new(),
+            weights,
+            compatible,
+            neighbors,
+            rng,
+            restarts: 0,
+            finished: false,
+        };
+        wave.clear();
+        wave
+    }
+
+    pub(super) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Collapses a lowest-entropy cell, picked at random among ties, to a
+    /// weighted random option and propagates the result. A contradiction
+    /// starts over, up to a limit.
+    pub(super) fn step(&mut self) {
+        let mut tied = Vec::new();
+        let mut entropy = f64::INFINITY;
+        for (i, cell) in self.cells.iter().enumerate() {
+            if cell.is_collapsed() || cell.is_contradiction() {
+                continue;
+            }
+            let e = cell.shannon_entropy(&self.weights);
+            if e < entropy {
+                entropy = e;
+                tied.clear();
+            }
+            if e == entropy {
+                tied.push(i);
+            }
+        }
+        let Some(&i) = tied.choose(&mut self.rng) else {
+            self.finished = true;
+            return;
+        };
+
+        self.cells[i].collapse(&mut self.rng, &self.weights);
+        self.propagate(i);
+        if self.contradiction().is_some() && self.restarts < MAX_RESTARTS {
+            self.restarts += 1;
+            self.clear();
+        }
+    }
+
+    /// The first cell no option fits.
+    pub(super) fn contradiction(&self) -> Option<usize> {
+        self.cells.iter().position(Cell::is_contradiction)
+    }
+
+    /// The option cell `i` collapsed to, if it has.
+    pub(super) fn collapsed(&self, i: usize) -> Option<usize> {
+        let cell = self.cells.get(i)?;
+        cell.possibilities
+            .first()
+            .copied()
+            .filter(|_| cell.is_collapsed())
+    }
+
+    fn clear(&mut self) {
+        let cell = Cell {
+            possibilities: (0..self.weights.len()).collect(),
+        };
+        self.cells = vec![cell; self.neighbors.len()];
+        self.finished = false;
+    }
+
+    /// Narrows every cell reachable from `start` to the options its
+    /// neighbors still allow.
+    fn propagate(&mut self, start: usize) {
+        let mut queue = VecDeque::from([start]);
+        let mut allowed = vec![false; self.weights.len()];
+
+        while let Some(i) = queue.pop_front() {
+            for &(neighbor, relation) in &self.neighbors[i] {
+                allowed.fill(false);
+                for &option in &self.cells[i].possibilities {
+                    for &other in &self.compatible[option][relation] {
+                        allowed[other] = true;
+                    }
+                }
+
+                let cell = &mut self.cells[neighbor];
+                let before = cell.possibilities.len();
+                cell.possibilities.retain(|&other| allowed[other]);
+                if cell.possibilities.len() < before {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+}